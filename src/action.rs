@@ -3,22 +3,38 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
 use dump_syms::common;
+use dump_syms::input::{self, Filter};
+use dump_syms::lookup;
 use dump_syms::mac::print_macho_architectures;
+use dump_syms::report::Report;
+use dump_syms::reproducible;
 use dump_syms::utils;
+use dump_syms::verify;
 
-use dump_syms::dumper::{self, Config};
+use dump_syms::diff::Diff;
+use dump_syms::dumper::{self, Config, FileOutput, Format, Output};
 
 #[allow(clippy::large_enum_variant)]
 pub(crate) enum Action<'a> {
     Dump(Config<'a>),
+    /// Dump each module independently, the directories are walked and the
+    /// files in the manifest, if any, are added to the input files
+    DumpModules(Config<'a>, Filter, Option<&'a str>),
+    Diff(Config<'a>),
+    /// Dump the files twice and check that the outputs are the same
+    CheckReproducible(Config<'a>),
+    Lookup(Config<'a>, Vec<&'a str>),
     ListArch,
+    Verify,
 }
 
 impl Action<'_> {
-    pub(super) fn action(&self, filenames: &[&str]) -> common::Result<()> {
+    /// Run the action and get the report of the dump, if it's one.
+    pub(super) fn action(&self, filenames: &[&str]) -> common::Result<Option<Report>> {
         if filenames.len() == 1 {
             // no need to spawn a thread for one file
             self.single_file(filenames[0])
@@ -27,44 +43,170 @@ impl Action<'_> {
         }
     }
 
-    fn single_file(&self, filename: &str) -> common::Result<()> {
+    fn single_file(&self, filename: &str) -> common::Result<Option<Report>> {
         match self {
-            Self::Dump(config) => dumper::single_file(config, filename),
+            Self::Dump(config) => return dumper::single_file(config, filename).map(Some),
+            Self::DumpModules(config, filter, manifest) => {
+                return dump_modules(config, filter, *manifest, &[filename]).map(Some)
+            }
             Self::ListArch => {
                 let path = PathBuf::from(filename);
                 let filename = utils::get_filename(&path);
 
-                let buf = utils::read_file(&path);
-                print_macho_architectures(&buf, filename)
+                let buf = utils::read_file(&path)?;
+                print_macho_architectures(&buf, filename)?;
             }
+            Self::Diff(_) => anyhow::bail!("Two files are required to compute a diff"),
+            Self::CheckReproducible(config) => reproducible::check(config, &[filename])?,
+            Self::Lookup(config, addresses) => lookup(config, filename, addresses)?,
+            Self::Verify => verify_file(filename)?,
         }
+        Ok(None)
     }
 
-    fn several_files(&self, filenames: &[&str]) -> common::Result<()> {
+    fn several_files(&self, filenames: &[&str]) -> common::Result<Option<Report>> {
         match self {
-            Self::Dump(config) => dumper::several_files(config, filenames),
+            Self::Dump(config) => return dumper::several_files(config, filenames).map(Some),
+            Self::DumpModules(config, filter, manifest) => {
+                return dump_modules(config, filter, *manifest, filenames).map(Some)
+            }
             Self::ListArch => {
                 for f in filenames {
                     let path = PathBuf::from(f);
                     let filename = utils::get_filename(&path);
 
-                    let buf = utils::read_file(&path);
+                    let buf = utils::read_file(&path)?;
                     print_macho_architectures(&buf, filename)?;
                 }
-                Ok(())
+            }
+            Self::Diff(config) => diff_files(config, filenames)?,
+            Self::CheckReproducible(config) => reproducible::check(config, filenames)?,
+            Self::Lookup(..) => anyhow::bail!("Addresses can only be looked up in one file"),
+            Self::Verify => {
+                let failures = filenames.iter().filter(|f| verify_file(f).is_err()).count();
+                anyhow::ensure!(
+                    failures == 0,
+                    "{} invalid file{}",
+                    failures,
+                    if failures == 1 { "" } else { "s" }
+                );
             }
         }
+        Ok(None)
+    }
+}
+
+fn dump_modules(
+    config: &Config,
+    filter: &Filter,
+    manifest: Option<&str>,
+    filenames: &[&str],
+) -> common::Result<Report> {
+    let mut inputs: Vec<PathBuf> = filenames.iter().map(PathBuf::from).collect();
+    if let Some(manifest) = manifest {
+        inputs.extend(input::read_manifest(manifest)?);
     }
+    let files = input::collect_files(&inputs, filter)?;
+    dumper::dump_modules(config, &files)
+}
+
+fn diff_files(config: &Config, filenames: &[&str]) -> common::Result<()> {
+    anyhow::ensure!(
+        filenames.len() == 2,
+        "Two files are required to compute a diff"
+    );
+
+    let old = dumper::object_info_from_file(config, filenames[0])?;
+    let new = dumper::object_info_from_file(config, filenames[1])?;
+    let diff = Diff::new(&old, &new);
+
+    let mut writer = get_output_writer(config)?;
+    match config.format {
+        Format::Breakpad => write!(writer, "{diff}")?,
+        Format::Json => serde_json::to_writer(&mut writer, &diff)?,
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn get_output_writer(
+    config: &Config,
+) -> common::Result<std::io::BufWriter<Box<dyn std::io::Write>>> {
+    let output = match &config.output {
+        Output::File(fo) => fo.clone(),
+        _ => FileOutput::Stdout,
+    };
+    dumper::get_writer_for_sym(&output)
+}
+
+fn lookup(config: &Config, filename: &str, addresses: &[&str]) -> common::Result<()> {
+    // A "-" means that the addresses are read from stdin.
+    let mut stdin = String::new();
+    if addresses.contains(&"-") {
+        std::io::stdin().read_to_string(&mut stdin)?;
+    }
+    let addresses = addresses
+        .iter()
+        .flat_map(|a| {
+            if *a == "-" {
+                stdin.split_whitespace().collect()
+            } else {
+                vec![*a]
+            }
+        })
+        .map(lookup::parse_address)
+        .collect::<common::Result<Vec<_>>>()?;
+
+    let info = dumper::object_info_from_file(config, filename)?;
+    let locations: Vec<_> = addresses
+        .iter()
+        .map(|address| info.lookup(*address))
+        .collect();
+
+    let mut writer = get_output_writer(config)?;
+    match config.format {
+        Format::Breakpad => {
+            for (address, location) in addresses.iter().zip(locations.iter()) {
+                if let Some(location) = location {
+                    write!(writer, "{location}")?;
+                } else {
+                    writeln!(writer, "{address:x} ??")?;
+                }
+            }
+        }
+        Format::Json => serde_json::to_writer(&mut writer, &locations)?,
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+fn verify_file(filename: &str) -> common::Result<()> {
+    let buf = utils::read_file(filename)?;
+    let problems = verify::verify(&buf);
+    for problem in problems.iter() {
+        println!("{filename}:{problem}");
+    }
+    anyhow::ensure!(
+        problems.is_empty(),
+        "{}: {} problem{} found",
+        filename,
+        problems.len(),
+        if problems.len() == 1 { "" } else { "s" }
+    );
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
 
     use regex::Regex;
-    use std::fs::{copy, read};
+    use std::fs::{self, copy, read};
     use tempfile::Builder;
 
     use super::*;
+    use dump_syms::dumper::Incremental;
 
     // Read and process the input so it can be compared with the output
     fn read_input(input_path: &str) -> Vec<String> {
@@ -91,17 +233,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: false,
+            prune_unreferenced: false,
         });
 
         action.action(&[tmp_file.to_str().unwrap()]).unwrap();
@@ -124,17 +275,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: true,
+            cfi_threshold: None,
             emit_inlines: false,
+            prune_unreferenced: false,
         });
 
         let res = action.action(&[tmp_file.to_str().unwrap()]);
@@ -155,17 +315,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: false,
+            prune_unreferenced: false,
         });
 
         action.action(&[tmp_pdb.to_str().unwrap()]).unwrap();
@@ -185,17 +354,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: false,
+            prune_unreferenced: false,
         });
 
         action.action(&[full.to_str().unwrap()]).unwrap();
@@ -214,17 +392,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: true,
+            prune_unreferenced: false,
         });
 
         action.action(&[full.to_str().unwrap()]).unwrap();
@@ -235,6 +422,65 @@ mod tests {
         assert_eq!(basic, new);
     }
 
+    #[test]
+    fn test_elf_full_json() {
+        let tmp_dir = Builder::new().prefix("full_json").tempdir().unwrap();
+        let full = PathBuf::from("./test_data/linux/basic.full");
+        let tmp_out = tmp_dir.path().join("output.json");
+
+        let action = Action::Dump(Config {
+            output: tmp_out.clone().into(),
+            format: Format::Json,
+            incremental: Incremental::Off,
+            symbol_server: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            all_archs: false,
+            num_jobs: 1,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
+            check_cfi: false,
+            cfi_threshold: None,
+            emit_inlines: true,
+            prune_unreferenced: false,
+        });
+
+        action.action(&[full.to_str().unwrap()]).unwrap();
+
+        let data = read(tmp_out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&data).unwrap();
+
+        assert_eq!(json["module"]["os"], "Linux");
+        assert_eq!(
+            json["module"]["debug_id"],
+            "20AD60B0B4C68177552708AA192E77390"
+        );
+        assert_eq!(json["module"]["name"], "basic.full");
+        assert_eq!(json["inline_origins"][0], "inline_4(int)");
+
+        // Check that we've the same content as in the Breakpad output.
+        let basic = read_input("./test_data/linux/basic.full.inlines.sym");
+        let count = |prefix: &str| basic.iter().filter(|l| l.starts_with(prefix)).count();
+        let len = |key: &str| json[key].as_array().unwrap().len();
+        assert_eq!(len("files"), count("FILE "));
+        assert_eq!(len("functions"), count("FUNC "));
+        assert_eq!(len("publics"), count("PUBLIC "));
+        assert_eq!(len("cfi"), count("STACK CFI INIT "));
+
+        let func = &json["functions"][0];
+        assert_eq!(func["address"], 0x1125);
+        assert_eq!(func["size"], 0x26);
+        assert_eq!(func["name"], "inline_1(int)");
+        assert_eq!(func["lines"][0]["line"], 2);
+    }
+
     #[test]
     fn test_elf_stripped_dbg() {
         let tmp_dir = Builder::new().prefix("stripped_dbg").tempdir().unwrap();
@@ -244,17 +490,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 2,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: true,
+            prune_unreferenced: false,
         });
 
         action
@@ -276,6 +531,47 @@ mod tests {
         assert_eq!(basic, new);
     }
 
+    #[test]
+    fn test_check_reproducible() {
+        let stripped = PathBuf::from("./test_data/linux/basic.stripped");
+        let dbg = PathBuf::from("./test_data/linux/basic.dbg");
+        let full = PathBuf::from("./test_data/linux/basic.full");
+
+        let action = Action::CheckReproducible(Config {
+            output: Output::File(FileOutput::Stdout),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
+            symbol_server: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            all_archs: false,
+            num_jobs: 3,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
+            check_cfi: false,
+            cfi_threshold: None,
+            emit_inlines: true,
+            prune_unreferenced: false,
+        });
+
+        // The three files have the same debug id so they're merged.
+        action
+            .action(&[
+                stripped.to_str().unwrap(),
+                dbg.to_str().unwrap(),
+                full.to_str().unwrap(),
+            ])
+            .unwrap();
+        action.action(&[full.to_str().unwrap()]).unwrap();
+    }
+
     #[test]
     fn test_elf_dbg_stripped() {
         let tmp_dir = Builder::new().prefix("stripped_dbg").tempdir().unwrap();
@@ -285,17 +581,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 2,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: false,
+            prune_unreferenced: false,
         });
 
         action
@@ -308,6 +613,278 @@ mod tests {
         assert_eq!(basic, new);
     }
 
+    #[test]
+    fn test_elf_debuglink() {
+        let tmp_dir = Builder::new().prefix("debuglink").tempdir().unwrap();
+        let debuglink = PathBuf::from("./test_data/linux/basic.debuglink");
+        let tmp_out = tmp_dir.path().join("output.sym");
+
+        let action = Action::Dump(Config {
+            output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
+            symbol_server: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            all_archs: false,
+            num_jobs: 1,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: Some(vec![]),
+            report: None,
+            check_cfi: false,
+            cfi_threshold: None,
+            emit_inlines: false,
+            prune_unreferenced: false,
+        });
+
+        action.action(&[debuglink.to_str().unwrap()]).unwrap();
+
+        let new = read_output(&tmp_out);
+        let basic = read_input("./test_data/linux/basic.dbg.sym");
+
+        assert_eq!(basic, new);
+    }
+
+    #[test]
+    fn test_mac_all_archs() {
+        let tmp_dir = Builder::new().prefix("all_archs").tempdir().unwrap();
+        let fat = PathBuf::from("./test_data/macos/basic.fat");
+
+        let action = Action::Dump(Config {
+            output: Output::Store(tmp_dir.path().to_path_buf()),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
+            symbol_server: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            all_archs: true,
+            num_jobs: 1,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
+            check_cfi: false,
+            cfi_threshold: None,
+            emit_inlines: false,
+            prune_unreferenced: false,
+        });
+
+        action.action(&[fat.to_str().unwrap()]).unwrap();
+
+        for (id, arch) in [
+            ("0123456789ABCDEF0123456789ABCDEF0", "x86_64"),
+            ("FEDCBA9876543210FEDCBA98765432100", "arm64"),
+        ] {
+            let path = tmp_dir
+                .path()
+                .join("basic.fat")
+                .join(id)
+                .join("basic.fat.sym");
+            let data = String::from_utf8(read(path).unwrap()).unwrap();
+            assert_eq!(
+                data.lines().next().unwrap(),
+                format!("MODULE Mac {arch} {id} basic.fat")
+            );
+        }
+
+        // Several objects can't be written in the same file.
+        let tmp_out = tmp_dir.path().join("output.sym");
+        let action = Action::Dump(Config {
+            all_archs: true,
+            check_cfi: false,
+            cfi_threshold: None,
+            ..Config::with_output(tmp_out.into())
+        });
+        assert!(action.action(&[fat.to_str().unwrap()]).is_err());
+    }
+
+    #[test]
+    fn test_incremental_store() {
+        let tmp_dir = Builder::new().prefix("incremental").tempdir().unwrap();
+        let store = tmp_dir.path();
+        let files = [
+            "./test_data/linux/basic.full",
+            "./test_data/windows/basic32-min.pdb",
+        ];
+        let sym_paths = [
+            store
+                .join("basic.full")
+                .join("20AD60B0B4C68177552708AA192E77390")
+                .join("basic.full.sym"),
+            store
+                .join("basic32-min.pdb")
+                .join("878E82E5E282481B88FCBBA2CFF8C7F12")
+                .join("basic32-min.sym"),
+        ];
+        let dump = |files: &[&str], incremental| {
+            let action = Action::Dump(Config {
+                incremental,
+                check_cfi: false,
+                cfi_threshold: None,
+                ..Config::with_output(Output::Store(store.to_path_buf()))
+            });
+            let report = action.action(files).unwrap().unwrap();
+            // The outputs are the files written by this dump (e.g. to upload them).
+            report
+                .outputs()
+                .iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        };
+        let fake = |generator: &str| {
+            for path in &sym_paths {
+                fs::write(path, format!("MODULE fake\nINFO GENERATOR {generator}\n")).unwrap();
+            }
+        };
+        let is_fake = |path: &PathBuf| read(path).unwrap().starts_with(b"MODULE fake");
+
+        assert_eq!(dump(&files, Incremental::Skip), sym_paths);
+        assert!(sym_paths.iter().all(|p| p.is_file() && !is_fake(p)));
+
+        // The existing files aren't dumped again, whatever their generator.
+        fake("mozilla/dump_syms 0.0.1");
+        assert!(dump(&files, Incremental::Skip).is_empty());
+        assert!(dump(&files[..1], Incremental::Skip).is_empty());
+        assert!(sym_paths.iter().all(is_fake));
+
+        // Except if they've been generated by another version.
+        fake(common::GENERATOR);
+        dump(&files, Incremental::SkipSameGenerator);
+        assert!(sym_paths.iter().all(is_fake));
+        fake("mozilla/dump_syms 0.0.1");
+        assert_eq!(
+            dump(&files[..1], Incremental::SkipSameGenerator),
+            sym_paths[..1]
+        );
+        assert!(!is_fake(&sym_paths[0]) && is_fake(&sym_paths[1]));
+        dump(&files, Incremental::SkipSameGenerator);
+        assert!(!sym_paths.iter().any(is_fake));
+
+        dump(&files, Incremental::Off);
+        assert!(!sym_paths.iter().any(is_fake));
+    }
+
+    #[test]
+    fn test_dump_modules() {
+        let tmp_dir = Builder::new().prefix("modules").tempdir().unwrap();
+        let tree = tmp_dir.path().join("tree");
+        let store = tmp_dir.path().join("store");
+        for (src, dest) in [
+            ("linux/basic.stripped", "bin/basic.stripped"),
+            ("linux/basic.dbg", "debug/basic.dbg"),
+            ("linux/basic.split", "bin/basic.split"),
+            ("linux/basic.split-basic.dwo", "bin/basic.split-basic.dwo"),
+            ("linux/basic.cpp", "src/basic.cpp"),
+            ("windows/basic32-min.dll", "win/basic32-min.dll"),
+            ("windows/basic32-min.pdb", "pdb/basic32-min.pdb"),
+        ] {
+            let dest = tree.join(dest);
+            fs::create_dir_all(dest.parent().unwrap()).unwrap();
+            copy(PathBuf::from("./test_data").join(src), dest).unwrap();
+        }
+        let manifest = tmp_dir.path().join("manifest");
+        fs::write(&manifest, "tree/pdb\ntree/win/basic32-min.dll\n").unwrap();
+
+        let action = Action::DumpModules(
+            Config {
+                check_cfi: false,
+                cfi_threshold: None,
+                emit_inlines: false,
+                prune_unreferenced: false,
+                ..Config::with_output(Output::Store(store.clone()))
+            },
+            Filter::new(&[], &["win", "pdb"]).unwrap(),
+            Some(manifest.to_str().unwrap()),
+        );
+        action.action(&[tree.to_str().unwrap()]).unwrap();
+
+        let mut modules: Vec<_> = fs::read_dir(&store)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect();
+        modules.sort();
+        assert_eq!(
+            modules,
+            ["basic.split", "basic.stripped", "basic32-min.pdb"]
+        );
+
+        // The stripped file is dumped with its debug file.
+        let stripped = read_output(
+            &store
+                .join("basic.stripped")
+                .join("20AD60B0B4C68177552708AA192E77390")
+                .join("basic.stripped.sym"),
+        );
+        assert_eq!(stripped, read_input("./test_data/linux/basic.dbg.sym"));
+
+        // The split DWARF is read from the .dwo file.
+        let split = read_output(
+            &store
+                .join("basic.split")
+                .join("AC6EF2572F418ED96B150804A4C350520")
+                .join("basic.split.sym"),
+        );
+        assert!(split.iter().any(|line| line.ends_with(" foo(int)")));
+
+        // The PDB is dumped with its PE file.
+        let pdb = read_output(
+            &store
+                .join("basic32-min.pdb")
+                .join("878E82E5E282481B88FCBBA2CFF8C7F12")
+                .join("basic32-min.sym"),
+        );
+        assert_eq!(pdb[0], "INFO CODE_ID 5DDC1E9B6C000 basic32-min.dll");
+    }
+
+    #[test]
+    fn test_elf_split_dwarf_with_inlines() {
+        let tmp_dir = Builder::new().prefix("split_dwarf").tempdir().unwrap();
+        let split = PathBuf::from("./test_data/linux/basic.split");
+        let tmp_out = tmp_dir.path().join("output.sym");
+
+        let action = Action::Dump(Config {
+            output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
+            symbol_server: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            all_archs: false,
+            num_jobs: 1,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: Some(vec![]),
+            report: None,
+            check_cfi: false,
+            cfi_threshold: None,
+            emit_inlines: true,
+            prune_unreferenced: false,
+        });
+
+        action.action(&[split.to_str().unwrap()]).unwrap();
+
+        let new = read_output(&tmp_out);
+        let basic = read_input("./test_data/linux/basic.split.inlines.sym");
+
+        assert_eq!(basic, new);
+    }
+
     #[test]
     fn test_elf_dwz_with_inlines() {
         let tmp_dir = Builder::new().prefix("dwz").tempdir().unwrap();
@@ -316,17 +893,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: true,
+            prune_unreferenced: false,
         });
 
         action.action(&[full.to_str().unwrap()]).unwrap();
@@ -345,17 +931,26 @@ mod tests {
 
         let action = Action::Dump(Config {
             output: tmp_out.clone().into(),
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
             check_cfi: false,
+            cfi_threshold: None,
             emit_inlines: true,
+            prune_unreferenced: false,
         });
 
         action.action(&[minidebuginfo.to_str().unwrap()]).unwrap();
@@ -365,4 +960,204 @@ mod tests {
 
         assert_eq!(basic, new);
     }
+
+    #[test]
+    fn test_report() {
+        let tmp_dir = Builder::new().prefix("report").tempdir().unwrap();
+        let store = tmp_dir.path().join("store");
+        let report = tmp_dir.path().join("report.json");
+        let missing = tmp_dir.path().join("missing.so");
+
+        let mut config = Config::with_output(Output::Store(store.clone()));
+        config.num_jobs = 2;
+        config.debug_dirs = Some(vec![]);
+        config.report = report.to_str();
+        let res = Action::Dump(config).action(&[
+            "./test_data/linux/basic.stripped",
+            "./test_data/linux/basic.dbg",
+            missing.to_str().unwrap(),
+        ]);
+        assert!(res.is_err());
+
+        let data = read(&report).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(json["failures"], 1);
+
+        let inputs = json["inputs"].as_array().unwrap();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[0]["input"], "./test_data/linux/basic.dbg");
+        assert_eq!(inputs[1]["input"], "./test_data/linux/basic.stripped");
+        assert_eq!(inputs[0]["modules"], inputs[1]["modules"]);
+        assert!(inputs[2]["error"].as_str().unwrap().contains("missing.so"));
+
+        let module = &inputs[0]["modules"][0];
+        assert_eq!(module["name"], "basic.stripped");
+        assert_eq!(module["debug_id"], "20AD60B0B4C68177552708AA192E77390");
+        assert_eq!(module["functions"], 6);
+        assert_eq!(module["inlines"], 13);
+        assert_eq!(module["cfi"], true);
+        assert_eq!(module["cfi_coverage"], 100.0);
+        assert!(module["error"].is_null());
+        assert_eq!(
+            module["outputs"][0],
+            store
+                .join("basic.stripped/20AD60B0B4C68177552708AA192E77390/basic.stripped.sym")
+                .to_str()
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dump_modules_unreadable_file() {
+        let tmp_dir = Builder::new().prefix("unreadable").tempdir().unwrap();
+        let store = tmp_dir.path().join("store");
+        let report = tmp_dir.path().join("report.json");
+        let missing = tmp_dir.path().join("missing.so");
+
+        let mut config = Config::with_output(Output::Store(store.clone()));
+        config.debug_dirs = Some(vec![]);
+        config.report = report.to_str();
+        let res = dumper::dump_modules(
+            &config,
+            &[
+                PathBuf::from("./test_data/linux/basic.stripped"),
+                PathBuf::from("./test_data/linux/basic.dbg"),
+                missing.clone(),
+            ],
+        );
+        assert!(res.is_err());
+
+        // The other modules are dumped.
+        assert!(store
+            .join("basic.stripped/20AD60B0B4C68177552708AA192E77390/basic.stripped.sym")
+            .is_file());
+
+        let data = read(&report).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(json["failures"], 1);
+
+        let inputs = json["inputs"].as_array().unwrap();
+        assert_eq!(inputs.len(), 3);
+        assert_eq!(inputs[0]["input"], "./test_data/linux/basic.dbg");
+        assert_eq!(inputs[1]["input"], "./test_data/linux/basic.stripped");
+        assert_eq!(inputs[2]["input"], missing.to_str().unwrap());
+        assert!(inputs[2]["error"].as_str().unwrap().contains("missing.so"));
+    }
+
+    #[test]
+    fn test_verify() {
+        let tmp_dir = Builder::new().prefix("verify").tempdir().unwrap();
+        let sym = "./test_data/linux/basic.full.inlines.sym";
+        let truncated = tmp_dir.path().join("truncated.sym");
+
+        let data = read(sym).unwrap();
+        std::fs::write(&truncated, &data[..data.len() / 2]).unwrap();
+
+        assert!(Action::Verify.action(&[sym]).is_ok());
+        assert!(Action::Verify
+            .action(&[truncated.to_str().unwrap()])
+            .is_err());
+        assert!(Action::Verify
+            .action(&[sym, truncated.to_str().unwrap()])
+            .is_err());
+    }
+
+    #[test]
+    fn test_diff() {
+        let tmp_dir = Builder::new().prefix("diff").tempdir().unwrap();
+        let full = PathBuf::from("./test_data/linux/basic.full");
+        let sym = PathBuf::from("./test_data/linux/basic.full.sym");
+        let tmp_out = tmp_dir.path().join("output.json");
+
+        let action = Action::Diff(Config {
+            output: tmp_out.clone().into(),
+            format: Format::Json,
+            incremental: Incremental::Off,
+            symbol_server: None,
+            debug_id: None,
+            code_id: None,
+            arch: common::get_compile_time_arch(),
+            all_archs: false,
+            num_jobs: 1,
+            mapping_var: None,
+            mapping_src: None,
+            mapping_dest: None,
+            mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
+            check_cfi: false,
+            cfi_threshold: None,
+            emit_inlines: true,
+            prune_unreferenced: false,
+        });
+
+        action
+            .action(&[sym.to_str().unwrap(), full.to_str().unwrap()])
+            .unwrap();
+
+        let data = read(tmp_out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&data).unwrap();
+
+        assert_eq!(json["old"]["functions"], 6);
+        assert_eq!(json["new"]["functions"], 6);
+        assert_eq!(json["old"]["inlines"], 0);
+        assert_eq!(json["new"]["inlines"], 13);
+        assert_eq!(json["added"].as_array().unwrap().len(), 0);
+        assert_eq!(json["removed"].as_array().unwrap().len(), 0);
+        assert_eq!(json["renamed"].as_array().unwrap().len(), 0);
+
+        assert!(
+            Action::Diff(Config::with_output(tmp_dir.path().join("x").into()))
+                .action(&[sym.to_str().unwrap()])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_lookup() {
+        let tmp_dir = Builder::new().prefix("lookup").tempdir().unwrap();
+        let full = PathBuf::from("./test_data/linux/basic.full");
+        let tmp_out = tmp_dir.path().join("output.json");
+
+        let action = Action::Lookup(
+            Config {
+                output: tmp_out.clone().into(),
+                format: Format::Json,
+                incremental: Incremental::Off,
+                symbol_server: None,
+                debug_id: None,
+                code_id: None,
+                arch: common::get_compile_time_arch(),
+                all_archs: false,
+                num_jobs: 1,
+                mapping_var: None,
+                mapping_src: None,
+                mapping_dest: None,
+                mapping_file: None,
+                mapping_source_link: None,
+                mapping_git: None,
+                debug_dirs: None,
+                report: None,
+                check_cfi: false,
+                cfi_threshold: None,
+                emit_inlines: true,
+                prune_unreferenced: false,
+            },
+            vec!["0x11c3", "basic.full+0x10"],
+        );
+
+        action.action(&[full.to_str().unwrap()]).unwrap();
+
+        let data = read(tmp_out).unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&data).unwrap();
+
+        assert_eq!(json[0]["symbol"], "inline_4(int)");
+        assert_eq!(json[0]["frames"].as_array().unwrap().len(), 4);
+        assert_eq!(json[0]["frames"][0]["function"], "inline_1(int)");
+        assert_eq!(json[0]["frames"][3]["function"], "inline_4(int)");
+        assert_eq!(json[0]["frames"][3]["line"], 20);
+        assert!(json[1].is_null());
+    }
 }
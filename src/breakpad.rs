@@ -0,0 +1,270 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashSet;
+use std::str::FromStr;
+use symbolic::common::Arch;
+use symbolic::debuginfo::breakpad::{
+    BreakpadFileRecord, BreakpadFuncRecord, BreakpadInfoRecord, BreakpadInlineOriginRecord,
+    BreakpadInlineRecord, BreakpadLineRecord, BreakpadModuleRecord, BreakpadPublicRecord, Lines,
+};
+
+use crate::common;
+use crate::line::{InlineAddressRange, InlineSite, Line};
+use crate::object_info::{ObjectInfo, Type};
+use crate::platform::Platform;
+use crate::source::SourceMap;
+use crate::symbol::{Symbol, Symbols};
+
+/// The largest gap allowed between the ids of the FILE or INLINE_ORIGIN records
+/// and the number of records read so far.
+const MAX_ID_GAP: u64 = 1 << 16;
+
+/// Put `value` at index `id` in `list`, growing the list with empty strings if needed.
+///
+/// The ids come from the file so the ones too far away from the current length
+/// are rejected rather than allocating a huge list.
+fn set_at(list: &mut Vec<String>, id: u64, value: &str) -> common::Result<()> {
+    if id >= list.len() as u64 + MAX_ID_GAP {
+        anyhow::bail!("id {} is too large", id);
+    }
+    let id = id as usize;
+    if list.len() <= id {
+        list.resize(id + 1, String::new());
+    }
+    list[id] = value.to_string();
+    Ok(())
+}
+
+impl ObjectInfo {
+    /// Read back the content of a Breakpad symbol file.
+    ///
+    /// The INFO records, except the CODE_ID one, are dropped.
+    pub fn from_breakpad(buf: &[u8]) -> common::Result<Self> {
+        let mut lines = Lines::new(buf).enumerate();
+
+        let (n, module) = lines
+            .next()
+            .ok_or_else(|| anyhow::anyhow!("Empty Breakpad file"))?;
+        let module = BreakpadModuleRecord::parse(module)
+            .map_err(|e| anyhow::anyhow!("line {}: {}", n + 1, e))?;
+
+        let mut files = Vec::new();
+        let mut inline_origins = Vec::new();
+        let mut symbols = Symbols::new();
+        let mut stack = String::new();
+        let mut code_id = None;
+        let mut pe_name = None;
+        // The address of the FUNC the line and INLINE records belong to.
+        let mut current = None;
+        let mut file_ids = HashSet::new();
+        let mut origin_ids = HashSet::new();
+        // The FILE and INLINE_ORIGIN references to check once all the ids are known.
+        let mut file_refs = Vec::new();
+        let mut origin_refs = Vec::new();
+
+        for (n, line) in lines {
+            if line.is_empty() {
+                continue;
+            }
+            let err = |e: &dyn std::fmt::Display| anyhow::anyhow!("line {}: {}", n + 1, e);
+
+            if line.starts_with(b"INFO ") {
+                if let BreakpadInfoRecord::CodeId {
+                    code_id: id,
+                    code_file,
+                } = BreakpadInfoRecord::parse(line).map_err(|e| err(&e))?
+                {
+                    code_id = Some(id.to_string());
+                    pe_name = Some(code_file.to_string()).filter(|s| !s.is_empty());
+                }
+            } else if line.starts_with(b"FILE ") {
+                let file = BreakpadFileRecord::parse(line).map_err(|e| err(&e))?;
+                set_at(&mut files, file.id, file.name).map_err(|e| err(&e))?;
+                file_ids.insert(file.id);
+            } else if line.starts_with(b"INLINE_ORIGIN ") {
+                let origin = BreakpadInlineOriginRecord::parse(line).map_err(|e| err(&e))?;
+                set_at(&mut inline_origins, origin.id, origin.name).map_err(|e| err(&e))?;
+                origin_ids.insert(origin.id);
+            } else if line.starts_with(b"FUNC ") {
+                let func = BreakpadFuncRecord::parse(line, Lines::new(&[])).map_err(|e| err(&e))?;
+                let rva = func.address as u32;
+                symbols.insert(
+                    rva,
+                    Symbol {
+                        name: func.name.to_string(),
+                        is_public: false,
+                        is_multiple: func.multiple,
+                        is_synthetic: false,
+                        rva,
+                        len: func.size as u32,
+                        parameter_size: func.parameter_size as u32,
+                        source: crate::line::Lines::new(),
+                    },
+                );
+                current = Some(rva);
+            } else if line.starts_with(b"PUBLIC ") {
+                let public = BreakpadPublicRecord::parse(line).map_err(|e| err(&e))?;
+                let rva = public.address as u32;
+                symbols.entry(rva).or_insert(Symbol {
+                    name: public.name.to_string(),
+                    is_public: true,
+                    is_multiple: public.multiple,
+                    is_synthetic: false,
+                    rva,
+                    len: 0,
+                    parameter_size: public.parameter_size as u32,
+                    source: crate::line::Lines::new(),
+                });
+                current = None;
+            } else if line.starts_with(b"STACK ") {
+                stack.push_str(&String::from_utf8_lossy(line));
+                stack.push('\n');
+                current = None;
+            } else if line.starts_with(b"INLINE ") {
+                let inline = BreakpadInlineRecord::parse(line).map_err(|e| err(&e))?;
+                let sym = current
+                    .and_then(|rva| symbols.get_mut(&rva))
+                    .ok_or_else(|| err(&"INLINE record outside of a FUNC"))?;
+                file_refs.push((n, inline.call_site_file_id));
+                origin_refs.push((n, inline.origin_id));
+                let site = InlineSite {
+                    inline_origin_id: inline.origin_id as u32,
+                    call_depth: inline.inline_depth as u32,
+                    call_line_number: inline.call_site_line as u32,
+                    call_file_id: inline.call_site_file_id as u32,
+                };
+                for range in inline.address_ranges {
+                    sym.source.add_inline(
+                        site.clone(),
+                        InlineAddressRange {
+                            rva: range.address as u32,
+                            len: range.size as u32,
+                        },
+                    );
+                }
+            } else {
+                let record = BreakpadLineRecord::parse(line).map_err(|e| err(&e))?;
+                let sym = current
+                    .and_then(|rva| symbols.get_mut(&rva))
+                    .ok_or_else(|| err(&"Line record outside of a FUNC"))?;
+                file_refs.push((n, record.file_id));
+                sym.source.lines.push(Line {
+                    rva: record.address as u32,
+                    len: record.size as u32,
+                    num: record.line as u32,
+                    file_id: record.file_id as u32,
+                });
+            }
+        }
+
+        if let Some((n, id)) = file_refs.iter().find(|(_, id)| !file_ids.contains(id)) {
+            anyhow::bail!("line {}: Unknown FILE id {}", n + 1, id);
+        }
+        if let Some((n, id)) = origin_refs.iter().find(|(_, id)| !origin_ids.contains(id)) {
+            anyhow::bail!("line {}: Unknown INLINE_ORIGIN id {}", n + 1, id);
+        }
+
+        let bin_type = if symbols.values().any(|sym| !sym.is_public) {
+            Type::DebugInfo
+        } else {
+            Type::Stripped
+        };
+
+        Ok(Self {
+            symbols,
+            files: SourceMap::from_paths(files),
+            inline_origins,
+            file_name: module.name.to_string(),
+            cpu: Arch::from_str(module.arch).unwrap_or_default().name(),
+            debug_id: module.id.to_string(),
+            code_id,
+            pe_name,
+            stack: stack.into(),
+            bin_type,
+            platform: Platform::from_str(module.os)?,
+            extra_info: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read;
+
+    #[test]
+    fn test_round_trip() {
+        for file in [
+            "./test_data/linux/basic.full.inlines.sym",
+            "./test_data/windows/basic32.sym",
+        ] {
+            let data = read(file).unwrap();
+            let info = ObjectInfo::from_breakpad(&data).unwrap();
+
+            let mut output = Vec::new();
+            info.dump(&mut output).unwrap();
+
+            // Skip the INFO records which aren't all kept.
+            let data = String::from_utf8(data).unwrap();
+            let output = String::from_utf8(output).unwrap();
+            let expected: Vec<_> = data.lines().filter(|l| !l.starts_with("INFO")).collect();
+            let output: Vec<_> = output.lines().filter(|l| !l.starts_with("INFO")).collect();
+
+            assert_eq!(expected, output, "Bad round trip for {file}");
+        }
+    }
+
+    #[test]
+    fn test_huge_ids() {
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+FILE 0 a.cpp
+FILE 4294967295 b.cpp
+";
+        let err = ObjectInfo::from_breakpad(data).unwrap_err();
+        assert_eq!(err.to_string(), "line 3: id 4294967295 is too large");
+
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+INLINE_ORIGIN 18446744073709551615 foo
+";
+        assert!(ObjectInfo::from_breakpad(data).is_err());
+
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+FILE 2 a.cpp
+";
+        let info = ObjectInfo::from_breakpad(data).unwrap();
+        assert_eq!(info.files.get_mapping(), ["", "", "a.cpp"]);
+    }
+
+    #[test]
+    fn test_unknown_ids() {
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+FILE 0 a.cpp
+FUNC 1000 10 0 foo
+1000 10 3 7
+";
+        let err = ObjectInfo::from_breakpad(data).unwrap_err();
+        assert_eq!(err.to_string(), "line 4: Unknown FILE id 7");
+
+        // The gaps in the ids aren't declared files.
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+FILE 2 a.cpp
+FUNC 1000 10 0 foo
+1000 10 3 1
+";
+        let err = ObjectInfo::from_breakpad(data).unwrap_err();
+        assert_eq!(err.to_string(), "line 4: Unknown FILE id 1");
+
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+FILE 0 a.cpp
+INLINE_ORIGIN 0 bar
+FUNC 1000 10 0 foo
+INLINE 0 12 0 1 1000 4
+1000 10 3 0
+";
+        let err = ObjectInfo::from_breakpad(data).unwrap_err();
+        assert_eq!(err.to_string(), "line 5: Unknown INLINE_ORIGIN id 1");
+    }
+}
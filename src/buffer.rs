@@ -0,0 +1,393 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Get the symbols of files held in memory.
+//!
+//! Contrary to [`crate::dumper`], nothing is read from the disk, fetched from
+//! a symbol server or taken from a global state (such as
+//! [`crate::common::EXTRA_INFO`]): the companion files must be passed along
+//! with the main one. They're the PE file for a PDB, the PDB file for a PE,
+//! the debug file of a stripped ELF or Mach-O file and the `.dwo` or `.dwp`
+//! files of an ELF file built with `-gsplit-dwarf`. The companions which
+//! don't match the main file are ignored.
+
+use std::borrow::Cow;
+use std::path::PathBuf;
+use std::sync::Arc;
+use symbolic::common::Arch;
+use symbolic::debuginfo::pdb::PdbObject;
+use symbolic::debuginfo::pe::PeObject;
+use symbolic::debuginfo::{peek, FileFormat, Object};
+
+use crate::common;
+use crate::error::Error;
+use crate::mapping::PathMappings;
+use crate::object_info::{CollectOptions, ObjectInfo};
+use crate::platform::Platform;
+use crate::split_dwarf::SplitDwarf;
+use crate::utils;
+
+/// The name and the content of a file.
+#[derive(Clone, Copy, Debug)]
+pub struct NamedBuffer<'a> {
+    /// The file name, without any directory (e.g. `xul.pdb` or `libxul.so`)
+    pub name: &'a str,
+    pub data: &'a [u8],
+}
+
+impl<'a> NamedBuffer<'a> {
+    pub fn new(name: &'a str, data: &'a [u8]) -> Self {
+        Self { name, data }
+    }
+
+    /// Get the name of the uncompressed file (e.g. `xul.pdb` for `xul.pd_`).
+    fn uncompressed_name(&self) -> String {
+        utils::get_base(self.name).to_string_lossy().into_owned()
+    }
+
+    /// Get the content, uncompressed if it's a cabinet file (e.g. `xul.pd_`).
+    fn content(&self) -> common::Result<Cow<'a, [u8]>> {
+        if !self.name.ends_with('_') {
+            return Ok(Cow::Borrowed(self.data));
+        }
+        utils::read_cabinet(self.data.to_vec(), PathBuf::from(self.name))
+            .map(Cow::Owned)
+            .ok_or_else(|| anyhow::anyhow!("Unable to read the cabinet file {}", self.name))
+    }
+}
+
+/// The options to get the symbols of some buffers.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The architecture to select in fat Mach-O files
+    pub arch: Arch,
+    pub mapping: Option<Arc<PathMappings>>,
+    pub emit_inlines: bool,
+    /// The extra INFO lines (e.g. `GENERATOR mozilla/dump_syms 2.3.7`)
+    pub extra_info: Vec<String>,
+    /// The number of threads collecting the functions of an object (the debug
+    /// information is still parsed by a single one)
+    pub num_threads: usize,
+}
+
+impl Options {
+    /// The source paths are never resolved against the filesystem.
+    fn collect_options(&self) -> CollectOptions {
+        CollectOptions {
+            mapping: self.mapping.clone(),
+            collect_inlines: self.emit_inlines,
+            canonicalize: false,
+            num_threads: self.num_threads,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            arch: common::get_compile_time_arch().parse().unwrap_or_default(),
+            mapping: None,
+            emit_inlines: true,
+            extra_info: Vec::new(),
+            num_threads: 1,
+        }
+    }
+}
+
+/// A companion file with its uncompressed content.
+struct Companion<'a> {
+    name: String,
+    data: Cow<'a, [u8]>,
+}
+
+impl Companion<'_> {
+    fn parse(&self) -> Option<Object<'_>> {
+        Object::parse(&self.data).ok()
+    }
+}
+
+/// Get the [`ObjectInfo`] for `main`, using the `companions` if needed.
+pub fn object_info_from_buffers(
+    main: &NamedBuffer,
+    companions: &[NamedBuffer],
+    options: &Options,
+) -> common::Result<ObjectInfo> {
+    let data = main.content()?;
+    let name = main.uncompressed_name();
+    let companions = companions
+        .iter()
+        .map(|c| {
+            Ok(Companion {
+                name: c.uncompressed_name(),
+                data: c.content()?,
+            })
+        })
+        .collect::<common::Result<Vec<_>>>()?;
+
+    let mut object_info = match peek(&data, true /* check for fat binary */) {
+        FileFormat::Elf => from_elf(&data, &name, &companions, options)?,
+        FileFormat::Pdb => from_pdb(&data, &name, &companions, options)?,
+        FileFormat::Pe => from_pe(&data, &name, &companions, options)?,
+        FileFormat::MachO => from_macho(&data, &name, &companions, options)?,
+        FileFormat::Breakpad => ObjectInfo::from_breakpad(&data)?,
+        _ => anyhow::bail!("Unknown file format for {}", main.name),
+    };
+
+    object_info.set_extra_info(options.extra_info.clone());
+    Ok(object_info)
+}
+
+fn from_elf_object(
+    object: &Object,
+    name: &str,
+    companions: &[Companion],
+    options: &Options,
+) -> common::Result<ObjectInfo> {
+    let split_dwarf = match object {
+        Object::Elf(elf) => {
+            let bufs: Vec<&[u8]> = companions.iter().map(|c| c.data.as_ref()).collect();
+            SplitDwarf::from_buffers(elf, &bufs)
+        }
+        _ => None,
+    };
+    ObjectInfo::from_object_and_split_dwarf(
+        object,
+        name,
+        None,
+        None,
+        Platform::Linux,
+        &options.collect_options(),
+        split_dwarf.as_ref(),
+    )
+}
+
+fn from_elf(
+    data: &[u8],
+    name: &str,
+    companions: &[Companion],
+    options: &Options,
+) -> common::Result<ObjectInfo> {
+    let object = Object::parse(data).map_err(|e| Error::parse(name, e))?;
+    let object_info = from_elf_object(&object, name, companions, options)?;
+    if object.has_debug_info() {
+        return Ok(object_info);
+    }
+
+    // The separate debug file.
+    let debug_file = companions.iter().find_map(|c| {
+        let o = c.parse()?;
+        (o.file_format() == FileFormat::Elf
+            && o.has_debug_info()
+            && o.debug_id() == object.debug_id())
+        .then_some((c, o))
+    });
+    match debug_file {
+        Some((c, o)) => {
+            let debug_info = from_elf_object(&o, &c.name, companions, options)?;
+            ObjectInfo::merge(object_info, debug_info)
+        }
+        None => Ok(object_info),
+    }
+}
+
+fn from_pdb(
+    data: &[u8],
+    name: &str,
+    companions: &[Companion],
+    options: &Options,
+) -> common::Result<ObjectInfo> {
+    let pdb = PdbObject::parse(data).map_err(|e| Error::parse(name, e))?;
+    let pe = companions.iter().find_map(|c| {
+        let pe = PeObject::parse(&c.data).ok()?;
+        (pe.debug_id() == pdb.debug_id()).then_some((c.name.as_str(), pe))
+    });
+    let (pe_name, pe) = pe.unzip();
+
+    ObjectInfo::from_pdb(pdb, name, pe_name, pe, &options.collect_options())
+}
+
+fn from_pe(
+    data: &[u8],
+    name: &str,
+    companions: &[Companion],
+    options: &Options,
+) -> common::Result<ObjectInfo> {
+    let pe = PeObject::parse(data).map_err(|e| Error::parse(name, e))?;
+    let pdb = companions.iter().find_map(|c| {
+        let pdb = PdbObject::parse(&c.data).ok()?;
+        (pdb.debug_id() == pe.debug_id()).then_some((c.name.as_str(), pdb))
+    });
+
+    match pdb {
+        Some((pdb_name, pdb)) => ObjectInfo::from_pdb(
+            pdb,
+            pdb_name,
+            Some(name),
+            Some(pe),
+            &options.collect_options(),
+        ),
+        None => ObjectInfo::from_pe(name, pe),
+    }
+}
+
+fn from_macho(
+    data: &[u8],
+    name: &str,
+    companions: &[Companion],
+    options: &Options,
+) -> common::Result<ObjectInfo> {
+    let collect_options = options.collect_options();
+    let mut object_info = ObjectInfo::from_macho(data, name, options.arch, &collect_options)?;
+
+    // The dSYM file.
+    for c in companions {
+        if peek(&c.data, true) != FileFormat::MachO {
+            continue;
+        }
+        let Ok(info) = ObjectInfo::from_macho(&c.data, &c.name, options.arch, &collect_options)
+        else {
+            continue;
+        };
+        if info.get_debug_id() == object_info.get_debug_id() {
+            object_info = ObjectInfo::merge(object_info, info)?;
+        }
+    }
+
+    Ok(object_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read;
+
+    fn dump(main: (&str, &str), companions: &[(&str, &str)], options: &Options) -> Vec<String> {
+        let data = read(main.1).unwrap();
+        let companion_data: Vec<_> = companions.iter().map(|(_, p)| read(p).unwrap()).collect();
+        let companions: Vec<_> = companions
+            .iter()
+            .zip(companion_data.iter())
+            .map(|((name, _), data)| NamedBuffer::new(name, data))
+            .collect();
+        let info = object_info_from_buffers(&NamedBuffer::new(main.0, &data), &companions, options)
+            .unwrap();
+
+        let mut output = Vec::new();
+        info.dump(&mut output).unwrap();
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    fn read_sym(path: &str) -> Vec<String> {
+        String::from_utf8(read(path).unwrap())
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn test_elf_debug_file() {
+        let options = Options {
+            emit_inlines: false,
+            num_threads: 2,
+            ..Default::default()
+        };
+        let output = dump(
+            ("basic.stripped", "./test_data/linux/basic.stripped"),
+            &[
+                ("basic.full", "./test_data/linux/basic.full.inlines.sym"),
+                ("basic.dbg", "./test_data/linux/basic.dbg"),
+            ],
+            &options,
+        );
+        let expected = read_sym("./test_data/linux/basic.dbg.sym");
+
+        // The module name is the stripped one.
+        assert_eq!(output[1..], expected[1..]);
+    }
+
+    #[test]
+    fn test_elf_split_dwarf() {
+        let output = dump(
+            ("basic.split", "./test_data/linux/basic.split"),
+            &[("basic.split.dwp", "./test_data/linux/basic.split.dwp")],
+            &Options::default(),
+        );
+        let expected = read_sym("./test_data/linux/basic.split.inlines.sym");
+
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_no_canonicalization() {
+        // The path of the source file becomes
+        // /home/calixte/dev/mozilla/dump_syms/../calixte/test_data/linux/basic.cpp
+        // which is normalized when it's resolved against the filesystem.
+        let mut data = read("./test_data/linux/basic.full").unwrap();
+        let from = b"dump_syms.calixteman";
+        let pos = data.windows(from.len()).position(|w| w == from).unwrap();
+        data[pos..pos + from.len()].copy_from_slice(b"dump_syms/../calixte");
+
+        let info = object_info_from_buffers(
+            &NamedBuffer::new("basic.full", &data),
+            &[],
+            &Options::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            info.files.get_mapping(),
+            ["/home/calixte/dev/mozilla/dump_syms/../calixte/test_data/linux/basic.cpp"]
+        );
+
+        if Platform::Linux.is_target() {
+            let info =
+                ObjectInfo::from_elf(&data, "basic.full", Platform::Linux, &Default::default())
+                    .unwrap();
+            assert_eq!(
+                info.files.get_mapping(),
+                ["/home/calixte/dev/mozilla/calixte/test_data/linux/basic.cpp"]
+            );
+        }
+    }
+
+    #[test]
+    fn test_pe_pdb() {
+        let options = Options {
+            extra_info: vec!["GENERATOR test".to_string()],
+            ..Default::default()
+        };
+        let companions = [("basic32.pd_", "./test_data/windows/basic32.pd_")];
+        let output = dump(
+            ("basic32.dll", "./test_data/windows/basic32.dll"),
+            &companions,
+            &options,
+        );
+
+        assert!(output[0].ends_with(" basic32.pdb"));
+        assert!(output[1].ends_with(" basic32.dll"));
+        assert_eq!(output[2], "INFO GENERATOR test");
+        assert!(output.iter().any(|line| line.starts_with("FUNC ")));
+
+        // The PDB with the PE as companion gives the same result.
+        let pdb_output = dump(
+            ("basic32.pd_", "./test_data/windows/basic32.pd_"),
+            &[("basic32.dll", "./test_data/windows/basic32.dll")],
+            &options,
+        );
+        assert_eq!(output, pdb_output);
+
+        // Without the PDB there are only PUBLIC.
+        let output = dump(
+            ("basic32.dll", "./test_data/windows/basic32.dll"),
+            &[],
+            &options,
+        );
+        assert!(!output.iter().any(|line| line.starts_with("FUNC ")));
+    }
+}
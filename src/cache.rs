@@ -7,24 +7,50 @@ use dirs::home_dir;
 use futures::{stream, StreamExt};
 use reqwest::{blocking, header::USER_AGENT, Client};
 use std::fs::{self, File};
-use std::io::{BufWriter, Read, Write};
+use std::io::{BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
 use url::Url;
 
 use crate::common;
-use crate::utils;
+use crate::error::Error;
+use crate::utils::{self, FileData};
 
 const DEFAULT_STORE: &str = "https://msdl.microsoft.com/download/symbols";
 const DEFAULT_USER_AGENT: &str = "Microsoft-Symbol-Server/6.3.0.0";
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ServerKind {
+    /// A Microsoft symbol server: SRV*cache*url
+    Microsoft,
+    /// A debuginfod server: DEBUGINFOD*cache*url
+    Debuginfod,
+}
+
+#[derive(Clone, Debug)]
 pub struct SymbolServer {
+    kind: ServerKind,
     cache: Option<String>,
     server: String,
 }
 
+/// The files which can be fetched from a debuginfod server.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebuginfodArtifact {
+    DebugInfo,
+    Executable,
+}
+
+impl DebuginfodArtifact {
+    fn name(self) -> &'static str {
+        match self {
+            Self::DebugInfo => "debuginfo",
+            Self::Executable => "executable",
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct Job {
     cache: Option<PathBuf>,
@@ -33,7 +59,9 @@ struct Job {
 
 impl Job {
     fn new(cache: Option<PathBuf>, url: String) -> common::Result<Self> {
-        anyhow::ensure!(Url::parse(&url).is_ok(), "Invalid url: {}", url);
+        if Url::parse(&url).is_err() {
+            return Err(Error::Config(format!("Invalid url: {url}")).into());
+        }
         Ok(Self { cache, url })
     }
 }
@@ -52,20 +80,26 @@ fn correct_path(path: &str) -> String {
 
 fn parse_srv(path: &str) -> Option<SymbolServer> {
     // srv*symbolstore, or srv*localsymbolcache*symbolstore
+    // debuginfod*url, or debuginfod*localcache*url
     let parts: Vec<_> = path.split('*').map(|p| p.trim()).collect();
-    if parts.is_empty() || parts[0].to_lowercase() != "srv" {
-        return None;
-    }
+    let kind = match parts[0].to_lowercase().as_str() {
+        "srv" => ServerKind::Microsoft,
+        "debuginfod" => ServerKind::Debuginfod,
+        _ => return None,
+    };
     let server = match parts.len() {
-        1 => SymbolServer {
+        1 if kind == ServerKind::Microsoft => SymbolServer {
+            kind,
             cache: None,
             server: DEFAULT_STORE.to_string(),
         },
         2 => SymbolServer {
+            kind,
             cache: None,
             server: parts[1].to_string(),
         },
         3 => SymbolServer {
+            kind,
             cache: Some(correct_path(parts[1])),
             server: parts[2].to_string(),
         },
@@ -79,27 +113,20 @@ fn parse_sympath(path: &str) -> Vec<SymbolServer> {
     path.split([';', '\n']).filter_map(parse_srv).collect()
 }
 
-fn read_config() -> Option<Vec<SymbolServer>> {
+fn read_config() -> common::Result<Option<Vec<SymbolServer>>> {
     let home = match home_dir() {
         Some(h) => h,
-        _ => return None,
+        _ => return Ok(None),
     };
 
     let conf = home.join(".dump_syms").join("config");
     if !conf.exists() {
-        return None;
+        return Ok(None);
     }
 
-    let mut file = File::open(&conf)
-        .unwrap_or_else(|_| panic!("Unable to open the file {}", conf.to_str().unwrap()));
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf)
-        .unwrap_or_else(|_| panic!("Unable to read the file {}", conf.to_str().unwrap()));
-
-    let content = String::from_utf8(buf)
-        .unwrap_or_else(|_| panic!("Not utf-8 data in the file {}", conf.to_str().unwrap()));
+    let content = fs::read_to_string(&conf).map_err(|e| Error::io(&conf, e))?;
 
-    read_config_from_str(&content)
+    Ok(read_config_from_str(&content))
 }
 
 fn read_config_from_str(s: &str) -> Option<Vec<SymbolServer>> {
@@ -111,39 +138,39 @@ fn read_config_from_str(s: &str) -> Option<Vec<SymbolServer>> {
     }
 }
 
-pub fn get_sym_servers(symbol_server: Option<&str>) -> Option<Vec<SymbolServer>> {
-    symbol_server.map_or_else(read_config, read_config_from_str)
+/// Get the symbol servers from `symbol_server` or, if `None`, from the
+/// configuration file `$HOME/.dump_syms/config`.
+pub fn get_sym_servers(symbol_server: Option<&str>) -> common::Result<Option<Vec<SymbolServer>>> {
+    match symbol_server {
+        Some(symbol_server) => Ok(read_config_from_str(symbol_server)),
+        None => read_config(),
+    }
 }
 
-fn copy_in_cache(path: Option<PathBuf>, data: &[u8]) -> bool {
+fn copy_in_cache(path: Option<PathBuf>, data: &[u8]) -> common::Result<bool> {
     if data.is_empty() || data.starts_with(b"Symbol Not Found") {
-        return false;
+        return Ok(false);
     }
 
     let path = match path {
         Some(p) => p,
-        _ => return true,
+        _ => return Ok(true),
     };
 
     if let Some(parent) = path.parent() {
         if !parent.exists() {
-            fs::create_dir_all(parent).unwrap_or_else(|_| {
-                panic!(
-                    "Unable to create cache directory {}",
-                    parent.to_str().unwrap()
-                )
-            });
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
         }
     }
 
-    let output = File::create(&path)
-        .unwrap_or_else(|_| panic!("Cannot open file {} for writing", path.to_str().unwrap()));
+    let output = File::create(&path).map_err(|e| Error::io(&path, e))?;
     let mut output = BufWriter::new(output);
     output
         .write_all(data)
-        .unwrap_or_else(|_| panic!("Cannot write file {}", path.to_str().unwrap()));
+        .and_then(|_| output.flush())
+        .map_err(|e| Error::io(&path, e))?;
 
-    true
+    Ok(true)
 }
 
 fn search_in_cache(
@@ -152,7 +179,11 @@ fn search_in_cache(
     base: &Path,
     file_name: &str,
 ) -> Option<PathBuf> {
-    for cache in servers.iter().filter_map(|x| x.cache.as_ref()) {
+    for cache in servers
+        .iter()
+        .filter(|x| x.kind == ServerKind::Microsoft)
+        .filter_map(|x| x.cache.as_ref())
+    {
         let path = PathBuf::from(cache).join(base).join(id).join(file_name);
         if path.exists() {
             return Some(path);
@@ -161,10 +192,15 @@ fn search_in_cache(
     None
 }
 
-fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) -> Vec<Job> {
+fn get_jobs(
+    servers: &[SymbolServer],
+    id: &str,
+    base: &Path,
+    file_name: &str,
+) -> common::Result<Vec<Job>> {
     // The query urls are: https://symbols.mozilla.org/xul.pdb/DEBUG_ID/xul.pd_
     let mut jobs = Vec::new();
-    for server in servers.iter() {
+    for server in servers.iter().filter(|x| x.kind == ServerKind::Microsoft) {
         let path = server
             .cache
             .as_ref()
@@ -172,8 +208,7 @@ fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) ->
         let job = Job::new(
             path.clone(),
             format!("{}/{}/{}/{}", server.server, file_name, id, file_name),
-        )
-        .unwrap_or_else(|e| panic!("{}", e));
+        )?;
         jobs.push(job);
         if !file_name.ends_with('_') {
             let job = Job::new(
@@ -185,13 +220,12 @@ fn get_jobs(servers: &[SymbolServer], id: &str, base: &Path, file_name: &str) ->
                     id,
                     &file_name[..file_name.len() - 1]
                 ),
-            )
-            .unwrap_or_else(|e| panic!("{}", e));
+            )?;
             jobs.push(job);
         }
     }
 
-    jobs
+    Ok(jobs)
 }
 
 async fn check_if_file_exists(results: Arc<Mutex<Vec<Job>>>, client: &Client, job: Job) {
@@ -208,12 +242,13 @@ async fn check_if_file_exists(results: Arc<Mutex<Vec<Job>>>, client: &Client, jo
     }
 }
 
-fn check_data(jobs: Vec<Job>) -> Option<Job> {
+fn check_data(jobs: Vec<Job>) -> common::Result<Option<Job>> {
     let client = Client::new();
     let n_queries = jobs.len();
     let results = Arc::new(Mutex::new(Vec::new()));
 
-    Runtime::new().unwrap().block_on(async {
+    let runtime = Runtime::new().map_err(|e| Error::Network(e.to_string()))?;
+    runtime.block_on(async {
         stream::iter(jobs)
             .map({
                 let results = &results;
@@ -226,65 +261,230 @@ fn check_data(jobs: Vec<Job>) -> Option<Job> {
     });
 
     let results = Arc::try_unwrap(results).unwrap().into_inner().unwrap();
-    results.first().cloned()
+    Ok(results.first().cloned())
 }
 
-fn fetch_data(jobs: Vec<Job>) -> Option<Vec<u8>> {
-    if let Some(job) = check_data(jobs) {
-        let mut buf = Vec::new();
-        let client = blocking::Client::new();
-        let resp = client
-            .get(&job.url)
-            .header(USER_AGENT, DEFAULT_USER_AGENT)
-            .send();
-        if let Ok(mut resp) = resp {
-            if resp.copy_to(&mut buf).is_err() {
-                None
-            } else if copy_in_cache(job.cache, &buf) {
-                Some(buf)
-            } else {
-                None
-            }
-        } else {
-            None
-        }
-    } else {
-        None
-    }
+fn fetch_data(jobs: Vec<Job>) -> common::Result<Option<Vec<u8>>> {
+    let Some(job) = check_data(jobs)? else {
+        return Ok(None);
+    };
+
+    let mut buf = Vec::new();
+    let client = blocking::Client::new();
+    let mut resp = client
+        .get(&job.url)
+        .header(USER_AGENT, DEFAULT_USER_AGENT)
+        .send()
+        .map_err(|e| Error::Network(e.to_string()))?;
+    resp.copy_to(&mut buf)
+        .map_err(|e| Error::Network(e.to_string()))?;
+
+    Ok(copy_in_cache(job.cache, &buf)?.then_some(buf))
 }
 
 pub fn search_file(
     file_name: String,
     id: &str,
     sym_servers: Option<&Vec<SymbolServer>>,
-) -> (Option<Vec<u8>>, String) {
+) -> common::Result<(Option<FileData>, String)> {
     if file_name.is_empty() {
-        return (None, file_name);
+        return Ok((None, file_name));
     }
 
     let servers = match sym_servers {
         Some(s) => s,
-        _ => return (None, file_name),
+        _ => return Ok((None, file_name)),
     };
 
     let base = utils::get_base(&file_name);
 
     // Start with the caches
     if let Some(path) = search_in_cache(servers, id, &base, &file_name) {
-        return (Some(utils::read_file(path)), file_name);
+        return Ok((Some(utils::read_file(path)?), file_name));
     }
 
     // Try the symbol servers
     // Each job contains the path where to cache data (if one) and a query url
-    let jobs = get_jobs(servers, id, &base, &file_name);
-    let buf = fetch_data(jobs);
+    let jobs = get_jobs(servers, id, &base, &file_name)?;
+    let buf = fetch_data(jobs)?;
 
     if let Some(buf) = buf {
         let path = PathBuf::from(&file_name);
         let buf = utils::read_cabinet(buf, path)
-            .unwrap_or_else(|| panic!("Unable to read the file {} from the server", file_name));
-        (Some(buf), file_name)
+            .ok_or_else(|| Error::parse(&file_name, "invalid cabinet file from the server"))?;
+        Ok((Some(buf.into()), file_name))
     } else {
-        (None, file_name)
+        Ok((None, file_name))
+    }
+}
+
+/// Get a file from the debuginfod servers using the build id of an ELF file.
+///
+/// The files are cached at CACHE/buildid/BUILD_ID/{debuginfo,executable},
+/// the layout used by the debuginfod servers.
+pub fn search_debuginfod(
+    build_id: &str,
+    artifact: DebuginfodArtifact,
+    sym_servers: Option<&Vec<SymbolServer>>,
+) -> common::Result<Option<FileData>> {
+    let build_id = build_id.to_lowercase();
+    let Some(sym_servers) = sym_servers else {
+        return Ok(None);
+    };
+    let servers: Vec<_> = sym_servers
+        .iter()
+        .filter(|x| x.kind == ServerKind::Debuginfod)
+        .collect();
+    let cache_path = |server: &SymbolServer| {
+        server.cache.as_ref().map(|cache| {
+            PathBuf::from(cache)
+                .join("buildid")
+                .join(&build_id)
+                .join(artifact.name())
+        })
+    };
+
+    // Start with the caches
+    for path in servers.iter().filter_map(|server| cache_path(server)) {
+        if path.exists() {
+            return utils::read_file(path).map(Some);
+        }
+    }
+
+    // Try the servers one after the other: they're usually federated so
+    // there are only a few of them.
+    let client = blocking::Client::new();
+    for server in servers {
+        let url = format!(
+            "{}/buildid/{}/{}",
+            server.server.trim_end_matches('/'),
+            build_id,
+            artifact.name()
+        );
+        let resp = client
+            .get(&url)
+            .header(USER_AGENT, common::GENERATOR)
+            .send();
+        let buf = match resp {
+            Ok(resp) if resp.status().is_success() => resp.bytes(),
+            _ => continue,
+        };
+        if let Ok(buf) = buf {
+            if copy_in_cache(cache_path(server), &buf)? {
+                return Ok(Some(buf.to_vec().into()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::linux::DebugFileFinder;
+    use crate::object_info::{CollectOptions, ObjectInfo, Type};
+    use crate::platform::Platform;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::thread;
+    use tempfile::Builder;
+
+    const BUILD_ID: &str = "b060ad20c6b47781552708aa192e7739fac7c84a";
+
+    /// Start a minimal debuginfod server serving basic.dbg and basic.stripped
+    /// and return its url.
+    fn start_debuginfod() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut request = String::new();
+                reader.read_line(&mut request).unwrap();
+                // Skip the headers.
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap_or_default() > 2 {
+                    line.clear();
+                }
+                let path = request.split(' ').nth(1).unwrap_or_default();
+                let file = match path.strip_prefix(&format!("/buildid/{BUILD_ID}/")) {
+                    Some("debuginfo") => Some("./test_data/linux/basic.dbg"),
+                    Some("executable") => Some("./test_data/linux/basic.stripped"),
+                    _ => None,
+                };
+                let (status, body) = match file {
+                    Some(file) => ("200 OK", fs::read(file).unwrap()),
+                    None => ("404 Not Found", Vec::new()),
+                };
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                let _ = stream.write_all(&body);
+            }
+        });
+
+        url
+    }
+
+    #[test]
+    fn test_parse_sympath() {
+        let servers = parse_sympath(
+            "SRV*https://symbols.mozilla.org;debuginfod*/tmp/cache*https://debuginfod.example.org;debuginfod",
+        );
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0].kind, ServerKind::Microsoft);
+        assert_eq!(servers[1].kind, ServerKind::Debuginfod);
+        assert_eq!(servers[1].cache.as_deref(), Some("/tmp/cache"));
+        assert_eq!(servers[1].server, "https://debuginfod.example.org");
+    }
+
+    #[test]
+    fn test_debuginfod() {
+        let tmp_dir = Builder::new().prefix("debuginfod").tempdir().unwrap();
+        let url = start_debuginfod();
+        let servers = get_sym_servers(Some(&format!(
+            "debuginfod*{}*{url}",
+            tmp_dir.path().display()
+        )))
+        .unwrap();
+
+        let missing =
+            search_debuginfod("0123", DebuginfodArtifact::DebugInfo, servers.as_ref()).unwrap();
+        assert!(missing.is_none());
+
+        let exe =
+            search_debuginfod(BUILD_ID, DebuginfodArtifact::Executable, servers.as_ref()).unwrap();
+        assert_eq!(
+            exe.as_deref(),
+            Some(&*fs::read("./test_data/linux/basic.stripped").unwrap())
+        );
+
+        let dbg =
+            search_debuginfod(BUILD_ID, DebuginfodArtifact::DebugInfo, servers.as_ref()).unwrap();
+        let cached = tmp_dir
+            .path()
+            .join("buildid")
+            .join(BUILD_ID)
+            .join("debuginfo");
+        assert_eq!(dbg.as_deref(), Some(&*fs::read(cached).unwrap()));
+
+        // The stripped file has no local debug file so it's fetched from the server.
+        let stripped = Path::new("./test_data/linux/basic.stripped");
+        let finder = DebugFileFinder::new(Vec::<PathBuf>::new()).with_symbol_servers(servers);
+        let info = ObjectInfo::from_elf_with_debug_file(
+            &fs::read(stripped).unwrap(),
+            stripped,
+            "basic.stripped",
+            Platform::Linux,
+            &CollectOptions::default(),
+            &finder,
+        )
+        .unwrap();
+        assert_eq!(info.bin_type, Type::DebugInfo);
     }
 }
@@ -0,0 +1,160 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Find the functions which aren't covered by any STACK CFI INIT or
+//! STACK WIN record, so the stack can't be reliably unwound through them.
+
+use log::{info, warn};
+use serde::Serialize;
+use std::ops::Range;
+
+use crate::common;
+use crate::error::Error;
+use crate::object_info::ObjectInfo;
+
+#[derive(Debug, Serialize)]
+pub struct UncoveredFunction {
+    pub address: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct CfiCoverage {
+    /// The number of FUNC records with a non-empty range
+    pub functions: usize,
+    /// The number of functions overlapping some STACK records
+    pub covered: usize,
+    pub uncovered: Vec<UncoveredFunction>,
+}
+
+/// Check if `range` overlaps one of the sorted and merged `ranges`.
+fn overlaps(ranges: &[Range<u64>], range: Range<u64>) -> bool {
+    // The first range ending after the start of `range`.
+    let i = ranges.partition_point(|r| r.end <= range.start);
+    ranges.get(i).is_some_and(|r| r.start < range.end)
+}
+
+impl CfiCoverage {
+    pub fn new(object_info: &ObjectInfo) -> Self {
+        let ranges = object_info.get_stack_ranges();
+        let mut coverage = Self::default();
+
+        for sym in object_info.symbols.values() {
+            if sym.is_public || sym.len == 0 {
+                continue;
+            }
+            coverage.functions += 1;
+            let start = u64::from(sym.rva);
+            if overlaps(&ranges, start..start + u64::from(sym.len)) {
+                coverage.covered += 1;
+            } else {
+                coverage.uncovered.push(UncoveredFunction {
+                    address: sym.rva,
+                    size: sym.len,
+                    name: sym.name.clone(),
+                });
+            }
+        }
+
+        coverage
+    }
+
+    /// The percentage of the functions with some CFI data (100 when there
+    /// are no functions).
+    pub fn percent(&self) -> f64 {
+        if self.functions == 0 {
+            100.
+        } else {
+            self.covered as f64 * 100. / self.functions as f64
+        }
+    }
+
+    /// Log the uncovered functions of the module `name` and fail if the
+    /// coverage is below `threshold` (a percentage).
+    pub fn check(&self, name: &str, threshold: f64) -> common::Result<()> {
+        if !self.uncovered.is_empty() {
+            warn!(
+                "{name}: {} of {} functions have no CFI data ({:.1}% coverage)",
+                self.uncovered.len(),
+                self.functions,
+                self.percent()
+            );
+            for fun in &self.uncovered {
+                info!("No CFI data for {} at {:x}", fun.name, fun.address);
+            }
+        }
+
+        if self.percent() < threshold {
+            return Err(Error::CfiCoverage {
+                file: name.to_string(),
+                coverage: self.percent(),
+                threshold,
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::Path;
+
+    use crate::object_info::CollectOptions;
+    use crate::platform::Platform;
+
+    #[test]
+    fn test_overlaps() {
+        let ranges = [0x10..0x20, 0x30..0x40];
+        assert!(overlaps(&ranges, 0x10..0x11));
+        assert!(overlaps(&ranges, 0x1f..0x30));
+        assert!(overlaps(&ranges, 0x8..0x40));
+        assert!(!overlaps(&ranges, 0x0..0x10));
+        assert!(!overlaps(&ranges, 0x20..0x30));
+        assert!(!overlaps(&ranges, 0x40..0x50));
+        assert!(!overlaps(&[], 0x0..0x10));
+    }
+
+    #[test]
+    fn test_cfi_coverage() {
+        let path = Path::new("./test_data/linux/basic.full");
+        let buf = fs::read(path).unwrap();
+        let mut info = ObjectInfo::from_elf(
+            &buf,
+            "basic.full",
+            Platform::Linux,
+            &CollectOptions::default(),
+        )
+        .unwrap();
+
+        let coverage = CfiCoverage::new(&info);
+        assert_eq!(coverage.functions, 6);
+        assert_eq!(coverage.covered, 6);
+        assert!(coverage.check("basic.full", 100.).is_ok());
+
+        // Keep only the CFI of the first function.
+        let first = info.symbols.values().find(|sym| !sym.is_public).unwrap();
+        let first = format!("STACK CFI INIT {:x} ", first.rva);
+        let stack: String = info
+            .stack
+            .to_string()
+            .lines()
+            .skip_while(|line| !line.starts_with(&first))
+            .take_while(|line| line.starts_with(&first) || !line.starts_with("STACK CFI INIT "))
+            .map(|line| format!("{line}\n"))
+            .collect();
+        info.stack = stack.into();
+
+        let coverage = CfiCoverage::new(&info);
+        assert_eq!(coverage.covered, 1);
+        assert_eq!(coverage.uncovered.len(), 5);
+        assert!(coverage.check("basic.full", 10.).is_ok());
+        let err = coverage.check("basic.full", 50.).unwrap_err();
+        assert_eq!(crate::error::exit_code(&err), 7);
+    }
+}
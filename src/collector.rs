@@ -3,9 +3,13 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use crossbeam::channel::{bounded, unbounded};
 use goblin::pe::exception::ExceptionData;
+use hashbrown::HashSet;
 use log::{error, warn};
-use std::collections::btree_map;
+use std::collections::{btree_map, BTreeMap};
+use std::fmt::Debug;
+use std::thread;
 use symbolic::common::{Language, Name, NameMangling};
 use symbolic::debuginfo::{Function, Object, ObjectDebugSession};
 use symbolic::demangle::Demangle;
@@ -17,6 +21,12 @@ use crate::inline_origins::InlineOrigins;
 use crate::line::{InlineAddressRange, InlineSite, Lines};
 use crate::platform::Platform;
 
+/// The number of functions collected at once by a thread.
+const BATCH_SIZE: usize = 1024;
+
+/// The symbols, the files and the inline origins of a batch of functions.
+type Batch<'a> = (Symbols, SourceFiles, InlineOrigins<'a>);
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum Type {
     Stripped,
@@ -34,6 +44,8 @@ pub enum Type {
 pub struct Collector {
     pub platform: Platform,
     pub collect_inlines: bool,
+    /// The number of threads collecting the functions
+    pub num_threads: usize,
     pub syms: Symbols,
 }
 
@@ -345,6 +357,11 @@ impl Collector {
         source: &mut SourceFiles,
         inline_origins: &mut InlineOrigins<'a>,
     ) -> common::Result<()> {
+        if self.num_threads > 1 {
+            self.collect_functions_in_parallel(ds.functions(), source, inline_origins, BATCH_SIZE);
+            return Ok(());
+        }
+
         for fun in ds.functions() {
             match fun {
                 Ok(fun) => {
@@ -359,6 +376,136 @@ impl Collector {
         Ok(())
     }
 
+    /// Collect the functions in batches with `self.num_threads` threads.
+    ///
+    /// Only the collection is parallel: the compilation units aren't
+    /// partitioned, the functions are all parsed by the calling thread since
+    /// symbolic doesn't expose the units (and skips the functions already
+    /// seen in the previous ones). The batches are collected by the other
+    /// threads in their own symbols, files and inline origins, and they're
+    /// merged in order so the ids are the same as in the sequential case.
+    fn collect_functions_in_parallel<'a, I, E>(
+        &mut self,
+        functions: I,
+        source: &mut SourceFiles,
+        inline_origins: &mut InlineOrigins<'a>,
+        batch_size: usize,
+    ) where
+        I: Iterator<Item = Result<Function<'a>, E>>,
+        E: Debug,
+    {
+        let (job_sender, job_receiver) =
+            bounded::<(usize, Vec<Function<'a>>)>(2 * self.num_threads);
+        let (batch_sender, batch_receiver) = unbounded();
+        // The functions at the same address as a previous one are skipped
+        // before being sent so they don't add any file or inline origin.
+        let mut collected = HashSet::new();
+        let mut multiple = Vec::new();
+
+        thread::scope(|scope| {
+            for i in 0..self.num_threads {
+                let job_receiver = job_receiver.clone();
+                let batch_sender = batch_sender.clone();
+                let empty_source = source.new_empty();
+                let platform = self.platform;
+                let collect_inlines = self.collect_inlines;
+                thread::Builder::new()
+                    .name(format!("dump-syms collector {i}"))
+                    .spawn_scoped(scope, move || {
+                        for (index, functions) in job_receiver {
+                            let mut collector = Collector {
+                                platform,
+                                collect_inlines,
+                                num_threads: 1,
+                                syms: Symbols::default(),
+                            };
+                            let mut source = empty_source.new_empty();
+                            let mut inline_origins = InlineOrigins::default();
+                            for fun in &functions {
+                                collector.collect_function(fun, &mut source, &mut inline_origins);
+                            }
+                            let batch = (collector.syms, source, inline_origins);
+                            batch_sender.send((index, batch)).unwrap();
+                        }
+                    })
+                    .unwrap();
+            }
+            drop(batch_sender);
+
+            let mut batches = BTreeMap::new();
+            let mut next_index = 0;
+            let mut index = 0;
+            let mut batch = Vec::with_capacity(batch_size);
+
+            for fun in functions {
+                let fun = match fun {
+                    Ok(fun) => fun,
+                    Err(e) => {
+                        error!("Function collection: {e:?}");
+                        continue;
+                    }
+                };
+                if fun.address == 0 {
+                    continue;
+                }
+
+                let rva = fun.address as u32;
+                let is_function = self.syms.get(&rva).is_some_and(|sym| !sym.is_public);
+                if !collected.insert(rva) || is_function {
+                    multiple.push(rva);
+                    continue;
+                }
+
+                batch.push(fun);
+                if batch.len() == batch_size {
+                    let functions = std::mem::replace(&mut batch, Vec::with_capacity(batch_size));
+                    job_sender.send((index, functions)).unwrap();
+                    index += 1;
+
+                    // Merge the collected batches to bound the memory used.
+                    batches.extend(batch_receiver.try_iter());
+                    self.merge_batches(&mut batches, &mut next_index, source, inline_origins);
+                }
+            }
+
+            if !batch.is_empty() {
+                job_sender.send((index, batch)).unwrap();
+            }
+            drop(job_sender);
+
+            for (index, batch) in batch_receiver.iter() {
+                batches.insert(index, batch);
+                self.merge_batches(&mut batches, &mut next_index, source, inline_origins);
+            }
+        });
+
+        for rva in multiple {
+            if let Some(sym) = self.syms.get_mut(&rva) {
+                sym.is_multiple = true;
+            }
+        }
+    }
+
+    /// Merge the batches following the last merged one.
+    fn merge_batches<'a>(
+        &mut self,
+        batches: &mut BTreeMap<usize, Batch<'a>>,
+        next_index: &mut usize,
+        source: &mut SourceFiles,
+        inline_origins: &mut InlineOrigins<'a>,
+    ) {
+        while let Some((syms, batch_source, batch_inline_origins)) = batches.remove(next_index) {
+            let file_remapping = source.merge(batch_source);
+            let inline_origin_remapping = inline_origins.merge(batch_inline_origins);
+            for (rva, mut sym) in syms {
+                sym.remap_lines(Some(&file_remapping));
+                sym.remap_inlines(Some(&file_remapping), &inline_origin_remapping);
+                self.syms.insert(rva, sym);
+            }
+            *next_index += 1;
+        }
+    }
+
     // This runs after collect_functions / collect_placeholder_functions.
     pub fn collect_publics(&mut self, o: &Object) {
         for sym in o.symbols() {
@@ -462,3 +609,57 @@ impl Collector {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn collect(num_threads: usize) -> (Vec<String>, Vec<String>, Vec<String>) {
+        let buf = fs::read("./test_data/linux/basic.full").unwrap();
+        let object = Object::parse(&buf).unwrap();
+        let ds = object.debug_session().unwrap();
+        let mut collector = Collector {
+            platform: Platform::Linux,
+            collect_inlines: true,
+            num_threads,
+            syms: Symbols::default(),
+        };
+        let mut source = SourceFiles::new(None, Platform::Linux, false);
+        let mut inline_origins = InlineOrigins::default();
+
+        // The functions are collected twice to have some duplicates.
+        let functions = ds.functions().chain(ds.functions());
+        if num_threads == 1 {
+            for fun in functions {
+                collector.collect_function(&fun.unwrap(), &mut source, &mut inline_origins);
+            }
+        } else {
+            collector.collect_functions_in_parallel(functions, &mut source, &mut inline_origins, 1);
+        }
+
+        let syms = collector
+            .syms
+            .values()
+            .map(|sym| format!("{} {sym}", sym.is_multiple))
+            .collect();
+        (
+            syms,
+            source.get_mapping().get_mapping().to_vec(),
+            inline_origins.get_list(),
+        )
+    }
+
+    #[test]
+    fn test_collect_functions_in_parallel() {
+        let (syms, files, inline_origins) = collect(1);
+        assert!(syms.iter().all(|sym| sym.starts_with("true ")));
+        assert!(!inline_origins.is_empty());
+        for num_threads in [2, 3] {
+            assert_eq!(
+                collect(num_threads),
+                (syms.clone(), files.clone(), inline_origins.clone())
+            );
+        }
+    }
+}
@@ -14,6 +14,9 @@ pub type Result<T> = result::Result<T, anyhow::Error>;
 
 pub static EXTRA_INFO: OnceCell<Vec<String>> = OnceCell::new();
 
+/// The value of the `INFO GENERATOR` line.
+pub const GENERATOR: &str = concat!("mozilla/dump_syms ", env!("CARGO_PKG_VERSION"));
+
 pub fn demangle_options() -> DemangleOptions {
     DemangleOptions::complete().return_type(false)
 }
@@ -0,0 +1,763 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Translate the compact unwind entries into STACK CFI records: the ones of
+//! the `__LD,__compact_unwind` section of the relocatable object files and
+//! the ones of the `__TEXT,__unwind_info` section built from it by the linker.
+//!
+//! The records from `__eh_frame` (or the ones written by symbolic for
+//! `__unwind_info`) are only kept for the entries in DWARF mode and for the
+//! functions without any entry, and no record is written for the entries
+//! without unwind information.
+
+use goblin::mach::constants::S_ATTR_PURE_INSTRUCTIONS;
+use goblin::mach::header::MH_OBJECT;
+use goblin::mach::relocation::{
+    RelocType, RelocationInfo, ARM64_RELOC_SUBTRACTOR, ARM64_RELOC_UNSIGNED,
+    X86_64_RELOC_SUBTRACTOR, X86_64_RELOC_UNSIGNED,
+};
+use goblin::mach::segment::Section;
+use goblin::mach::MachO;
+use hashbrown::{HashMap, HashSet};
+use log::warn;
+use std::convert::TryInto;
+use std::fmt::Write;
+use symbolic::cfi::AsciiCfiWriter;
+use symbolic::common::Arch;
+use symbolic::debuginfo::macho::MachObject;
+use symbolic::debuginfo::Object;
+
+use crate::stack::Stack;
+
+/// The size of an entry in `__compact_unwind` (64-bit only): function address,
+/// function length, encoding, personality and LSDA.
+const ENTRY_SIZE: usize = 32;
+
+const MODE_MASK: u32 = 0x0F00_0000;
+
+const X86_64_MODE_RBP_FRAME: u32 = 0x0100_0000;
+const X86_64_MODE_STACK_IMMD: u32 = 0x0200_0000;
+const X86_64_MODE_STACK_IND: u32 = 0x0300_0000;
+const X86_64_MODE_DWARF: u32 = 0x0400_0000;
+const X86_64_REGS: [&str; 7] = ["", "$rbx", "$r12", "$r13", "$r14", "$r15", "$rbp"];
+
+const ARM64_MODE_FRAMELESS: u32 = 0x0200_0000;
+const ARM64_MODE_DWARF: u32 = 0x0300_0000;
+const ARM64_MODE_FRAME: u32 = 0x0400_0000;
+const ARM64_PAIRS: [(&str, &str); 9] = [
+    ("x19", "x20"),
+    ("x21", "x22"),
+    ("x23", "x24"),
+    ("x25", "x26"),
+    ("x27", "x28"),
+    ("d8", "d9"),
+    ("d10", "d11"),
+    ("d12", "d13"),
+    ("d14", "d15"),
+];
+
+/// The kinds of the second level pages of `__unwind_info`.
+const REGULAR_PAGE: u32 = 2;
+const COMPRESSED_PAGE: u32 = 3;
+
+#[derive(Debug, PartialEq)]
+struct Entry {
+    address: u64,
+    len: u32,
+    encoding: u32,
+}
+
+/// A register rule: `dest: src offset +`, followed by `^` when the value is
+/// read at this address.
+struct Rule {
+    dest: &'static str,
+    src: &'static str,
+    offset: i64,
+    deref: bool,
+}
+
+impl Rule {
+    fn is(dest: &'static str, src: &'static str, offset: i64) -> Self {
+        Self {
+            dest,
+            src,
+            offset,
+            deref: false,
+        }
+    }
+
+    fn at(dest: &'static str, offset: i64) -> Self {
+        Self {
+            dest,
+            src: ".cfa",
+            offset,
+            deref: true,
+        }
+    }
+}
+
+/// How to unwind the function of an entry.
+enum Unwind {
+    Rules(Vec<Rule>),
+    /// The function is described in `__eh_frame` (or the entry can't be
+    /// decoded).
+    EhFrame,
+    /// The entry has no unwind information (mode 0): the function may be a
+    /// leaf one or not, so there's no record for it.
+    Nothing,
+}
+
+type Decoder<'a> = dyn Fn(&Entry) -> Unwind + 'a;
+
+/// Get the registers saved by a frameless x86_64 function, as libunwind does:
+/// their numbers are encoded as a permutation in 10 bits.
+fn x86_64_frameless_registers(encoding: u32) -> Vec<&'static str> {
+    let count = ((encoding >> 10) & 0b111).min(6) as usize;
+    let mut permutation = encoding & 0x3FF;
+    let mut permunreg = [0u32; 6];
+    let factors: &[u32] = match count {
+        6 | 5 => &[120, 24, 6, 2, 1],
+        4 => &[60, 12, 3, 1],
+        3 => &[20, 4, 1],
+        2 => &[5, 1],
+        1 => &[1],
+        _ => &[],
+    };
+    for (i, factor) in factors.iter().enumerate() {
+        permunreg[i] = permutation / factor;
+        permutation -= permunreg[i] * factor;
+    }
+
+    let mut used = [false; 7];
+    let mut registers = Vec::with_capacity(count);
+    for unreg in permunreg.iter().take(count) {
+        let mut renum = 0;
+        for (reg, used) in used.iter_mut().enumerate().skip(1) {
+            if !*used {
+                if renum == *unreg {
+                    *used = true;
+                    registers.push(X86_64_REGS[reg]);
+                    break;
+                }
+                renum += 1;
+            }
+        }
+    }
+    registers
+}
+
+fn x86_64_rules(entry: &Entry, read_u32: &dyn Fn(u64) -> Option<u32>) -> Unwind {
+    let encoding = entry.encoding;
+    let stack_size = match encoding & MODE_MASK {
+        0 => return Unwind::Nothing,
+        X86_64_MODE_RBP_FRAME => {
+            let mut rules = vec![
+                Rule::is(".cfa", "$rbp", 16),
+                Rule::at("$rbp", -16),
+                Rule::at(".ra", -8),
+            ];
+            // The registers are saved below rbp and the offset advances even
+            // when there's no register.
+            let offset = i64::from((encoding >> 16) & 0xFF) + 2;
+            for i in 0..5 {
+                let reg = X86_64_REGS
+                    .get(((encoding >> (3 * i)) & 0b111) as usize)
+                    .copied()
+                    .unwrap_or_default();
+                if !reg.is_empty() {
+                    rules.push(Rule::at(reg, -(offset - i64::from(i)) * 8));
+                }
+            }
+            return Unwind::Rules(rules);
+        }
+        X86_64_MODE_STACK_IMMD => i64::from((encoding >> 16) & 0xFF) * 8,
+        X86_64_MODE_STACK_IND => {
+            // The stack size is the immediate value of the sub instruction
+            // in the function.
+            let sub_offset = u64::from((encoding >> 16) & 0xFF);
+            let adjust = i64::from((encoding >> 13) & 0b111);
+            match read_u32(entry.address + sub_offset) {
+                Some(size) => i64::from(size) + adjust * 8,
+                None => return Unwind::EhFrame,
+            }
+        }
+        X86_64_MODE_DWARF => return Unwind::EhFrame,
+        // An invalid mode.
+        _ => return Unwind::EhFrame,
+    };
+
+    let mut rules = vec![Rule::is(".cfa", "$rsp", stack_size), Rule::at(".ra", -8)];
+    // The registers are pushed just after the return address.
+    let registers = x86_64_frameless_registers(encoding);
+    for (i, reg) in registers.iter().rev().enumerate() {
+        rules.push(Rule::at(reg, -(i as i64 + 2) * 8));
+    }
+    Unwind::Rules(rules)
+}
+
+fn arm64_rules(entry: &Entry) -> Unwind {
+    let encoding = entry.encoding;
+    match encoding & MODE_MASK {
+        0 => Unwind::Nothing,
+        ARM64_MODE_FRAME => {
+            let mut rules = vec![
+                Rule::is(".cfa", "x29", 16),
+                Rule::at("x29", -16),
+                Rule::at(".ra", -8),
+            ];
+            // The pairs of registers are saved in ascending order below the
+            // frame record.
+            let saved = ARM64_PAIRS
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| encoding & (1 << i) != 0);
+            for (n, (_, (first, second))) in saved.enumerate() {
+                let n = n as i64;
+                rules.push(Rule::at(first, (-2 * n - 3) * 8));
+                rules.push(Rule::at(second, (-2 * n - 4) * 8));
+            }
+            Unwind::Rules(rules)
+        }
+        // A leaf function: the return address is in the link register.
+        ARM64_MODE_FRAMELESS => {
+            let stack_size = i64::from((encoding >> 12) & 0xFFF) * 16;
+            Unwind::Rules(vec![
+                Rule::is(".cfa", "sp", stack_size),
+                Rule::is(".ra", "x30", 0),
+            ])
+        }
+        ARM64_MODE_DWARF => Unwind::EhFrame,
+        // An invalid mode.
+        _ => Unwind::EhFrame,
+    }
+}
+
+/// Get the entries of the `__compact_unwind` section, with the addresses of
+/// the functions relocated.
+fn get_entries(
+    macho: &MachO,
+    data: &[u8],
+    relocs: &[RelocationInfo],
+    unsigned: RelocType,
+) -> Vec<Entry> {
+    let symbols = macho.symbols.as_ref();
+    let relocs: HashMap<_, _> = relocs
+        .iter()
+        .filter(|r| {
+            // The function addresses are the only absolute 64-bit values.
+            r.r_length() == 3 && !r.is_pic() && r.r_type() == unsigned
+        })
+        .map(|r| (r.r_address as usize, *r))
+        .collect();
+
+    data.chunks_exact(ENTRY_SIZE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut address = u64::from_le_bytes(chunk[0..8].try_into().unwrap());
+            let reloc = relocs.get(&(i * ENTRY_SIZE)).filter(|r| r.is_extern());
+            if let Some(reloc) = reloc {
+                // The value is an addend to the address of the symbol.
+                let symbol = symbols.and_then(|s| s.get(reloc.r_symbolnum()).ok());
+                address = address.wrapping_add(symbol.map_or(0, |(_, nlist)| nlist.n_value));
+            }
+            Entry {
+                address,
+                len: u32::from_le_bytes(chunk[8..12].try_into().unwrap()),
+                encoding: u32::from_le_bytes(chunk[12..16].try_into().unwrap()),
+            }
+        })
+        .collect()
+}
+
+/// Get the entries of the `__unwind_info` section of a linked file: the
+/// addresses of the functions are relative to the image, whose address is
+/// `load_address`.
+fn get_unwind_info_entries(data: &[u8], load_address: u64) -> Option<Vec<Entry>> {
+    let u32_at = |offset: usize| {
+        let bytes = data.get(offset..offset + 4)?;
+        Some(u32::from_le_bytes(bytes.try_into().ok()?))
+    };
+    let u16_at = |offset: usize| {
+        let bytes = data.get(offset..offset + 2)?;
+        Some(usize::from(u16::from_le_bytes(bytes.try_into().ok()?)))
+    };
+
+    if u32_at(0)? != 1 {
+        return None;
+    }
+    let common_offset = u32_at(4)? as usize;
+    let common_count = u32_at(8)? as usize;
+    let index_offset = u32_at(20)? as usize;
+    let index_count = u32_at(24)? as usize;
+
+    // The start and the encoding of the functions.
+    let mut functions = Vec::new();
+    // The last index only holds the end of the last function.
+    for index in (0..index_count.checked_sub(1)?).map(|i| index_offset + 12 * i) {
+        let base = u32_at(index)?;
+        let page = u32_at(index + 4)? as usize;
+        let entries = page + u16_at(page + 4)?;
+        let count = u16_at(page + 6)?;
+        match u32_at(page)? {
+            REGULAR_PAGE => {
+                for entry in (0..count).map(|i| entries + 8 * i) {
+                    functions.push((u32_at(entry)?, u32_at(entry + 4)?));
+                }
+            }
+            COMPRESSED_PAGE => {
+                // The encodings are the common ones followed by the ones of
+                // the page.
+                let encodings = page + u16_at(page + 8)?;
+                for entry in (0..count).map(|i| entries + 4 * i) {
+                    let entry = u32_at(entry)?;
+                    let i = (entry >> 24) as usize;
+                    let encoding = if i < common_count {
+                        u32_at(common_offset + 4 * i)?
+                    } else {
+                        u32_at(encodings + 4 * (i - common_count))?
+                    };
+                    functions.push((base.checked_add(entry & 0xFF_FFFF)?, encoding));
+                }
+            }
+            _ => return None,
+        }
+    }
+
+    // A function ends where the next one starts.
+    let end = u32_at(index_offset + 12 * (index_count - 1))?;
+    let ends = functions
+        .iter()
+        .skip(1)
+        .map(|(start, _)| *start)
+        .chain(std::iter::once(end));
+    Some(
+        functions
+            .iter()
+            .zip(ends)
+            .map(|(&(start, encoding), end)| Entry {
+                address: load_address + u64::from(start),
+                len: end.saturating_sub(start),
+                encoding,
+            })
+            .collect(),
+    )
+}
+
+fn find_section<'a, 'd>(
+    sections: &'a [(Section, &'d [u8])],
+    segment: &str,
+    name: &str,
+) -> Option<&'a (Section, &'d [u8])> {
+    sections
+        .iter()
+        .find(|(s, _)| s.segname().is_ok_and(|n| n == segment) && s.name().is_ok_and(|n| n == name))
+}
+
+fn get_relocations(macho: &MachO, section: &Section) -> Vec<RelocationInfo> {
+    macho
+        .relocations()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, _, s)| s.offset == section.offset && s.addr == section.addr)
+        .flat_map(|(_, relocs, _)| relocs.filter_map(Result::ok))
+        .collect()
+}
+
+/// Get a copy of the object file `data` where the differences between two
+/// symbols in `__eh_frame` (e.g. the pc-relative addresses of the functions
+/// in the FDEs) are relocated.
+fn relocate_eh_frame(
+    macho: &MachO,
+    data: &[u8],
+    sections: &[(Section, &[u8])],
+    (subtractor, unsigned_type): (RelocType, RelocType),
+) -> Option<Vec<u8>> {
+    let (section, _) = find_section(sections, "__TEXT", "__eh_frame")?;
+    let symbols = macho.symbols.as_ref()?;
+    let symbol = |r: &RelocationInfo| {
+        let (_, nlist) = symbols.get(r.r_symbolnum()).ok()?;
+        Some(nlist.n_value)
+    };
+
+    let relocs = get_relocations(macho, section);
+    let mut data = data.to_vec();
+    let mut relocated = false;
+    // A SUBTRACTOR relocation is followed by an UNSIGNED one at the same
+    // address: the value is the second symbol minus the first one plus the
+    // addend.
+    for pair in relocs.windows(2) {
+        let (sub, unsigned) = (&pair[0], &pair[1]);
+        if sub.r_type() != subtractor
+            || unsigned.r_type() != unsigned_type
+            || sub.r_address != unsigned.r_address
+            || !sub.is_extern()
+            || !unsigned.is_extern()
+        {
+            continue;
+        }
+        let (Some(from), Some(to)) = (symbol(sub), symbol(unsigned)) else {
+            continue;
+        };
+        let offset = section.offset as usize + sub.r_address as usize;
+        let delta = to.wrapping_sub(from);
+        match sub.r_length() {
+            2 => {
+                let value = data.get_mut(offset..offset + 4)?;
+                let addend = u32::from_le_bytes((*value).try_into().ok()?);
+                value.copy_from_slice(&addend.wrapping_add(delta as u32).to_le_bytes());
+            }
+            3 => {
+                let value = data.get_mut(offset..offset + 8)?;
+                let addend = u64::from_le_bytes((*value).try_into().ok()?);
+                value.copy_from_slice(&addend.wrapping_add(delta).to_le_bytes());
+            }
+            _ => continue,
+        }
+        relocated = true;
+    }
+
+    relocated.then_some(data)
+}
+
+/// Get the STACK CFI records from the DWARF unwind information of the Mach-O
+/// file `data`.
+fn get_dwarf_stack(data: &[u8]) -> Option<String> {
+    let object = MachObject::parse(data).ok()?;
+    let mut buf = Vec::new();
+    AsciiCfiWriter::new(&mut buf)
+        .process(&Object::MachO(object))
+        .ok()?;
+    String::from_utf8(buf).ok()
+}
+
+fn parse_init(line: &str) -> Option<u64> {
+    let rest = line.strip_prefix("STACK CFI INIT ")?;
+    u64::from_str_radix(rest.split(' ').next()?, 16).ok()
+}
+
+/// Get the start addresses of the STACK CFI INIT records.
+fn get_starts(stack: &str) -> HashSet<u64> {
+    stack.lines().filter_map(parse_init).collect()
+}
+
+/// Remove the STACK CFI records (an INIT one and the following ones) for the
+/// functions starting at one of the `starts` addresses.
+fn remove_records(stack: &str, starts: &HashSet<u64>) -> String {
+    let mut keep = true;
+    let mut res = String::with_capacity(stack.len());
+    for line in stack.lines() {
+        if line.starts_with("STACK CFI INIT ") {
+            keep = !parse_init(line).is_some_and(|start| starts.contains(&start));
+        } else if !line.starts_with("STACK CFI ") {
+            keep = true;
+        }
+        if keep {
+            res.push_str(line);
+            res.push('\n');
+        }
+    }
+    res
+}
+
+/// Add the STACK CFI records for the compact unwind entries of `object` to
+/// the `stack` ones, built from its DWARF unwind information (and from its
+/// `__unwind_info` section by symbolic).
+pub(crate) fn add_stack_records(object: &MachObject, stack: &mut Stack) {
+    let arch = object.arch();
+    // The types of the relocations of the differences between two symbols.
+    let relocation_types = match arch {
+        Arch::Amd64 => (X86_64_RELOC_SUBTRACTOR, X86_64_RELOC_UNSIGNED),
+        Arch::Arm64 => (ARM64_RELOC_SUBTRACTOR, ARM64_RELOC_UNSIGNED),
+        _ => return,
+    };
+    let Ok(macho) = MachO::parse(object.data(), 0) else {
+        return;
+    };
+    if !macho.is_64 || !macho.little_endian {
+        return;
+    }
+
+    let sections: Vec<_> = macho
+        .segments
+        .sections()
+        .flatten()
+        .filter_map(Result::ok)
+        .collect();
+    let load_address = object.load_address();
+    let entries = if let Some((section, data)) = find_section(&sections, "__LD", "__compact_unwind")
+    {
+        let relocs = get_relocations(&macho, section);
+        get_entries(&macho, data, &relocs, relocation_types.1)
+    } else if let Some((_, data)) = find_section(&sections, "__TEXT", "__unwind_info") {
+        let Some(entries) = get_unwind_info_entries(data, load_address) else {
+            warn!("Invalid __unwind_info section");
+            return;
+        };
+        entries
+    } else {
+        return;
+    };
+    let mut text = match std::mem::take(stack).into_string() {
+        Ok(text) => text,
+        Err(e) => {
+            warn!("Cannot read the STACK records: {e}");
+            return;
+        }
+    };
+
+    if macho.header.filetype == MH_OBJECT {
+        // The FDEs have been read without their relocations.
+        if let Some(dwarf_stack) =
+            relocate_eh_frame(&macho, object.data(), &sections, relocation_types)
+                .as_deref()
+                .and_then(get_dwarf_stack)
+        {
+            text = dwarf_stack;
+        }
+    }
+
+    // Read the code of the functions (e.g. to get the size of a big stack).
+    let read_u32 = |address: u64| -> Option<u32> {
+        let (section, data) = sections.iter().find(|(s, _)| {
+            s.flags & S_ATTR_PURE_INSTRUCTIONS != 0 && (s.addr..s.addr + s.size).contains(&address)
+        })?;
+        let offset = (address - section.addr) as usize;
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    };
+    let decode: Box<Decoder> = if arch == Arch::Amd64 {
+        Box::new(|e| x86_64_rules(e, &read_u32))
+    } else {
+        Box::new(arm64_rules)
+    };
+
+    let dwarf_starts = get_starts(&text);
+    let mut starts = HashSet::new();
+    let mut records = String::new();
+    for entry in entries.iter().filter(|e| e.len != 0) {
+        let start = entry.address.wrapping_sub(load_address);
+        let rules = match decode(entry) {
+            Unwind::Rules(rules) => rules,
+            Unwind::EhFrame => {
+                if !dwarf_starts.contains(&start) {
+                    warn!("No CFI data for the compact unwind entry at {start:x}");
+                }
+                continue;
+            }
+            Unwind::Nothing => {
+                starts.insert(start);
+                continue;
+            }
+        };
+        starts.insert(start);
+        write!(records, "STACK CFI INIT {start:x} {:x}", entry.len).unwrap();
+        for rule in rules {
+            write!(records, " {}: {} {} +", rule.dest, rule.src, rule.offset).unwrap();
+            if rule.deref {
+                records.push_str(" ^");
+            }
+        }
+        records.push('\n');
+    }
+
+    let mut text = remove_records(&text, &starts);
+    text.push_str(&records);
+    *stack = text.into();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_info::{CollectOptions, ObjectInfo};
+
+    fn entry(encoding: u32) -> Entry {
+        Entry {
+            address: 0x10,
+            len: 0x20,
+            encoding,
+        }
+    }
+
+    fn to_string(unwind: Unwind) -> String {
+        let Unwind::Rules(rules) = unwind else {
+            panic!("No rules");
+        };
+        rules
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}: {} {} +{}",
+                    r.dest,
+                    r.src,
+                    r.offset,
+                    if r.deref { " ^" } else { "" }
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    #[test]
+    fn test_x86_64_frameless_registers() {
+        // The registers are listed from the last pushed one.
+        assert_eq!(x86_64_frameless_registers(0x0208_0802), ["$rbx", "$r14"]);
+        assert_eq!(
+            x86_64_frameless_registers(0x0200_1800),
+            ["$rbx", "$r12", "$r13", "$r14", "$r15", "$rbp"]
+        );
+        assert!(x86_64_frameless_registers(0x0200_0000).is_empty());
+    }
+
+    #[test]
+    fn test_x86_64_rules() {
+        let no_code = |_| None;
+        assert_eq!(
+            to_string(x86_64_rules(&entry(0x0102_0011), &no_code)),
+            ".cfa: $rbp 16 + $rbp: .cfa -16 + ^ .ra: .cfa -8 + ^ $rbx: .cfa -32 + ^ $r12: .cfa -24 + ^"
+        );
+        assert_eq!(
+            to_string(x86_64_rules(&entry(0x0208_0802), &no_code)),
+            ".cfa: $rsp 64 + .ra: .cfa -8 + ^ $r14: .cfa -16 + ^ $rbx: .cfa -24 + ^"
+        );
+        // sub $0x10000, %rsp at 0x14.
+        let code = |address| (address == 0x14).then_some(0x10000);
+        assert_eq!(
+            to_string(x86_64_rules(&entry(0x0304_4400), &code)),
+            ".cfa: $rsp 65552 + .ra: .cfa -8 + ^ $rbx: .cfa -16 + ^"
+        );
+        assert!(matches!(
+            x86_64_rules(&entry(0x0304_4400), &no_code),
+            Unwind::EhFrame
+        ));
+        assert!(matches!(
+            x86_64_rules(&entry(0x0400_0100), &no_code),
+            Unwind::EhFrame
+        ));
+        assert!(matches!(x86_64_rules(&entry(0), &no_code), Unwind::Nothing));
+    }
+
+    #[test]
+    fn test_arm64_rules() {
+        assert_eq!(
+            to_string(arm64_rules(&entry(0x0400_0003))),
+            ".cfa: x29 16 + x29: .cfa -16 + ^ .ra: .cfa -8 + ^ x19: .cfa -24 + ^ x20: .cfa -32 + ^ x21: .cfa -40 + ^ x22: .cfa -48 + ^"
+        );
+        assert_eq!(
+            to_string(arm64_rules(&entry(0x0200_3000))),
+            ".cfa: sp 48 + .ra: x30 0 +"
+        );
+        assert!(matches!(arm64_rules(&entry(0x0300_0000)), Unwind::EhFrame));
+        assert!(matches!(arm64_rules(&entry(0)), Unwind::Nothing));
+    }
+
+    fn get_stack(file_name: &str, arch: Arch) -> Vec<String> {
+        let buf = std::fs::read(format!("./test_data/macos/{file_name}")).unwrap();
+        let info =
+            ObjectInfo::from_macho(&buf, file_name, arch, &CollectOptions::default()).unwrap();
+        info.stack
+            .to_string()
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn test_unwind_fat() {
+        // The functions at 0x50 on x86_64 and at 0x24 on arm64 are described
+        // in __eh_frame and the leaf ones have no compact unwind entry.
+        assert_eq!(
+            get_stack("unwind.fat", Arch::Amd64),
+            [
+                "STACK CFI INIT 50 5 .cfa: $rsp 8 + .ra: .cfa -8 + ^",
+                "STACK CFI 51 .cfa: $rsp 16 + $rbx: .cfa -16 + ^",
+                "STACK CFI 52 .cfa: $rsp 24 +",
+                "STACK CFI INIT 0 c .cfa: $rbp 16 + $rbp: .cfa -16 + ^ .ra: .cfa -8 + ^ $rbx: .cfa -32 + ^ $r12: .cfa -24 + ^",
+                "STACK CFI INIT 10 f .cfa: $rsp 64 + .ra: .cfa -8 + ^ $r14: .cfa -16 + ^ $rbx: .cfa -24 + ^",
+                "STACK CFI INIT 20 11 .cfa: $rsp 65552 + .ra: .cfa -8 + ^ $rbx: .cfa -16 + ^",
+            ]
+        );
+        assert_eq!(
+            get_stack("unwind.fat", Arch::Arm64),
+            [
+                "STACK CFI INIT 24 c .cfa: sp 0 + .ra: x30",
+                "STACK CFI 28 .cfa: sp 16 + x19: .cfa -16 + ^",
+                "STACK CFI INIT 0 18 .cfa: x29 16 + x29: .cfa -16 + ^ .ra: .cfa -8 + ^ x19: .cfa -24 + ^ x20: .cfa -32 + ^",
+                "STACK CFI INIT 18 c .cfa: sp 48 + .ra: x30 0 +",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unwind_linked_fat() {
+        // The same functions linked at 0x1000 (see link_unwind.py): the
+        // x86_64 entries are in a compressed page and the arm64 ones in a
+        // regular one, the leaf functions are in entries without unwind
+        // information (at 0x1040 and 0x1030) so there's no record for them.
+        assert_eq!(
+            get_stack("unwind-linked.fat", Arch::Amd64),
+            [
+                "STACK CFI INIT 1050 5 .cfa: $rsp 8 + .ra: .cfa -8 + ^",
+                "STACK CFI 1051 .cfa: $rsp 16 + $rbx: .cfa -16 + ^",
+                "STACK CFI 1052 .cfa: $rsp 24 +",
+                "STACK CFI INIT 1000 10 .cfa: $rbp 16 + $rbp: .cfa -16 + ^ .ra: .cfa -8 + ^ $rbx: .cfa -32 + ^ $r12: .cfa -24 + ^",
+                "STACK CFI INIT 1010 10 .cfa: $rsp 64 + .ra: .cfa -8 + ^ $r14: .cfa -16 + ^ $rbx: .cfa -24 + ^",
+                "STACK CFI INIT 1020 20 .cfa: $rsp 65552 + .ra: .cfa -8 + ^ $rbx: .cfa -16 + ^",
+            ]
+        );
+        assert_eq!(
+            get_stack("unwind-linked.fat", Arch::Arm64),
+            [
+                "STACK CFI INIT 1024 c .cfa: sp 0 + .ra: x30",
+                "STACK CFI 1028 .cfa: sp 16 + x19: .cfa -16 + ^",
+                "STACK CFI INIT 1000 18 .cfa: x29 16 + x29: .cfa -16 + ^ .ra: .cfa -8 + ^ x19: .cfa -24 + ^ x20: .cfa -32 + ^",
+                "STACK CFI INIT 1018 c .cfa: sp 48 + .ra: x30 0 +",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unwind_info_entries() {
+        // The x86_64 entries, the first ones being in the common encodings.
+        let buf = std::fs::read("./test_data/macos/unwind-linked.fat").unwrap();
+        let archive = symbolic::debuginfo::Archive::parse(&buf).unwrap();
+        let Some(Object::MachO(macho)) = archive.object_by_index(0).unwrap() else {
+            panic!("Not a Mach-O file");
+        };
+        let macho = MachO::parse(macho.data(), 0).unwrap();
+        let sections: Vec<_> = macho
+            .segments
+            .sections()
+            .flatten()
+            .filter_map(Result::ok)
+            .collect();
+        let (_, data) = find_section(&sections, "__TEXT", "__unwind_info").unwrap();
+        let entries = get_unwind_info_entries(data, 0).unwrap();
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|e| (e.address, e.len, e.encoding))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                (0x1000, 0x10, 0x0102_0011),
+                (0x1010, 0x10, 0x0208_0802),
+                (0x1020, 0x20, 0x0304_4400),
+                (0x1040, 0x10, 0),
+                // The FDE is at 0x90 in __eh_frame.
+                (0x1050, 0x5, 0x0400_0090),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_records() {
+        let stack = "STACK CFI INIT 0 4 .cfa: $rsp 8 +\nSTACK CFI 1 .cfa: $rsp 16 +\nSTACK CFI INIT 4 4 .cfa: $rsp 8 +\nSTACK CFI 5 .cfa: $rsp 16 +\n";
+        assert_eq!(
+            remove_records(stack, &std::iter::once(0).collect()),
+            "STACK CFI INIT 4 4 .cfa: $rsp 8 +\nSTACK CFI 5 .cfa: $rsp 16 +\n"
+        );
+    }
+}
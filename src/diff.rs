@@ -0,0 +1,368 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashMap;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+
+use crate::object_info::ObjectInfo;
+use crate::symbol::Symbol;
+
+/// Some global numbers about a module.
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Stats {
+    pub functions: usize,
+    pub publics: usize,
+    /// Number of bytes covered by line records.
+    pub line_bytes: u64,
+    /// Number of INLINE records.
+    pub inlines: usize,
+    /// Number of bytes covered by STACK CFI INIT or STACK WIN records.
+    pub cfi_bytes: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Function {
+    pub address: u32,
+    pub size: u32,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Renamed {
+    pub address: u32,
+    pub old_name: String,
+    pub new_name: String,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Moved {
+    pub name: String,
+    pub old_address: u32,
+    pub new_address: u32,
+}
+
+/// A numeric value which changed for a function.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Changed {
+    pub address: u32,
+    pub name: String,
+    pub old: u64,
+    pub new: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct InlineChange {
+    pub address: u32,
+    pub name: String,
+    /// Names of the functions which are now inlined.
+    pub added: Vec<String>,
+    /// Names of the functions which aren't inlined anymore.
+    pub removed: Vec<String>,
+}
+
+/// The differences between two versions of the symbols for the same module.
+///
+/// The functions are first matched by address, and then the remaining ones
+/// by name.
+#[derive(Debug, Default, Serialize)]
+pub struct Diff {
+    pub old: Stats,
+    pub new: Stats,
+    pub added: Vec<Function>,
+    pub removed: Vec<Function>,
+    pub renamed: Vec<Renamed>,
+    pub moved: Vec<Moved>,
+    pub resized: Vec<Changed>,
+    pub line_coverage: Vec<Changed>,
+    pub inlines: Vec<InlineChange>,
+}
+
+fn get_stats(info: &ObjectInfo) -> Stats {
+    let mut stats = Stats::default();
+    for sym in info.symbols.values() {
+        if sym.is_public {
+            stats.publics += 1;
+        } else {
+            stats.functions += 1;
+            stats.line_bytes += line_bytes(sym);
+            stats.inlines += sym.source.inlines.len();
+        }
+    }
+    stats.cfi_bytes = info
+        .get_stack_ranges()
+        .iter()
+        .map(|r| r.end - r.start)
+        .sum();
+    stats
+}
+
+fn line_bytes(sym: &Symbol) -> u64 {
+    sym.source.lines.iter().map(|l| u64::from(l.len)).sum()
+}
+
+fn inlined_names<'a>(info: &'a ObjectInfo, sym: &Symbol) -> BTreeSet<&'a str> {
+    sym.source
+        .inlines
+        .keys()
+        .filter_map(|site| info.inline_origins.get(site.inline_origin_id as usize))
+        .map(String::as_str)
+        .collect()
+}
+
+fn get_functions(info: &ObjectInfo) -> BTreeMap<u32, &Symbol> {
+    info.symbols
+        .iter()
+        .filter(|(_, sym)| !sym.is_public)
+        .map(|(addr, sym)| (*addr, sym))
+        .collect()
+}
+
+fn to_function(sym: &Symbol) -> Function {
+    Function {
+        address: sym.rva,
+        size: sym.len,
+        name: sym.name.clone(),
+    }
+}
+
+impl Diff {
+    pub fn new(old: &ObjectInfo, new: &ObjectInfo) -> Self {
+        let mut diff = Diff {
+            old: get_stats(old),
+            new: get_stats(new),
+            ..Default::default()
+        };
+
+        let mut old_funcs = get_functions(old);
+        let mut new_funcs = get_functions(new);
+        let mut pairs = Vec::new();
+
+        // Match by address.
+        old_funcs.retain(|addr, old_sym| {
+            if let Some(new_sym) = new_funcs.remove(addr) {
+                if old_sym.name != new_sym.name {
+                    diff.renamed.push(Renamed {
+                        address: *addr,
+                        old_name: old_sym.name.clone(),
+                        new_name: new_sym.name.clone(),
+                    });
+                }
+                pairs.push((*old_sym, new_sym));
+                false
+            } else {
+                true
+            }
+        });
+
+        // Match the remaining ones by name.
+        let mut new_by_name: HashMap<&str, Vec<u32>> = HashMap::default();
+        for (addr, sym) in new_funcs.iter() {
+            new_by_name.entry(&sym.name).or_default().push(*addr);
+        }
+        for old_sym in old_funcs.values() {
+            let new_sym = new_by_name
+                .get_mut(old_sym.name.as_str())
+                .and_then(|addrs| addrs.pop())
+                .and_then(|addr| new_funcs.remove(&addr));
+            if let Some(new_sym) = new_sym {
+                diff.moved.push(Moved {
+                    name: old_sym.name.clone(),
+                    old_address: old_sym.rva,
+                    new_address: new_sym.rva,
+                });
+                pairs.push((old_sym, new_sym));
+            } else {
+                diff.removed.push(to_function(old_sym));
+            }
+        }
+        diff.added = new_funcs.values().map(|sym| to_function(sym)).collect();
+
+        pairs.sort_by_key(|(_, new_sym)| new_sym.rva);
+        for (old_sym, new_sym) in pairs {
+            if old_sym.len != new_sym.len {
+                diff.resized.push(Changed {
+                    address: new_sym.rva,
+                    name: new_sym.name.clone(),
+                    old: u64::from(old_sym.len),
+                    new: u64::from(new_sym.len),
+                });
+            }
+
+            let (old_lines, new_lines) = (line_bytes(old_sym), line_bytes(new_sym));
+            if old_lines != new_lines {
+                diff.line_coverage.push(Changed {
+                    address: new_sym.rva,
+                    name: new_sym.name.clone(),
+                    old: old_lines,
+                    new: new_lines,
+                });
+            }
+
+            let old_inlined = inlined_names(old, old_sym);
+            let new_inlined = inlined_names(new, new_sym);
+            if old_inlined != new_inlined {
+                diff.inlines.push(InlineChange {
+                    address: new_sym.rva,
+                    name: new_sym.name.clone(),
+                    added: new_inlined
+                        .difference(&old_inlined)
+                        .map(|s| s.to_string())
+                        .collect(),
+                    removed: old_inlined
+                        .difference(&new_inlined)
+                        .map(|s| s.to_string())
+                        .collect(),
+                });
+            }
+        }
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.old == self.new
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.renamed.is_empty()
+            && self.moved.is_empty()
+            && self.resized.is_empty()
+            && self.line_coverage.is_empty()
+            && self.inlines.is_empty()
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let (old, new) = (&self.old, &self.new);
+        writeln!(
+            f,
+            "FUNC: {} -> {} ({} added, {} removed, {} renamed, {} moved, {} resized)",
+            old.functions,
+            new.functions,
+            self.added.len(),
+            self.removed.len(),
+            self.renamed.len(),
+            self.moved.len(),
+            self.resized.len()
+        )?;
+        writeln!(f, "PUBLIC: {} -> {}", old.publics, new.publics)?;
+        writeln!(
+            f,
+            "Line coverage: {} -> {} bytes ({} functions changed)",
+            old.line_bytes,
+            new.line_bytes,
+            self.line_coverage.len()
+        )?;
+        writeln!(
+            f,
+            "INLINE: {} -> {} ({} functions changed)",
+            old.inlines,
+            new.inlines,
+            self.inlines.len()
+        )?;
+        writeln!(
+            f,
+            "CFI coverage: {} -> {} bytes",
+            old.cfi_bytes, new.cfi_bytes
+        )?;
+
+        for fun in self.added.iter() {
+            writeln!(f, "+ {:x} {:x} {}", fun.address, fun.size, fun.name)?;
+        }
+        for fun in self.removed.iter() {
+            writeln!(f, "- {:x} {:x} {}", fun.address, fun.size, fun.name)?;
+        }
+        for r in self.renamed.iter() {
+            writeln!(f, "R {:x} {} => {}", r.address, r.old_name, r.new_name)?;
+        }
+        for m in self.moved.iter() {
+            writeln!(f, "M {:x} => {:x} {}", m.old_address, m.new_address, m.name)?;
+        }
+        for c in self.resized.iter() {
+            writeln!(f, "S {:x} {:x} => {:x} {}", c.address, c.old, c.new, c.name)?;
+        }
+        for c in self.line_coverage.iter() {
+            writeln!(f, "L {:x} {} => {} {}", c.address, c.old, c.new, c.name)?;
+        }
+        for i in self.inlines.iter() {
+            writeln!(f, "I {:x} {}", i.address, i.name)?;
+            for name in i.added.iter() {
+                writeln!(f, "  + {name}")?;
+            }
+            for name in i.removed.iter() {
+                writeln!(f, "  - {name}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read;
+
+    fn get_info(file: &str) -> ObjectInfo {
+        ObjectInfo::from_breakpad(&read(file).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_same() {
+        let info = get_info("./test_data/linux/basic.full.inlines.sym");
+        let diff = Diff::new(&info, &info);
+
+        assert!(diff.is_empty());
+        assert_eq!(diff.old.functions, 6);
+        assert_eq!(diff.old.inlines, 13);
+    }
+
+    #[test]
+    fn test_inlines() {
+        let old = get_info("./test_data/linux/basic.full.sym");
+        let new = get_info("./test_data/linux/basic.full.inlines.sym");
+        let diff = Diff::new(&old, &new);
+
+        assert!(!diff.is_empty());
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.renamed.is_empty());
+        assert_eq!(diff.old.inlines, 0);
+        assert_eq!(diff.new.inlines, 13);
+        assert_eq!(diff.inlines.len(), 4);
+        assert_eq!(diff.inlines[0].name, "inline_2(int)");
+        assert_eq!(diff.inlines[0].added, vec!["inline_1(int)".to_string()]);
+        assert_eq!(diff.old.cfi_bytes, diff.new.cfi_bytes);
+    }
+
+    #[test]
+    fn test_changes() {
+        let old = get_info("./test_data/linux/basic.full.sym");
+        let data = String::from_utf8(read("./test_data/linux/basic.full.sym").unwrap()).unwrap();
+        let data = data
+            .replace(
+                "FUNC 1125 26 0 inline_1(int)",
+                "FUNC 1125 28 0 inline_one(int)",
+            )
+            .replace("STACK CFI INIT 1040 2b", "STACK CFI INIT 1040 2a");
+        let new = ObjectInfo::from_breakpad(data.as_bytes()).unwrap();
+        let diff = Diff::new(&old, &new);
+
+        assert_eq!(
+            diff.renamed,
+            vec![Renamed {
+                address: 0x1125,
+                old_name: "inline_1(int)".to_string(),
+                new_name: "inline_one(int)".to_string(),
+            }]
+        );
+        assert_eq!(diff.resized.len(), 1);
+        assert_eq!(diff.resized[0].old, 0x26);
+        assert_eq!(diff.resized[0].new, 0x28);
+        assert_eq!(diff.old.cfi_bytes, diff.new.cfi_bytes + 1);
+    }
+}
@@ -3,26 +3,32 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use crossbeam::channel::{bounded, Receiver, Sender};
-use hashbrown::HashMap;
+use crossbeam::channel::{unbounded, Receiver, Sender};
+use hashbrown::{HashMap, HashSet};
 use log::{error, info};
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
+use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::thread;
-use symbolic::common::Arch;
+use symbolic::common::{Arch, DebugId};
 use symbolic::debuginfo::pdb::PdbObject;
 use symbolic::debuginfo::pe::PeObject;
-use symbolic::debuginfo::{peek, FileFormat};
+use symbolic::debuginfo::{peek, Archive, FileFormat, Object};
 
+use crate::cfi_coverage::CfiCoverage;
 use crate::common;
+use crate::error::Error;
+use crate::linux::DebugFileFinder;
 use crate::mapping::PathMappings;
-use crate::object_info::ObjectInfo;
+use crate::object_info::{CollectOptions, ObjectInfo};
 use crate::platform::Platform;
-use crate::utils;
+use crate::report::{ModuleReport, Report, ReportBuilder};
+use crate::utils::{self, FileData};
 use crate::windows;
 
 /// Different locations for file output
@@ -68,25 +74,87 @@ pub enum Output {
     },
 }
 
+/// Defines the format of the outputted symbols
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// The Breakpad text format
+    #[default]
+    Breakpad,
+    /// A JSON document, see [`crate::json`] for the schema
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> common::Result<Self> {
+        match s {
+            "breakpad" => Ok(Self::Breakpad),
+            "json" => Ok(Self::Json),
+            _ => anyhow::bail!("Invalid output format: {}", s),
+        }
+    }
+}
+
+/// Defines what to do with the modules already in the store directory
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Incremental {
+    /// Always dump the modules
+    #[default]
+    Off,
+    /// Don't dump the modules whose symbol file is in the store directory
+    Skip,
+    /// Same as `Skip` but the modules are dumped again if their symbol file
+    /// has been generated by another version of dump_syms
+    SkipSameGenerator,
+}
+
 impl From<PathBuf> for Output {
     fn from(path: PathBuf) -> Self {
         Self::File(FileOutput::Path(path))
     }
 }
 
+#[derive(Clone)]
 pub struct Config<'a> {
     pub output: Output,
+    pub format: Format,
+    /// Skip the modules already in the store directory (only with
+    /// [`Output::Store`]): their debug ids are read without collecting their
+    /// symbols
+    pub incremental: Incremental,
     pub symbol_server: Option<&'a str>,
     pub debug_id: Option<&'a str>,
     pub code_id: Option<&'a str>,
     pub arch: &'a str,
+    /// Dump all the architectures of the fat Mach-O files instead of `arch`
+    /// (each one has its own debug id so they need a store directory)
+    pub all_archs: bool,
     pub num_jobs: usize,
     pub check_cfi: bool,
+    /// Fail if the percentage of the functions covered by some STACK records
+    /// is below this one (see [`crate::cfi_coverage::CfiCoverage`])
+    pub cfi_threshold: Option<f64>,
     pub emit_inlines: bool,
+    /// Remove the FILE and INLINE_ORIGIN records which aren't referenced by
+    /// any line or INLINE record (e.g. after a merge)
+    pub prune_unreferenced: bool,
     pub mapping_var: Option<Vec<&'a str>>,
     pub mapping_src: Option<Vec<&'a str>>,
     pub mapping_dest: Option<Vec<&'a str>>,
     pub mapping_file: Option<&'a str>,
+    /// A SourceLink file mapping the path prefixes to URLs (the SourceLink
+    /// streams of the PDB files are used too)
+    pub mapping_source_link: Option<&'a str>,
+    /// Some local git checkouts: their files are mapped to
+    /// `git:host/repository:path:revision`
+    pub mapping_git: Option<Vec<&'a str>>,
+    /// The directories where to look for the separate debug files of the
+    /// stripped ELF files ([`crate::linux::DEFAULT_DEBUG_DIR`] if `None`)
+    pub debug_dirs: Option<Vec<&'a str>>,
+    /// The file where to write a JSON report of the results of each input
+    /// (see [`crate::report::Report`])
+    pub report: Option<&'a str>,
 }
 
 impl Config<'_> {
@@ -98,44 +166,96 @@ impl Config<'_> {
     pub fn with_output(output: Output) -> Self {
         Self {
             output,
+            format: Format::Breakpad,
+            incremental: Incremental::Off,
             symbol_server: None,
             debug_id: None,
             code_id: None,
             arch: common::get_compile_time_arch(),
+            all_archs: false,
             num_jobs: 1,
             check_cfi: true,
+            cfi_threshold: None,
             emit_inlines: true,
+            prune_unreferenced: false,
             mapping_var: None,
             mapping_src: None,
             mapping_dest: None,
             mapping_file: None,
+            mapping_source_link: None,
+            mapping_git: None,
+            debug_dirs: None,
+            report: None,
+        }
+    }
+
+    fn get_write_options(&self) -> WriteOptions {
+        WriteOptions {
+            cfi_check: CfiCheck {
+                required: self.check_cfi,
+                threshold: self.cfi_threshold,
+            },
+            prune_unreferenced: self.prune_unreferenced,
+            report_stats: self.report.is_some(),
+        }
+    }
+
+    /// Get the options to collect the symbols of a file with `num_threads`
+    /// threads (the jobs which aren't used to dump other files).
+    fn get_collect_options(&self, num_threads: usize) -> common::Result<CollectOptions> {
+        let mapping = PathMappings::new(
+            &self.mapping_var,
+            &self.mapping_src,
+            &self.mapping_dest,
+            &self.mapping_file,
+            &self.mapping_source_link,
+            &self.mapping_git,
+        )?
+        .map(Arc::new);
+        Ok(CollectOptions {
+            mapping,
+            collect_inlines: self.emit_inlines,
+            canonicalize: true,
+            num_threads: num_threads.max(1),
+        })
+    }
+
+    /// The architecture to dump or `None` for all of them.
+    fn get_arch(&self) -> common::Result<Option<Arch>> {
+        if self.all_archs {
+            Ok(None)
+        } else {
+            Ok(Some(Arch::from_str(self.arch)?))
         }
     }
+
+    fn get_debug_file_finder(&self) -> common::Result<DebugFileFinder> {
+        let finder = match &self.debug_dirs {
+            Some(dirs) => DebugFileFinder::new(dirs.iter().copied()),
+            None => DebugFileFinder::default(),
+        };
+        #[cfg(feature = "http")]
+        let finder = finder.with_symbol_servers(crate::cache::get_sym_servers(self.symbol_server)?);
+        Ok(finder)
+    }
 }
 
 fn get_pdb_object_info(
     buf: &[u8],
     path: &Path,
     filename: &str,
-    mapping: Option<Arc<PathMappings>>,
-    collect_inlines: bool,
+    options: &CollectOptions,
 ) -> common::Result<ObjectInfo> {
-    let pdb = PdbObject::parse(buf)?;
+    let pdb = PdbObject::parse(buf).map_err(|e| Error::parse(filename, e))?;
 
-    let (pe_name, pe_buf) = match windows::utils::find_pe_for_pdb(path, &pdb.debug_id()) {
-        Some((pe_name, pe_buf)) => (Some(pe_name), Some(pe_buf)),
-        None => (None, None),
-    };
-    let pe = pe_buf.as_deref().map(|buf| PeObject::parse(buf).unwrap());
+    let (pe_name, pe_buf) = windows::utils::find_pe_for_pdb(path, &pdb.debug_id())?.unzip();
+    let pe = pe_buf
+        .as_deref()
+        .map(PeObject::parse)
+        .transpose()
+        .map_err(|e| Error::parse(pe_name.as_deref().unwrap_or_default(), e))?;
 
-    ObjectInfo::from_pdb(
-        pdb,
-        filename,
-        pe_name.as_deref(),
-        pe,
-        mapping,
-        collect_inlines,
-    )
+    ObjectInfo::from_pdb(pdb, filename, pe_name.as_deref(), pe, options)
 }
 
 #[cfg(feature = "http")]
@@ -143,78 +263,263 @@ fn get_pe_pdb_object_info(
     buf: &[u8],
     path: &Path,
     filename: &str,
-    mapping: Option<Arc<PathMappings>>,
+    options: &CollectOptions,
     symbol_server: Option<&str>,
-    emit_inlines: bool,
 ) -> common::Result<ObjectInfo> {
-    let symbol_server = crate::cache::get_sym_servers(symbol_server);
-    let res = windows::utils::get_pe_pdb_buf(path, buf, symbol_server.as_ref());
+    let symbol_server = crate::cache::get_sym_servers(symbol_server)?;
+    let res = windows::utils::get_pe_pdb_buf(path, buf, symbol_server.as_ref())?;
 
     if let Some((pe, pdb_buf, pdb_name)) = res {
-        let pdb = PdbObject::parse(&pdb_buf)?;
-        let pdb = ObjectInfo::from_pdb(
-            pdb,
-            &pdb_name,
-            Some(filename),
-            Some(pe),
-            mapping,
-            emit_inlines,
-        )?;
+        let pdb = PdbObject::parse(&pdb_buf).map_err(|e| Error::parse(&pdb_name, e))?;
+        let pdb = ObjectInfo::from_pdb(pdb, &pdb_name, Some(filename), Some(pe), options)?;
         Ok(pdb)
     } else {
-        anyhow::bail!("No pdb file found")
+        Err(Error::MissingCompanion(format!("no pdb file found for {filename}")).into())
     }
 }
 
 #[cfg(not(feature = "http"))]
-fn get_pe_pdb_object_info<'a>(
-    buf: &[u8],
-    path: &Path,
-    filename: &str,
-    mapping: Option<Arc<PathMappings>>,
-    symbol_server: Option<&str>,
-    emit_inlines: bool,
+fn get_pe_pdb_object_info(
+    _buf: &[u8],
+    _path: &Path,
+    _filename: &str,
+    _options: &CollectOptions,
+    _symbol_server: Option<&str>,
 ) -> common::Result<ObjectInfo> {
     anyhow::bail!("HTTP symbol retrieval not enabled")
 }
 
 fn get_pe_object_info(buf: &[u8], path: &Path, filename: &str) -> common::Result<ObjectInfo> {
-    let pe = PeObject::parse(buf)
-        .unwrap_or_else(|_| panic!("Unable to parse the PE file {}", path.to_str().unwrap()));
+    let pe = PeObject::parse(buf).map_err(|e| Error::parse(path.display().to_string(), e))?;
     let pe = ObjectInfo::from_pe(filename, pe)?;
     Ok(pe)
 }
 
 #[inline]
-pub fn get_writer_for_sym(fo: &FileOutput) -> std::io::BufWriter<Box<dyn std::io::Write>> {
+pub fn get_writer_for_sym(
+    fo: &FileOutput,
+) -> common::Result<std::io::BufWriter<Box<dyn std::io::Write>>> {
     let output: Box<dyn std::io::Write> = match fo {
         FileOutput::Stdout => Box::new(std::io::stdout()),
         FileOutput::Stderr => Box::new(std::io::stderr()),
         FileOutput::Path(path) => {
-            let output = std::fs::File::create(path)
-                .unwrap_or_else(|_| panic!("Cannot open file {} for writing", path.display()));
+            let output = std::fs::File::create(path).map_err(|e| Error::io(path, e))?;
             Box::new(output)
         }
     };
 
-    std::io::BufWriter::new(output)
+    Ok(std::io::BufWriter::new(output))
+}
+
+fn get_store_path(dir: &Path, name: &str, debug_id: &str, format: Format) -> PathBuf {
+    let mut pb = dir.join(utils::get_path_for_sym(name, debug_id));
+    if format == Format::Json {
+        pb.set_extension("json");
+    }
+    pb
 }
 
-fn store(output: &Output, check_cfi: bool, object_info: ObjectInfo) -> common::Result<()> {
-    anyhow::ensure!(!check_cfi || object_info.has_stack(), "No CFI data");
+/// Get the names and the debug ids of the modules in `buf`, as they're used
+/// in the store directory, without collecting their symbols.
+fn get_module_ids(buf: &[u8], filename: &str, arch: Option<Arch>) -> Vec<(String, String)> {
+    let module_id = |name: &str, id: DebugId| (name.to_string(), id.breakpad().to_string());
+    match peek(buf, true /* check for fat binary */) {
+        FileFormat::Elf | FileFormat::Pdb => Object::parse(buf)
+            .map(|o| vec![module_id(filename, o.debug_id())])
+            .unwrap_or_default(),
+        FileFormat::Pe => PeObject::parse(buf)
+            .map(|pe| {
+                // The module is stored with the name of its PDB file.
+                let pdb_name = pe.debug_file_name();
+                let name = pdb_name
+                    .as_deref()
+                    .and_then(|n| n.rsplit(['/', '\\']).next())
+                    .unwrap_or(filename);
+                vec![module_id(name, pe.debug_id())]
+            })
+            .unwrap_or_default(),
+        FileFormat::MachO => {
+            let Ok(archive) = Archive::parse(buf) else {
+                return Vec::new();
+            };
+            let objects = archive.objects().filter_map(|o| o.ok());
+            let objects: Vec<_> = match arch {
+                Some(arch) if archive.object_count() > 1 => {
+                    objects.filter(|o| o.arch() == arch).collect()
+                }
+                _ => objects.collect(),
+            };
+            objects
+                .iter()
+                .map(|o| module_id(filename, o.debug_id()))
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Check if the symbol file at `path` has been generated by this version of
+/// dump_syms.
+fn has_same_generator(path: &Path, format: Format) -> bool {
+    let generator = format!("GENERATOR {}", common::GENERATOR);
+    match format {
+        Format::Breakpad => {
+            let Ok(file) = fs::File::open(path) else {
+                return false;
+            };
+            // The INFO lines are just after the MODULE one.
+            BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .skip(1)
+                .take_while(|line| line.starts_with("INFO "))
+                .any(|line| line["INFO ".len()..] == generator)
+        }
+        Format::Json => fs::read(path)
+            .ok()
+            .and_then(|buf| serde_json::from_slice::<serde_json::Value>(&buf).ok())
+            .and_then(|module| {
+                let info = module.get("info")?.as_array()?;
+                Some(info.iter().any(|line| line.as_str() == Some(&generator)))
+            })
+            .unwrap_or(false),
+    }
+}
+
+/// Get the names and the debug ids of the modules in `buf` if they're all
+/// already in the store directory and mustn't be dumped again.
+fn get_stored_modules(
+    output: &Output,
+    format: Format,
+    incremental: Incremental,
+    buf: &[u8],
+    filename: &str,
+    arch: Option<Arch>,
+) -> Option<Vec<(String, String)>> {
+    let Output::Store(dir) = output else {
+        return None;
+    };
+    if incremental == Incremental::Off {
+        return None;
+    }
+
+    let modules = get_module_ids(buf, filename, arch);
+    let stored = !modules.is_empty()
+        && modules.iter().all(|(name, debug_id)| {
+            let path = get_store_path(dir, name, debug_id, format);
+            path.is_file()
+                && (incremental == Incremental::Skip || has_same_generator(&path, format))
+        });
+    stored.then_some(modules)
+}
+
+/// Add the modules skipped by [`get_stored_modules`] to the report and get
+/// their debug ids.
+fn add_stored_modules(report: &mut ReportBuilder, modules: Vec<(String, String)>) -> Vec<String> {
+    modules
+        .into_iter()
+        .map(|(name, debug_id)| {
+            report.add_module(ModuleReport::stored(&name, &debug_id));
+            debug_id
+        })
+        .collect()
+}
+
+/// The CFI checks done before writing the symbols of a module.
+#[derive(Clone, Copy, Debug)]
+struct CfiCheck {
+    required: bool,
+    threshold: Option<f64>,
+}
+
+impl CfiCheck {
+    /// `coverage` is the one of `object_info`, it's required with a threshold.
+    fn check(
+        &self,
+        object_info: &ObjectInfo,
+        coverage: Option<&CfiCoverage>,
+    ) -> common::Result<()> {
+        if self.required && !object_info.has_stack() {
+            return Err(Error::NoCfi(object_info.get_name().to_string()).into());
+        }
+        if let (Some(threshold), Some(coverage)) = (self.threshold, coverage) {
+            coverage.check(object_info.get_name(), threshold)?;
+        }
+        Ok(())
+    }
+}
+
+/// What is done before writing the symbols of a module.
+#[derive(Clone, Copy, Debug)]
+struct WriteOptions {
+    cfi_check: CfiCheck,
+    /// Remove the FILE and INLINE_ORIGIN records which aren't referenced
+    prune_unreferenced: bool,
+    /// Add the statistics of the modules to the report (only when it's
+    /// written)
+    report_stats: bool,
+}
+
+impl WriteOptions {
+    /// The CFI coverage is computed once for the threshold and the report.
+    fn needs_coverage(&self) -> bool {
+        self.cfi_check.threshold.is_some() || self.report_stats
+    }
+}
+
+/// Store the symbols and add the result to the report.
+fn store(
+    output: &Output,
+    format: Format,
+    write_options: WriteOptions,
+    object_info: ObjectInfo,
+    report: &Mutex<ReportBuilder>,
+) {
+    let coverage = write_options
+        .needs_coverage()
+        .then(|| CfiCoverage::new(&object_info));
+    let mut module = ModuleReport::new(&object_info);
+    if let Some(coverage) = coverage.as_ref().filter(|_| write_options.report_stats) {
+        module.add_stats(&object_info, coverage);
+    }
+    let res = write_symbols(
+        output,
+        format,
+        write_options,
+        object_info,
+        coverage.as_ref(),
+        &mut module.outputs,
+    );
+    let mut report = report.lock().unwrap();
+    match res {
+        Ok(()) => report.add_module(module),
+        Err(e) => report.add_module_error(module, e),
+    }
+}
+
+fn write_symbols(
+    output: &Output,
+    format: Format,
+    write_options: WriteOptions,
+    mut object_info: ObjectInfo,
+    coverage: Option<&CfiCoverage>,
+    outputs: &mut Vec<String>,
+) -> common::Result<()> {
+    write_options.cfi_check.check(&object_info, coverage)?;
+    if write_options.prune_unreferenced {
+        object_info.prune_unreferenced()?;
+    }
 
     let sym_store_path = |dir: &Path| -> Option<PathBuf> {
         if dir.to_str()?.is_empty() {
             return None;
         }
-
-        let mut pb = PathBuf::new();
-        pb.push(dir);
-        pb.push(utils::get_path_for_sym(
+        Some(get_store_path(
+            dir,
             object_info.get_name(),
             object_info.get_debug_id(),
-        ));
-        Some(pb)
+            format,
+        ))
     };
 
     let (foutput, store) = match output {
@@ -226,23 +531,55 @@ fn store(output: &Output, check_cfi: bool, object_info: ObjectInfo) -> common::R
         } => (Some(file), sym_store_path(store_directory)),
     };
 
+    let mut files = Vec::new();
     if let Some(store) = store {
-        fs::create_dir_all(store.parent().unwrap())?;
+        let parent = store.parent().unwrap();
+        fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        files.push(("Store", FileOutput::Path(store)));
+    }
+    if let Some(file) = foutput {
+        files.push(("Write", file.clone()));
+    }
 
-        let fo = FileOutput::Path(store);
-        let output = get_writer_for_sym(&fo);
-        object_info.dump(output)?;
+    let writers = files
+        .iter()
+        .map(|(_, fo)| get_writer_for_sym(fo))
+        .collect::<common::Result<Vec<_>>>()?;
+    match format {
+        // The symbols are written in all the files at once so they can be
+        // dropped as soon as they're written.
+        Format::Breakpad => object_info.write_breakpad(Tee(writers))?,
+        Format::Json => {
+            for writer in writers {
+                object_info.dump_json(writer)?;
+            }
+        }
+    }
 
-        info!("Store symbols at {fo}");
+    for (action, fo) in files {
+        info!("{action} symbols at {fo}");
+        outputs.push(fo.to_string());
     }
+    Ok(())
+}
 
-    if let Some(file) = foutput {
-        let writer = get_writer_for_sym(file);
-        object_info.dump(writer)?;
+/// A writer writing the same data in several writers.
+struct Tee<W>(Vec<W>);
 
-        info!("Write symbols at {file}");
+impl<W: std::io::Write> std::io::Write for Tee<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        for writer in self.0.iter_mut() {
+            writer.write_all(buf)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        for writer in self.0.iter_mut() {
+            writer.flush()?;
+        }
+        Ok(())
     }
-    Ok(())
 }
 
 #[cfg(feature = "http")]
@@ -250,18 +587,29 @@ fn get_from_id(
     config: &Config,
     path: &Path,
     filename: String,
-) -> common::Result<(Vec<u8>, String)> {
+) -> common::Result<(FileData, String)> {
     if let Some(id) = config.debug_id.or(config.code_id) {
-        let symbol_server = crate::cache::get_sym_servers(config.symbol_server);
-        let (buf, filename) = crate::cache::search_file(filename, id, symbol_server.as_ref());
+        let symbol_server = crate::cache::get_sym_servers(config.symbol_server)?;
+        let (mut buf, filename) = crate::cache::search_file(filename, id, symbol_server.as_ref())?;
+        // An ELF file can be fetched from a debuginfod server with its build id.
+        if let (None, Some(code_id)) = (&buf, config.code_id) {
+            buf = crate::cache::search_debuginfod(
+                code_id,
+                crate::cache::DebuginfodArtifact::Executable,
+                symbol_server.as_ref(),
+            )?;
+        }
         return if let Some(buf) = buf {
             Ok((buf, filename))
         } else {
-            anyhow::bail!("Impossible to get file {} with id {}", filename, id)
+            Err(Error::MissingCompanion(format!(
+                "{filename} with id {id} isn't on the symbol servers"
+            ))
+            .into())
         };
     }
 
-    Ok((utils::read_file(path), filename))
+    Ok((utils::read_file(path)?, filename))
 }
 
 #[cfg(not(feature = "http"))]
@@ -269,73 +617,192 @@ fn get_from_id(
     _config: &Config,
     path: &Path,
     filename: String,
-) -> common::Result<(Vec<u8>, String)> {
-    Ok((utils::read_file(path), filename))
+) -> common::Result<(FileData, String)> {
+    Ok((utils::read_file(path)?, filename))
 }
 
-pub fn single_file(config: &Config, filename: &str) -> common::Result<()> {
+fn get_object_infos_from_file(
+    config: &Config,
+    filename: &str,
+    arch: Option<Arch>,
+) -> common::Result<Vec<ObjectInfo>> {
     let path = Path::new(filename);
     let filename = utils::get_filename(path);
 
     let (buf, filename) = get_from_id(config, path, filename)?;
+    get_object_infos_from_buf(config, buf, path, &filename, arch)
+}
 
-    let path_mappings = PathMappings::new(
-        &config.mapping_var,
-        &config.mapping_src,
-        &config.mapping_dest,
-        &config.mapping_file,
-    )?
-    .map(Arc::new);
-    let arch = Arch::from_str(config.arch)?;
-    let object_info = get_object_info(
-        buf,
+fn get_object_infos_from_buf(
+    config: &Config,
+    buf: FileData,
+    path: &Path,
+    filename: &str,
+    arch: Option<Arch>,
+) -> common::Result<Vec<ObjectInfo>> {
+    get_object_infos(
+        &buf,
         path,
-        &filename,
-        path_mappings,
+        filename,
+        &config.get_collect_options(config.num_jobs)?,
         arch,
         config.symbol_server,
-        config.emit_inlines,
-    )?;
-    store(&config.output, config.check_cfi, object_info)
+        &config.get_debug_file_finder()?,
+    )
+}
+
+/// Get the [`ObjectInfo`] for a single file, as it would be dumped by [`single_file`].
+pub fn object_info_from_file(config: &Config, filename: &str) -> common::Result<ObjectInfo> {
+    let arch = Arch::from_str(config.arch)?;
+    let mut object_infos = get_object_infos_from_file(config, filename, Some(arch))?;
+    Ok(object_infos.remove(0))
+}
+
+/// Get the [`ObjectInfo`] for each architecture of a single file if
+/// `config.all_archs` is set, or for the selected one otherwise.
+pub fn object_infos_from_file(config: &Config, filename: &str) -> common::Result<Vec<ObjectInfo>> {
+    get_object_infos_from_file(config, filename, config.get_arch()?)
+}
+
+/// Write the report if required and get an error if some inputs failed, or
+/// the report otherwise.
+fn finish_report(config: &Config, report: ReportBuilder) -> common::Result<Report> {
+    let report = report.finish();
+    if let Some(path) = config.report {
+        report.write_to_file(path)?;
+    }
+    if report.inputs.len() > 1 {
+        for input in &report.inputs {
+            let module_errors = input.modules.iter().filter_map(|m| m.error.as_ref());
+            for error in input.error.iter().chain(module_errors) {
+                error!("{}: {error}", input.input);
+            }
+        }
+    }
+    report.into_result()
+}
+
+pub fn single_file(config: &Config, filename: &str) -> common::Result<Report> {
+    let report = Mutex::new(ReportBuilder::default());
+    if let Err(e) = dump_single_file(config, filename, &report) {
+        report.lock().unwrap().add_input_error(filename, e);
+    }
+    finish_report(config, report.into_inner().unwrap())
+}
+
+fn dump_single_file(
+    config: &Config,
+    filename: &str,
+    report: &Mutex<ReportBuilder>,
+) -> common::Result<()> {
+    let arch = config.get_arch()?;
+    let path = Path::new(filename);
+    let (buf, name) = get_from_id(config, path, utils::get_filename(path))?;
+    let stored = get_stored_modules(
+        &config.output,
+        config.format,
+        config.incremental,
+        &buf,
+        &name,
+        arch,
+    );
+    if let Some(modules) = stored {
+        info!("{filename} is already in the store directory");
+        let mut report = report.lock().unwrap();
+        let debug_ids = add_stored_modules(&mut report, modules);
+        report.add_input(filename, debug_ids);
+        return Ok(());
+    }
+
+    let object_infos = get_object_infos_from_buf(config, buf, path, &name, arch)?;
+    anyhow::ensure!(
+        object_infos.len() == 1 || matches!(config.output, Output::Store(_)),
+        "A store directory (and no output file) is required to dump the {} architectures of {}",
+        object_infos.len(),
+        filename
+    );
+    let debug_ids = object_infos
+        .iter()
+        .map(|o| o.get_debug_id().to_string())
+        .collect();
+    report.lock().unwrap().add_input(filename, debug_ids);
+    for object_info in object_infos {
+        store(
+            &config.output,
+            config.format,
+            config.get_write_options(),
+            object_info,
+            report,
+        );
+    }
+    Ok(())
 }
 
 /// Detects the object format based on the bytes in the file.
+#[allow(clippy::too_many_arguments)]
 fn get_object_info(
-    buf: Vec<u8>,
+    buf: &[u8],
     path: &Path,
     filename: &str,
-    file_mapping: Option<Arc<PathMappings>>,
+    options: &CollectOptions,
     arch: Arch,
     symbol_server: Option<&str>,
-    emit_inlines: bool,
+    debug_file_finder: &DebugFileFinder,
 ) -> common::Result<ObjectInfo> {
-    let object_info = match peek(&buf, true /* check for fat binary */) {
-        FileFormat::Elf => {
-            ObjectInfo::from_elf(&buf, filename, Platform::Linux, file_mapping, emit_inlines)?
-        }
-        FileFormat::Pdb => get_pdb_object_info(&buf, path, filename, file_mapping, emit_inlines)?,
+    let object_info = match peek(buf, true /* check for fat binary */) {
+        FileFormat::Elf => ObjectInfo::from_elf_with_debug_file(
+            buf,
+            path,
+            filename,
+            Platform::Linux,
+            options,
+            debug_file_finder,
+        )?,
+        FileFormat::Pdb => get_pdb_object_info(buf, path, filename, options)?,
         FileFormat::Pe => {
-            if let Ok(pdb_info) = get_pe_pdb_object_info(
-                &buf,
-                path,
-                filename,
-                file_mapping,
-                symbol_server,
-                emit_inlines,
-            ) {
+            if let Ok(pdb_info) =
+                get_pe_pdb_object_info(buf, path, filename, options, symbol_server)
+            {
                 pdb_info
             } else {
-                get_pe_object_info(&buf, path, filename)?
+                get_pe_object_info(buf, path, filename)?
             }
         }
-        FileFormat::MachO => {
-            ObjectInfo::from_macho(&buf, filename, arch, file_mapping, emit_inlines)?
-        }
-        _ => anyhow::bail!("Unknown file format"),
+        FileFormat::MachO => ObjectInfo::from_macho(buf, filename, arch, options)?,
+        FileFormat::Breakpad => ObjectInfo::from_breakpad(buf)?,
+        _ => return Err(Error::UnknownFormat(path.display().to_string()).into()),
     };
     Ok(object_info)
 }
 
+/// Same as [`get_object_info`] but all the objects of a fat Mach-O file are
+/// returned if `arch` is `None`.
+#[allow(clippy::too_many_arguments)]
+fn get_object_infos(
+    buf: &[u8],
+    path: &Path,
+    filename: &str,
+    options: &CollectOptions,
+    arch: Option<Arch>,
+    symbol_server: Option<&str>,
+    debug_file_finder: &DebugFileFinder,
+) -> common::Result<Vec<ObjectInfo>> {
+    match arch {
+        None if peek(buf, true /* check for fat binary */) == FileFormat::MachO => {
+            ObjectInfo::from_macho_all(buf, filename, options)
+        }
+        _ => Ok(vec![get_object_info(
+            buf,
+            path,
+            filename,
+            options,
+            arch.unwrap_or_default(),
+            symbol_server,
+            debug_file_finder,
+        )?]),
+    }
+}
+
 #[allow(clippy::large_enum_variant)]
 enum JobType {
     Get,
@@ -345,36 +812,63 @@ enum JobType {
 struct JobItem {
     file: String,
     typ: JobType,
-    mapping: Option<Arc<PathMappings>>,
-    collect_inlines: bool,
+}
+
+/// The objects got from each input file, by debug id.
+type Results = HashMap<String, Vec<(String, ObjectInfo)>>;
+
+/// Merge the objects with the same debug id in the order given by
+/// [`ObjectInfo::cmp_for_merge`] (and by input file), so the result doesn't
+/// depend on the order in which the files have been dumped.
+fn merge_results(results: &mut Results, report: &Mutex<ReportBuilder>) -> Vec<ObjectInfo> {
+    let mut merged: Vec<_> = results
+        .drain()
+        .filter_map(|(_, mut infos)| {
+            infos.sort_by(|(a_file, a), (b_file, b)| {
+                a.cmp_for_merge(b).then_with(|| a_file.cmp(b_file))
+            });
+            let mut infos = infos.into_iter();
+            let (_, mut merged) = infos.next()?;
+            for (file, info) in infos {
+                merged = match ObjectInfo::merge(merged, info) {
+                    Ok(info) => info,
+                    Err(e) => {
+                        report.lock().unwrap().add_input_error(&file, e);
+                        return None;
+                    }
+                };
+            }
+            Some(merged)
+        })
+        .collect();
+    merged.sort_by(|a, b| a.get_debug_id().cmp(b.get_debug_id()));
+    merged
 }
 
 fn send_store_jobs(
     sender: &Sender<Option<JobItem>>,
-    results: &mut HashMap<String, ObjectInfo>,
+    results: Vec<ObjectInfo>,
     num_threads: usize,
     output: Output,
-    check_cfi: bool,
-    collect_inlines: bool,
-) -> common::Result<()> {
+    format: Format,
+    write_options: WriteOptions,
+    report: &Mutex<ReportBuilder>,
+) {
     if results.len() == 1 {
-        let (_, d) = results.drain().take(1).next().unwrap();
-        self::store(&output, check_cfi, d)?;
+        let d = results.into_iter().next().unwrap();
+        self::store(&output, format, write_options, d, report);
     } else {
-        for (_, d) in results.drain() {
+        for d in results {
             sender
                 .send(Some(JobItem {
                     file: "".to_string(),
                     typ: JobType::Dump(d),
-                    mapping: None,
-                    collect_inlines,
                 }))
                 .unwrap();
         }
     }
 
     poison_queue(sender, num_threads);
-    Ok(())
 }
 
 fn poison_queue(sender: &Sender<Option<JobItem>>, num_threads: usize) {
@@ -384,50 +878,104 @@ fn poison_queue(sender: &Sender<Option<JobItem>>, num_threads: usize) {
     }
 }
 
+/// Get the symbols of the file at `path`, if it isn't already in the store
+/// directory, and add it to the report.
+#[allow(clippy::too_many_arguments)]
+fn get_file(
+    path: &Path,
+    options: &CollectOptions,
+    arch: Option<Arch>,
+    output: &Output,
+    format: Format,
+    incremental: Incremental,
+    debug_file_finder: &DebugFileFinder,
+    stored: &Mutex<HashSet<String>>,
+    report: &Mutex<ReportBuilder>,
+) -> common::Result<Vec<ObjectInfo>> {
+    let filename = utils::get_filename(path);
+    let buf = utils::read_file(path)?;
+    let input = path.to_string_lossy();
+
+    if let Some(modules) = get_stored_modules(output, format, incremental, &buf, &filename, arch) {
+        // The files merged with this one (e.g. its debug file) are
+        // skipped too, thanks to the debug ids.
+        info!("{input} is already in the store directory");
+        let mut report = report.lock().unwrap();
+        let debug_ids = add_stored_modules(&mut report, modules);
+        stored.lock().unwrap().extend(debug_ids.iter().cloned());
+        report.add_input(&input, debug_ids);
+        return Ok(Vec::new());
+    }
+
+    let infos = get_object_infos(
+        &buf,
+        path,
+        &filename,
+        options,
+        arch,
+        None,
+        debug_file_finder,
+    )?;
+    let debug_ids = infos.iter().map(|i| i.get_debug_id().to_string()).collect();
+    report.lock().unwrap().add_input(&input, debug_ids);
+    Ok(infos)
+}
+
 #[allow(clippy::too_many_arguments)]
 fn consumer(
-    arch: Arch,
+    arch: Option<Arch>,
     sender: Sender<Option<JobItem>>,
     receiver: Receiver<Option<JobItem>>,
-    results: Arc<Mutex<HashMap<String, ObjectInfo>>>,
+    results: Arc<Mutex<Results>>,
+    stored: Arc<Mutex<HashSet<String>>>,
+    report: Arc<Mutex<ReportBuilder>>,
     counter: Arc<AtomicUsize>,
     num_threads: usize,
     output: Output,
-    check_cfi: bool,
-) -> common::Result<()> {
+    format: Format,
+    incremental: Incremental,
+    write_options: WriteOptions,
+    collect_options: CollectOptions,
+    debug_file_finder: Arc<DebugFileFinder>,
+) {
     while let Ok(job) = receiver.recv() {
         if job.is_none() {
-            return Ok(());
+            return;
         }
 
-        let JobItem {
-            file,
-            typ,
-            mapping,
-            collect_inlines,
-        } = job.unwrap();
+        let JobItem { file, typ } = job.unwrap();
 
         match typ {
             JobType::Get => {
-                let path = PathBuf::from(file);
-                let filename = utils::get_filename(&path);
-                let buf = utils::read_file(&path);
-
-                let info =
-                    get_object_info(buf, &path, &filename, mapping, arch, None, collect_inlines)?;
+                // A failure is reported and the other files are still
+                // dumped.
+                let infos = get_file(
+                    Path::new(&file),
+                    &collect_options,
+                    arch,
+                    &output,
+                    format,
+                    incremental,
+                    &debug_file_finder,
+                    &stored,
+                    &report,
+                )
+                .unwrap_or_else(|e| {
+                    report.lock().unwrap().add_input_error(&file, e);
+                    Vec::new()
+                });
 
+                // The objects are merged once all the files are dumped.
                 let mut results = results.lock().unwrap();
-                let info = if let Some(prev) = results.remove(info.get_debug_id()) {
-                    ObjectInfo::merge(info, prev).inspect_err(|_e| {
-                        poison_queue(&sender, num_threads);
-                    })?
-                } else {
-                    info
-                };
-                results.insert(info.get_debug_id().to_string(), info);
+                for info in infos {
+                    results
+                        .entry(info.get_debug_id().to_string())
+                        .or_default()
+                        .push((file.clone(), info));
+                }
             }
             JobType::Dump(d) => {
-                self::store(&output, check_cfi, d)?;
+                self::store(&output, format, write_options, d, &report);
                 continue;
             }
         }
@@ -436,52 +984,79 @@ fn consumer(
             // it was the last file: so we just have to add jobs to dump & store
             // and then poison the queue
             let mut results = results.lock().unwrap();
+            let stored = stored.lock().unwrap();
+            results.retain(|debug_id, _| !stored.contains(debug_id));
+            let results = merge_results(&mut results, &report);
             send_store_jobs(
                 &sender,
-                &mut results,
+                results,
                 num_threads,
                 output.clone(),
-                check_cfi,
-                collect_inlines,
-            )?;
+                format,
+                write_options,
+                &report,
+            );
         } else {
             counter.fetch_sub(1, Ordering::SeqCst);
         }
     }
-
-    Ok(())
 }
 
-pub fn several_files(config: &Config, filenames: &[&str]) -> common::Result<()> {
-    let file_mapping = PathMappings::new(
-        &config.mapping_var,
-        &config.mapping_src,
-        &config.mapping_dest,
-        &config.mapping_file,
-    )?
-    .map(Arc::new);
-    let arch = Arch::from_str(config.arch)?;
-    let results = Arc::new(Mutex::new(HashMap::default()));
+pub fn several_files(config: &Config, filenames: &[&str]) -> common::Result<Report> {
     let num_jobs = config.num_jobs.min(filenames.len());
+    let collect_options = config.get_collect_options(config.num_jobs / num_jobs.max(1))?;
+    let arch = config.get_arch()?;
+    let results = Arc::new(Mutex::new(HashMap::default()));
+    let stored = Arc::new(Mutex::new(HashSet::default()));
+    let report = Arc::new(Mutex::new(ReportBuilder::default()));
     let counter = Arc::new(AtomicUsize::new(filenames.len()));
 
-    let (sender, receiver) = bounded(num_jobs + 1);
+    // The files to dump are merged by debug id so they mustn't be used
+    // as separate debug files too.
+    let mut debug_file_finder = config.get_debug_file_finder()?;
+    for f in filenames {
+        debug_file_finder.ignore(f);
+    }
+    let debug_file_finder = Arc::new(debug_file_finder);
+
+    // The consumers send the dump jobs themselves (one per debug id, e.g. one
+    // per architecture of a fat file) so the queue mustn't block them.
+    let (sender, receiver) = unbounded();
 
     let mut receivers = Vec::with_capacity(num_jobs);
     for i in 0..num_jobs {
         let sender = sender.clone();
         let receiver = receiver.clone();
         let results = Arc::clone(&results);
+        let stored = Arc::clone(&stored);
+        let report = Arc::clone(&report);
         let counter = Arc::clone(&counter);
         let output = config.output.clone();
+        let format = config.format;
+        let incremental = config.incremental;
+        let debug_file_finder = Arc::clone(&debug_file_finder);
 
-        let check_cfi = config.check_cfi;
+        let write_options = config.get_write_options();
+        let collect_options = collect_options.clone();
 
         let t = thread::Builder::new()
             .name(format!("dump-syms {i}"))
             .spawn(move || {
                 consumer(
-                    arch, sender, receiver, results, counter, num_jobs, output, check_cfi,
+                    arch,
+                    sender,
+                    receiver,
+                    results,
+                    stored,
+                    report,
+                    counter,
+                    num_jobs,
+                    output,
+                    format,
+                    incremental,
+                    write_options,
+                    collect_options,
+                    debug_file_finder,
                 )
             })
             .unwrap();
@@ -494,17 +1069,232 @@ pub fn several_files(config: &Config, filenames: &[&str]) -> common::Result<()>
             .send(Some(JobItem {
                 file: f.to_string(),
                 typ: JobType::Get,
-                mapping: file_mapping.clone(),
-                collect_inlines: config.emit_inlines,
             }))
             .unwrap();
     }
 
     for receiver in receivers {
-        if let Err(e) = receiver.join().unwrap() {
-            error!("{e}");
+        receiver.join().unwrap();
+    }
+
+    let report = Arc::into_inner(report).unwrap().into_inner().unwrap();
+    finish_report(config, report)
+}
+
+/// A file of a module with its content.
+type ModuleFile = (PathBuf, FileData);
+
+/// Group the files by debug ids: a module is made of the files with the same
+/// debug ids (e.g. a PE file and its PDB file, a stripped ELF file and its
+/// debug file or a Mach-O file and its dSYM).
+///
+/// The content of the files is kept to dump them (the cabinet files aren't
+/// uncompressed again) and the files which can't be read are added to the
+/// report.
+fn group_modules(
+    files: &[PathBuf],
+    arch: Option<Arch>,
+    report: &Mutex<ReportBuilder>,
+) -> Vec<(Vec<String>, Vec<ModuleFile>)> {
+    let nil = DebugId::nil().breakpad().to_string();
+    let mut modules: BTreeMap<Vec<String>, Vec<ModuleFile>> = BTreeMap::new();
+    for path in files {
+        let buf = match utils::read_file(path) {
+            Ok(buf) => buf,
+            Err(e) => {
+                report
+                    .lock()
+                    .unwrap()
+                    .add_input_error(&path.to_string_lossy(), e);
+                continue;
+            }
+        };
+        let mut ids: Vec<_> = get_module_ids(&buf, &utils::get_filename(path), arch)
+            .into_iter()
+            .map(|(_, debug_id)| debug_id)
+            .filter(|debug_id| *debug_id != nil)
+            .collect();
+        if ids.is_empty() {
+            // E.g. a .dwo file.
+            info!("Skip {}: no debug id", path.display());
+            continue;
+        }
+        ids.sort();
+        modules.entry(ids).or_default().push((path.clone(), buf));
+    }
+
+    // The files are sorted so the merge doesn't depend on the order of the
+    // inputs.
+    modules
+        .into_iter()
+        .map(|(ids, mut files)| {
+            files.sort_by(|(a, _), (b, _)| a.cmp(b));
+            (ids, files)
+        })
+        .collect()
+}
+
+fn has_debug_info(buf: &[u8]) -> bool {
+    Archive::parse(buf)
+        .ok()
+        .and_then(|archive| archive.object_by_index(0).ok().flatten())
+        .is_some_and(|o| o.has_debug_info())
+}
+
+/// Get the symbols of a PDB file with the PE file of its module.
+fn get_module_pdb_object_info(
+    (pdb_path, pdb_buf): &ModuleFile,
+    (pe_path, pe_buf): &ModuleFile,
+    options: &CollectOptions,
+) -> common::Result<ObjectInfo> {
+    let pdb_name = utils::get_base(&utils::get_filename(pdb_path));
+    let pdb_name = pdb_name.to_string_lossy();
+    let pe_name = utils::get_base(&utils::get_filename(pe_path));
+    let pe_name = pe_name.to_string_lossy();
+    let pdb = PdbObject::parse(pdb_buf).map_err(|e| Error::parse(&*pdb_name, e))?;
+    let pe = PeObject::parse(pe_buf).map_err(|e| Error::parse(&*pe_name, e))?;
+    ObjectInfo::from_pdb(pdb, &pdb_name, Some(&pe_name), Some(pe), options)
+}
+
+/// Dump the files of a module, as [`several_files`] does: the objects with
+/// the same debug id are merged, the PE files being dumped with their PDB
+/// file if it's in the module.
+fn dump_module(
+    config: &Config,
+    files: &[ModuleFile],
+    arch: Option<Arch>,
+    options: &CollectOptions,
+    debug_file_finder: &DebugFileFinder,
+    report: &Mutex<ReportBuilder>,
+) -> common::Result<()> {
+    // The main file is the one without debug information, if any.
+    let (main_path, main_buf) = files
+        .iter()
+        .find(|(_, buf)| !has_debug_info(buf))
+        .unwrap_or(&files[0]);
+
+    let stored = get_stored_modules(
+        &config.output,
+        config.format,
+        config.incremental,
+        main_buf,
+        &utils::get_filename(main_path),
+        arch,
+    );
+    if let Some(modules) = stored {
+        info!("{} is already in the store directory", main_path.display());
+        add_stored_modules(&mut report.lock().unwrap(), modules);
+        return Ok(());
+    }
+
+    let position = |format| files.iter().position(|(_, buf)| peek(buf, false) == format);
+    let pdb = position(FileFormat::Pdb);
+    let pe = position(FileFormat::Pe);
+
+    let mut results = Results::default();
+    for (i, (path, buf)) in files.iter().enumerate() {
+        let infos = match (pdb, pe) {
+            (Some(pdb), Some(pe)) if i == pdb => {
+                vec![get_module_pdb_object_info(
+                    &files[pdb],
+                    &files[pe],
+                    options,
+                )?]
+            }
+            (Some(_), Some(pe)) if i == pe => continue,
+            _ => get_object_infos(
+                buf,
+                path,
+                &utils::get_filename(path),
+                options,
+                arch,
+                config.symbol_server,
+                debug_file_finder,
+            )?,
+        };
+        for info in infos {
+            results
+                .entry(info.get_debug_id().to_string())
+                .or_default()
+                .push((path.to_string_lossy().into_owned(), info));
         }
     }
 
+    for object_info in merge_results(&mut results, report) {
+        store(
+            &config.output,
+            config.format,
+            config.get_write_options(),
+            object_info,
+            report,
+        );
+    }
+
     Ok(())
 }
+
+/// Dump each module made of some of the `files` independently in the store
+/// directory (see [`crate::input`] to get the files in some directories).
+///
+/// Contrary to [`several_files`], the files which don't have the same debug
+/// ids aren't merged.
+pub fn dump_modules(config: &Config, files: &[PathBuf]) -> common::Result<Report> {
+    if !matches!(config.output, Output::Store(_)) {
+        return Err(Error::Config(
+            "a store directory (and no output file) is required to dump several modules"
+                .to_string(),
+        )
+        .into());
+    }
+
+    let arch = config.get_arch()?;
+    let report = Mutex::new(ReportBuilder::default());
+    let modules = group_modules(files, arch, &report);
+    let mut debug_file_finder = config.get_debug_file_finder()?;
+    for f in files {
+        debug_file_finder.ignore(f);
+    }
+
+    // The modules are sent by value so their files are released once dumped.
+    let num_jobs = config.num_jobs.clamp(1, modules.len().max(1));
+    let (sender, receiver) = unbounded();
+    for module in modules {
+        sender.send(module).unwrap();
+    }
+    drop(sender);
+
+    let collect_options = config.get_collect_options(config.num_jobs / num_jobs)?;
+
+    thread::scope(|scope| {
+        for _ in 0..num_jobs {
+            let receiver = receiver.clone();
+            let collect_options = &collect_options;
+            let debug_file_finder = &debug_file_finder;
+            let report = &report;
+            scope.spawn(move || {
+                for (debug_ids, files) in receiver {
+                    let res = dump_module(
+                        config,
+                        &files,
+                        arch,
+                        collect_options,
+                        debug_file_finder,
+                        report,
+                    );
+                    let inputs = files.iter().map(|(f, _)| f.to_string_lossy().into_owned());
+                    let mut report = report.lock().unwrap();
+                    match res {
+                        Ok(()) => {
+                            for input in inputs {
+                                report.add_input(&input, debug_ids.clone());
+                            }
+                        }
+                        Err(e) => report.add_inputs_error(inputs, e),
+                    }
+                }
+            });
+        }
+    });
+
+    finish_report(config, report.into_inner().unwrap())
+}
@@ -0,0 +1,130 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The kind of failure behind a [`crate::common::Result`] error.
+///
+/// It can be retrieved with `err.downcast_ref::<Error>()` (or by looking for
+/// it in `err.chain()` when some context has been added).
+#[derive(Debug)]
+pub enum Error {
+    /// A file can't be read or written
+    Io { path: PathBuf, source: io::Error },
+    /// The format of a file isn't supported
+    UnknownFormat(String),
+    /// A file needed with the dumped one can't be found (e.g. the PDB file
+    /// for a PE file)
+    MissingCompanion(String),
+    /// A file can't be fetched from a symbol server
+    Network(String),
+    /// A file can't be parsed
+    Parse { file: String, message: String },
+    /// There are no CFI data and they're required
+    NoCfi(String),
+    /// The percentage of the functions with some CFI data is below the
+    /// required one
+    CfiCoverage {
+        file: String,
+        coverage: f64,
+        threshold: f64,
+    },
+    /// The configuration is invalid (e.g. a bad symbol server url)
+    Config(String),
+    /// Two dumps of the same files aren't the same
+    NotReproducible(String),
+}
+
+impl Error {
+    pub(crate) fn io<P: AsRef<Path>>(path: P, source: io::Error) -> Self {
+        Self::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    pub(crate) fn parse<E: Display>(file: impl Into<String>, e: E) -> Self {
+        Self::Parse {
+            file: file.into(),
+            message: e.to_string(),
+        }
+    }
+
+    /// The exit code of the dump_syms executable for this kind of failure
+    /// (1 is used for any other failure).
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Io { .. } => 2,
+            Self::UnknownFormat(_) => 3,
+            Self::MissingCompanion(_) => 4,
+            Self::Network(_) => 5,
+            Self::Parse { .. } => 6,
+            Self::NoCfi(_) | Self::CfiCoverage { .. } => 7,
+            Self::Config(_) => 8,
+            Self::NotReproducible(_) => 9,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Self::UnknownFormat(file) => write!(f, "Unknown file format: {file}"),
+            Self::MissingCompanion(message) => write!(f, "Missing file: {message}"),
+            Self::Network(message) => write!(f, "Network error: {message}"),
+            Self::Parse { file, message } => write!(f, "Unable to parse {file}: {message}"),
+            Self::NoCfi(file) => write!(f, "No CFI data in {file}"),
+            Self::CfiCoverage {
+                file,
+                coverage,
+                threshold,
+            } => write!(
+                f,
+                "Only {coverage:.1}% of the functions have some CFI data in {file} (at least {threshold}% required)"
+            ),
+            Self::Config(message) => write!(f, "Invalid configuration: {message}"),
+            Self::NotReproducible(message) => write!(f, "The output isn't reproducible: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Get the exit code for `err`, see [`Error::exit_code`].
+pub fn exit_code(err: &anyhow::Error) -> i32 {
+    err.chain()
+        .find_map(|e| e.downcast_ref::<Error>())
+        .map_or(1, Error::exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn test_exit_code() {
+        let err = anyhow::Error::from(Error::NoCfi("libxul.so".to_string()));
+        assert_eq!(err.to_string(), "No CFI data in libxul.so");
+        assert_eq!(exit_code(&err), 7);
+
+        let err = Err::<(), _>(Error::io("foo", io::ErrorKind::NotFound.into()))
+            .context("Unable to dump foo")
+            .unwrap_err();
+        assert_eq!(exit_code(&err), 2);
+
+        assert_eq!(exit_code(&anyhow::anyhow!("Something else")), 1);
+    }
+}
@@ -0,0 +1,198 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Map the source files in some local git checkouts to
+//! `git:host/repository:path:revision` (e.g.
+//! `git:github.com/mozilla/dump_syms:src/main.rs:abcdef`), the files which
+//! aren't tracked (e.g. the generated ones) being mapped to `digest/path`
+//! where digest is the SHA-512 of the file.
+
+use hashbrown::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::process::Command;
+
+use crate::common;
+use crate::mapping;
+
+#[derive(Debug)]
+pub(crate) struct Checkout {
+    root: PathBuf,
+    repository: String,
+    revision: String,
+    /// The tracked files, relative to the root
+    files: HashSet<String>,
+}
+
+fn git(dir: &Path, args: &[&str]) -> common::Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Cannot run git in {}: {e}", dir.display()))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git {} failed in {}: {}",
+        args.join(" "),
+        dir.display(),
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Get the host and the path of a remote url such as
+/// `https://github.com/mozilla/dump_syms.git` or
+/// `git@github.com:mozilla/dump_syms.git`.
+fn get_repository(url: &str) -> String {
+    let url = url.trim_end_matches('/');
+    let url = url.strip_suffix(".git").unwrap_or(url);
+    let url = match url.split_once("://") {
+        Some((_, url)) => url.to_string(),
+        // The scp-like syntax: [user@]host:path
+        None => url.replacen(':', "/", 1),
+    };
+    let (host, path) = url.split_once('/').unwrap_or((url.as_str(), ""));
+    let host = host.rsplit('@').next().unwrap_or(host);
+    let host = match host.split_once(':') {
+        Some((name, port)) if port.chars().all(|c| c.is_ascii_digit()) => name,
+        _ => host,
+    };
+    format!("{host}/{path}")
+}
+
+impl Checkout {
+    /// Get the revision, the remote and the tracked files of the checkout
+    /// containing `dir`.
+    pub(crate) fn new<P: AsRef<Path>>(dir: P) -> common::Result<Self> {
+        let dir = dir.as_ref();
+        let root = PathBuf::from(git(dir, &["rev-parse", "--show-toplevel"])?.trim());
+        let revision = git(&root, &["rev-parse", "HEAD"])?.trim().to_string();
+        let remote = match git(&root, &["remote", "get-url", "origin"]) {
+            Ok(url) => url,
+            Err(_) => {
+                let remotes = git(&root, &["remote"])?;
+                let remote = remotes.lines().next().ok_or_else(|| {
+                    anyhow::anyhow!("The git checkout {} has no remote", root.display())
+                })?;
+                git(&root, &["remote", "get-url", remote])?
+            }
+        };
+        let files = git(&root, &["ls-files", "-z"])?
+            .split('\0')
+            .filter(|f| !f.is_empty())
+            .map(ToOwned::to_owned)
+            .collect();
+
+        Ok(Self {
+            root,
+            repository: get_repository(remote.trim()),
+            revision,
+            files,
+        })
+    }
+
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Map a file in the checkout: `None` if it isn't in the checkout or if
+    /// it's an untracked file which doesn't exist anymore.
+    pub(crate) fn map(&self, file: &Path) -> common::Result<Option<String>> {
+        let relative = match file.strip_prefix(&self.root) {
+            Ok(relative) => relative,
+            Err(_) => return Ok(None),
+        };
+        let relative = relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(c) => Some(c.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        if self.files.contains(&relative) {
+            Ok(Some(format!(
+                "git:{}:{relative}:{}",
+                self.repository, self.revision
+            )))
+        } else if file.is_file() {
+            Ok(Some(format!(
+                "{}/{relative}",
+                mapping::get_digest(file, false)?
+            )))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_get_repository() {
+        for url in [
+            "https://github.com/mozilla/dump_syms.git",
+            "https://user@github.com/mozilla/dump_syms/",
+            "git@github.com:mozilla/dump_syms.git",
+            "ssh://git@github.com:22/mozilla/dump_syms",
+        ] {
+            assert_eq!(get_repository(url), "github.com/mozilla/dump_syms");
+        }
+    }
+
+    #[test]
+    fn test_checkout() {
+        let tmp_dir = Builder::new().prefix("checkout").tempdir().unwrap();
+        let dir = fs::canonicalize(tmp_dir.path()).unwrap();
+        fs::create_dir(dir.join("src")).unwrap();
+        fs::write(dir.join("src/main.cpp"), "int main() {}\n").unwrap();
+        for args in [
+            &["init", "-q"][..],
+            &["remote", "add", "origin", "git@github.com:mozilla/foo.git"],
+            &["add", "src/main.cpp"],
+            &[
+                "-c",
+                "user.name=foo",
+                "-c",
+                "user.email=foo@example.com",
+                "commit",
+                "-q",
+                "-m",
+                "Add main.cpp",
+            ],
+        ] {
+            git(&dir, args).unwrap();
+        }
+        let revision = git(&dir, &["rev-parse", "HEAD"]).unwrap();
+        fs::write(dir.join("src/generated.cpp"), "").unwrap();
+
+        let checkout = Checkout::new(dir.join("src")).unwrap();
+        assert_eq!(checkout.root(), dir);
+        assert_eq!(
+            checkout.map(&dir.join("src/main.cpp")).unwrap().unwrap(),
+            format!(
+                "git:github.com/mozilla/foo:src/main.cpp:{}",
+                revision.trim()
+            )
+        );
+        assert_eq!(
+            checkout.map(&dir.join("src/generated.cpp")).unwrap().unwrap(),
+            "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e/src/generated.cpp"
+        );
+        assert!(checkout
+            .map(&dir.join("src/missing.cpp"))
+            .unwrap()
+            .is_none());
+        assert!(checkout
+            .map(Path::new("/usr/include/stdio.h"))
+            .unwrap()
+            .is_none());
+    }
+}
@@ -2,7 +2,7 @@ use log::warn;
 use symbolic::common::{Language, Name};
 use symbolic::demangle::Demangle;
 
-use std::collections::HashMap;
+use std::collections::hash_map::{Entry, HashMap};
 
 use crate::common;
 
@@ -25,6 +25,30 @@ impl<'a> InlineOrigins<'a> {
         index
     }
 
+    /// Append the inline origins of `other`, collected from the functions
+    /// following the ones of `self`, and get their new ids.
+    pub fn merge(&mut self, other: InlineOrigins<'a>) -> Vec<u32> {
+        let mut names = vec![None; other.demangled_names.len()];
+        for (name, index) in other.index_for_mangled_name {
+            names[index as usize] = Some(name);
+        }
+
+        names
+            .into_iter()
+            .zip(other.demangled_names)
+            .map(
+                |(name, demangled_name)| match self.index_for_mangled_name.entry(name.unwrap()) {
+                    Entry::Occupied(e) => *e.get(),
+                    Entry::Vacant(e) => {
+                        let index = self.demangled_names.len() as u32;
+                        self.demangled_names.push(demangled_name);
+                        *e.insert(index)
+                    }
+                },
+            )
+            .collect()
+    }
+
     pub fn get_list(self) -> Vec<String> {
         self.demangled_names
     }
@@ -58,12 +82,9 @@ impl<'a> InlineOrigins<'a> {
 /// Adds all inline origins from `right` into `left`, and returns
 /// a Vec which maps the IDs from right into the IDs in left.
 pub fn merge_inline_origins(left: &mut Vec<String>, right: Vec<String>) -> Vec<u32> {
-    // Merging is a bit of a silly business. This is used when we feed both a binary
-    // and a debug file into dump_syms. In this case, the debug file has all the
-    // information about inlines, and the binary file has none of the information about
-    // inlines.
-    // So we only need to care about the case where either `right` or `left` is empty.
-    // The other case is a theoretical possibility but doesn't need to be good or fast.
+    // This is used when we feed both a binary and a debug file into dump_syms,
+    // the binary file having none of the information about inlines, or several
+    // partial debug files which can have the same inline origins.
     if right.is_empty() {
         return Vec::new();
     }
@@ -72,18 +93,72 @@ pub fn merge_inline_origins(left: &mut Vec<String>, right: Vec<String>) -> Vec<u
         *left = right;
         return (0..count).collect();
     }
-    // Just append the two vecs to each other. We don't bother with deduplication.
-    let count = right.len() as u32;
-    let offset = left.len() as u32;
-    left.extend(right);
-    (offset..(offset + count)).collect()
+    // The names already in `left` keep their id and the other ones are
+    // appended in order.
+    let mut ids: HashMap<String, u32> = HashMap::with_capacity(left.len() + right.len());
+    for (id, name) in left.iter().enumerate() {
+        ids.entry(name.clone()).or_insert(id as u32);
+    }
+    right
+        .into_iter()
+        .map(|name| match ids.entry(name) {
+            Entry::Occupied(e) => *e.get(),
+            Entry::Vacant(e) => {
+                let id = left.len() as u32;
+                left.push(e.key().clone());
+                *e.insert(id)
+            }
+        })
+        .collect()
+}
+
+/// Remove the inline origins which aren't `used` and get the new ids (the
+/// removed ones are mapped to `u32::MAX`).
+pub fn prune_inline_origins(inline_origins: &mut Vec<String>, used: &[bool]) -> Vec<u32> {
+    let mut remapping = Vec::with_capacity(inline_origins.len());
+    let mut id = 0;
+    for &used in used {
+        if used {
+            remapping.push(id);
+            id += 1;
+        } else {
+            remapping.push(u32::MAX);
+        }
+    }
+    let mut used = used.iter();
+    inline_origins.retain(|_| *used.next().unwrap());
+    remapping
 }
 
 #[cfg(test)]
 mod test {
     use symbolic::common::{Language, Name, NameMangling};
 
-    use super::InlineOrigins;
+    use super::{merge_inline_origins, prune_inline_origins, InlineOrigins};
+
+    fn to_strings(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_merge_inline_origins() {
+        let mut left = to_strings(&["foo()", "bar()"]);
+        let remapping = merge_inline_origins(&mut left, to_strings(&["baz()", "foo()", "baz()"]));
+        assert_eq!(left, to_strings(&["foo()", "bar()", "baz()"]));
+        assert_eq!(remapping, vec![2, 0, 2]);
+
+        let mut empty = Vec::new();
+        assert_eq!(
+            merge_inline_origins(&mut empty, left.clone()),
+            vec![0, 1, 2]
+        );
+        assert_eq!(empty, left);
+        assert!(merge_inline_origins(&mut left, Vec::new()).is_empty());
+
+        let remapping = prune_inline_origins(&mut left, &[false, true, true]);
+        assert_eq!(left, to_strings(&["bar()", "baz()"]));
+        assert_eq!(remapping, vec![u32::MAX, 0, 1]);
+    }
 
     #[test]
     fn test_demangle() {
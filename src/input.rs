@@ -0,0 +1,309 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Find the files to dump in directories and manifest files.
+
+use log::info;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use symbolic::debuginfo::{peek, FileFormat};
+
+use crate::common;
+use crate::error::Error;
+
+/// The size of the header read to get the format of a file.
+const HEADER_SIZE: u64 = 4096;
+
+/// Select the files with glob patterns.
+///
+/// The patterns support `*`, `**`, `?` and `[...]`. A pattern without any `/`
+/// is matched against the file name, and against the path relative to the
+/// walked directory otherwise (e.g. `*.so` or `lib/**/*.so`).
+#[derive(Clone, Debug, Default)]
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+#[derive(Clone, Debug)]
+struct Glob {
+    re: Regex,
+    full_path: bool,
+}
+
+impl Glob {
+    fn new(pattern: &str) -> common::Result<Self> {
+        let mut re = String::from("^");
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        re.push_str("(?:.*/)?");
+                    } else {
+                        re.push_str(".*");
+                    }
+                }
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                '[' => {
+                    re.push('[');
+                    if chars.peek() == Some(&'!') {
+                        chars.next();
+                        re.push('^');
+                    }
+                    for c in chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                        if c == '\\' || c == '[' {
+                            re.push('\\');
+                        }
+                        re.push(c);
+                    }
+                    re.push(']');
+                }
+                c => re.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+            }
+        }
+        re.push('$');
+
+        let re = Regex::new(&re)
+            .map_err(|e| Error::Config(format!("Invalid glob pattern {pattern}: {e}")))?;
+        Ok(Self {
+            re,
+            full_path: pattern.contains('/'),
+        })
+    }
+
+    fn is_match(&self, relative_path: &str) -> bool {
+        if self.full_path {
+            self.re.is_match(relative_path)
+        } else {
+            let name = relative_path.rsplit('/').next().unwrap_or(relative_path);
+            self.re.is_match(name)
+        }
+    }
+}
+
+impl Filter {
+    /// Create a filter which selects the files matching one of the `include`
+    /// patterns (or all of them if there are none) and none of the `exclude`
+    /// ones.
+    pub fn new(include: &[&str], exclude: &[&str]) -> common::Result<Self> {
+        Ok(Self {
+            include: include
+                .iter()
+                .map(|p| Glob::new(p))
+                .collect::<common::Result<_>>()?,
+            exclude: exclude
+                .iter()
+                .map(|p| Glob::new(p))
+                .collect::<common::Result<_>>()?,
+        })
+    }
+
+    fn is_excluded(&self, relative_path: &str) -> bool {
+        self.exclude.iter().any(|g| g.is_match(relative_path))
+    }
+
+    fn is_included(&self, relative_path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.is_match(relative_path)))
+            && !self.is_excluded(relative_path)
+    }
+}
+
+/// The magic number of the cabinet files such as `xul.pd_`.
+const CAB_MAGIC: &[u8] = b"MSCF";
+
+/// Check if the file at `path` can be dumped, using its first bytes only
+/// (the cabinet files such as `xul.pd_` are kept without being decompressed).
+fn is_dumpable(path: &Path) -> bool {
+    let mut buf = Vec::new();
+    let read = File::open(path).and_then(|f| f.take(HEADER_SIZE).read_to_end(&mut buf));
+    if read.is_err() {
+        return false;
+    }
+
+    if path.to_string_lossy().ends_with('_') && buf.starts_with(CAB_MAGIC) {
+        return true;
+    }
+
+    matches!(
+        peek(&buf, true /* check for fat binary */),
+        FileFormat::Elf | FileFormat::MachO | FileFormat::Pe | FileFormat::Pdb
+    )
+}
+
+fn walk(
+    dir: &Path,
+    relative_dir: &str,
+    filter: &Filter,
+    visited: &mut HashSet<PathBuf>,
+    files: &mut Vec<PathBuf>,
+) -> common::Result<()> {
+    // A symlink can point to one of its parents.
+    let canonical_dir = fs::canonicalize(dir).map_err(|e| Error::io(dir, e))?;
+    if !visited.insert(canonical_dir) {
+        info!("Skip {}: already walked", dir.display());
+        return Ok(());
+    }
+
+    let mut entries = fs::read_dir(dir)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(|e| Error::io(dir, e))?;
+    entries.sort_by_key(|e| e.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let name = entry.file_name();
+        let relative_path = if relative_dir.is_empty() {
+            name.to_string_lossy().into_owned()
+        } else {
+            format!("{relative_dir}/{}", name.to_string_lossy())
+        };
+
+        // Follow the symlinks as find -L does.
+        if path.is_dir() {
+            if !filter.is_excluded(&relative_path) {
+                walk(&path, &relative_path, filter, visited, files)?;
+            }
+        } else if path.is_file() && filter.is_included(&relative_path) {
+            if is_dumpable(&path) {
+                files.push(path);
+            } else {
+                info!("Skip {}: unknown file format", path.display());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Get the files which can be dumped in `dir` and its subdirectories.
+pub fn walk_dir<P: AsRef<Path>>(dir: P, filter: &Filter) -> common::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    walk(dir.as_ref(), "", filter, &mut HashSet::new(), &mut files)?;
+    Ok(files)
+}
+
+/// Read a manifest file: one file or directory per line, the empty lines
+/// and the ones starting with `#` are ignored.
+///
+/// The relative paths are relative to the directory of the manifest.
+pub fn read_manifest<P: AsRef<Path>>(manifest: P) -> common::Result<Vec<PathBuf>> {
+    let manifest = manifest.as_ref();
+    let content = fs::read_to_string(manifest).map_err(|e| Error::io(manifest, e))?;
+    let dir = manifest.parent().unwrap_or_else(|| Path::new(""));
+
+    Ok(content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| dir.join(line))
+        .collect())
+}
+
+/// Get the files to dump: the directories in `inputs` are walked and the
+/// files are kept as they are.
+pub fn collect_files(inputs: &[PathBuf], filter: &Filter) -> common::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            files.extend(walk_dir(input, filter)?);
+        } else if input.is_file() {
+            files.push(input.clone());
+        } else {
+            return Err(Error::io(input, std::io::ErrorKind::NotFound.into()).into());
+        }
+    }
+
+    // A file can be in several directories or listed twice.
+    let mut seen = HashSet::with_capacity(files.len());
+    files.retain(|f| seen.insert(fs::canonicalize(f).unwrap_or_else(|_| f.clone())));
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::Builder;
+
+    #[test]
+    fn test_glob() {
+        let glob = |p: &str, path: &str| Glob::new(p).unwrap().is_match(path);
+
+        assert!(glob("*.so", "lib/libxul.so"));
+        assert!(!glob("*.so", "lib/libxul.so.dbg"));
+        assert!(glob("lib*.so*", "libxul.so.6"));
+        assert!(glob("lib/*.so", "lib/libxul.so"));
+        assert!(!glob("lib/*.so", "lib/gtk/libxul.so"));
+        assert!(glob("lib/**/*.so", "lib/libxul.so"));
+        assert!(glob("lib/**/*.so", "lib/gtk/3/libxul.so"));
+        assert!(glob("**/test?/*", "a/b/test1/c"));
+        assert!(glob("*.[ep]db", "xul.pdb"));
+        assert!(!glob("*.[!p]db", "xul.pdb"));
+        assert!(glob("a+b(1).dll", "a+b(1).dll"));
+        assert!(Glob::new("[z-a]").is_err());
+    }
+
+    #[test]
+    fn test_walk_dir() {
+        let tmp_dir = Builder::new().prefix("walk").tempdir().unwrap();
+        let dir = tmp_dir.path();
+        for (src, dest) in [
+            ("linux/basic.full", "bin/basic.full"),
+            ("linux/basic.dbg", "bin/debug/basic.dbg"),
+            ("linux/basic.cpp", "src/basic.cpp"),
+            ("windows/basic32.pd_", "win/basic32.pd_"),
+            ("linux/basic.cpp", "win/basic.pd_"),
+            ("windows/basic32.dll", "win/basic32.dll"),
+            ("windows/basic32.sym", "win/basic32.sym"),
+        ] {
+            let dest = dir.join(dest);
+            fs::create_dir_all(dest.parent().unwrap()).unwrap();
+            fs::copy(Path::new("./test_data").join(src), dest).unwrap();
+        }
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir, dir.join("bin/debug/loop")).unwrap();
+        let names = |filter: &Filter| -> Vec<String> {
+            walk_dir(dir, filter)
+                .unwrap()
+                .iter()
+                .map(|p| p.strip_prefix(dir).unwrap().to_string_lossy().into_owned())
+                .collect()
+        };
+
+        assert_eq!(
+            names(&Filter::default()),
+            [
+                "bin/basic.full",
+                "bin/debug/basic.dbg",
+                "win/basic32.dll",
+                "win/basic32.pd_"
+            ]
+        );
+        assert_eq!(
+            names(&Filter::new(&["*.dll", "bin/**"], &["debug"]).unwrap()),
+            ["bin/basic.full", "win/basic32.dll"]
+        );
+
+        let manifest = dir.join("manifest.txt");
+        fs::write(&manifest, "# The Windows files\nwin\n\nbin/basic.full\n").unwrap();
+        let inputs = read_manifest(&manifest).unwrap();
+        assert_eq!(inputs, [dir.join("win"), dir.join("bin/basic.full")]);
+        let files = collect_files(&inputs, &Filter::new(&[], &["*.pd_"]).unwrap()).unwrap();
+        assert_eq!(
+            files,
+            [dir.join("win/basic32.dll"), dir.join("bin/basic.full")]
+        );
+    }
+}
@@ -0,0 +1,337 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! JSON serialization of an [`ObjectInfo`].
+//!
+//! The JSON output carries the same data as the Breakpad text format, but in a
+//! structured form which doesn't require a dedicated parser:
+//!
+//! ```text
+//! {
+//!   "module": {
+//!     "os": "Linux",
+//!     "arch": "x86_64",
+//!     "debug_id": "20AD60B0B4C68177552708AA192E77390",
+//!     "name": "basic.full",
+//!     "code_id": "B060AD20C6B47781552708AA192E7739FAC7C84A", // or null
+//!     "code_file": null                                      // PE name, if any
+//!   },
+//!   "info": ["GENERATOR mozilla/dump_syms 2.3.7"],
+//!   "files": ["/path/to/basic.cpp"],                         // FILE id == index
+//!   "inline_origins": ["inline_1(int)"],                     // INLINE_ORIGIN id == index
+//!   "functions": [{
+//!     "address": 4389, "size": 38, "parameter_size": 0,
+//!     "name": "inline_1(int)", "multiple": false,
+//!     "lines": [{ "address": 4389, "size": 7, "line": 2, "file": 0 }],
+//!     "inlines": [{
+//!       "depth": 0, "call_line": 12, "call_file": 0, "origin": 0,
+//!       "ranges": [{ "address": 4400, "size": 10 }]
+//!     }]
+//!   }],
+//!   "publics": [{ "address": 4096, "parameter_size": 0, "name": "_init", "multiple": false }],
+//!   "cfi": [{
+//!     "address": 4160, "size": 43, "init": ".cfa: $rsp 8 +",
+//!     "deltas": [{ "address": 4166, "rules": ".cfa: $rsp 24 +" }]
+//!   }],
+//!   "stack_win": ["4 6be0 18 4 0 4 0 0 0 1 $T0 .raSearch = ..."]
+//! }
+//! ```
+//!
+//! All the addresses are relative to the module base. The `stack_win` records
+//! are kept as in the Breakpad format (without the `STACK WIN` prefix). The
+//! `cfi` and `stack_win` records are serialized while the STACK records are
+//! read, so they aren't loaded in memory when they're in a temporary file.
+
+use serde::ser::{Error, SerializeSeq, Serializer};
+use serde::Serialize;
+
+use crate::line::Lines;
+use crate::object_info::ObjectInfo;
+use crate::stack::Stack;
+use crate::symbol::Symbol;
+
+#[derive(Debug, Serialize)]
+pub struct JsonModule<'a> {
+    pub module: JsonHeader<'a>,
+    pub info: Vec<&'a str>,
+    pub files: &'a [String],
+    pub inline_origins: Vec<&'a str>,
+    pub functions: Vec<JsonFunction<'a>>,
+    pub publics: Vec<JsonPublic<'a>>,
+    pub cfi: JsonCfiRecords<'a>,
+    pub stack_win: JsonStackWinRecords<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonHeader<'a> {
+    pub os: String,
+    pub arch: &'a str,
+    pub debug_id: &'a str,
+    pub name: &'a str,
+    pub code_id: Option<&'a str>,
+    pub code_file: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonFunction<'a> {
+    pub address: u32,
+    pub size: u32,
+    pub parameter_size: u32,
+    pub name: &'a str,
+    pub multiple: bool,
+    pub lines: Vec<JsonLine>,
+    pub inlines: Vec<JsonInline>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonLine {
+    pub address: u32,
+    pub size: u32,
+    pub line: u32,
+    pub file: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonInline {
+    pub depth: u32,
+    pub call_line: u32,
+    pub call_file: u32,
+    pub origin: u32,
+    pub ranges: Vec<JsonRange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonRange {
+    pub address: u32,
+    pub size: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonPublic<'a> {
+    pub address: u32,
+    pub parameter_size: u32,
+    pub name: &'a str,
+    pub multiple: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonCfi {
+    pub address: u64,
+    pub size: u64,
+    pub init: String,
+    pub deltas: Vec<JsonCfiDelta>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonCfiDelta {
+    pub address: u64,
+    pub rules: String,
+}
+
+/// The STACK CFI records of a module, serialized as [`JsonCfi`] entries.
+#[derive(Debug)]
+pub struct JsonCfiRecords<'a>(&'a Stack);
+
+/// The STACK WIN records of a module, serialized as strings.
+#[derive(Debug)]
+pub struct JsonStackWinRecords<'a>(&'a Stack);
+
+fn get_lines(lines: &Lines) -> Vec<JsonLine> {
+    lines
+        .lines
+        .iter()
+        .map(|line| JsonLine {
+            address: line.rva,
+            size: line.len,
+            line: line.num,
+            file: line.file_id,
+        })
+        .collect()
+}
+
+fn get_inlines(lines: &Lines) -> Vec<JsonInline> {
+    // Same order as in the Breakpad output.
+    let mut inlines: Vec<_> = lines.inlines.iter().collect();
+    inlines.sort_by_key(|(site, ranges)| (ranges.first().unwrap().rva, site.call_depth));
+    inlines
+        .into_iter()
+        .map(|(site, ranges)| JsonInline {
+            depth: site.call_depth,
+            call_line: site.call_line_number,
+            call_file: site.call_file_id,
+            origin: site.inline_origin_id,
+            ranges: ranges
+                .iter()
+                .map(|r| JsonRange {
+                    address: r.rva,
+                    size: r.len,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+fn get_function(sym: &Symbol) -> JsonFunction<'_> {
+    JsonFunction {
+        address: sym.rva,
+        size: sym.len,
+        parameter_size: sym.parameter_size,
+        name: &sym.name,
+        multiple: sym.is_multiple,
+        lines: get_lines(&sym.source),
+        inlines: get_inlines(&sym.source),
+    }
+}
+
+fn get_public(sym: &Symbol) -> JsonPublic<'_> {
+    JsonPublic {
+        address: sym.rva,
+        parameter_size: sym.parameter_size,
+        name: &sym.name,
+        multiple: sym.is_multiple,
+    }
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+/// Get the entry of a STACK CFI INIT record (without its prefix).
+fn get_cfi(rest: &str) -> Option<JsonCfi> {
+    let mut toks = rest.splitn(3, ' ');
+    let address = toks.next().and_then(parse_hex)?;
+    let size = toks.next().and_then(parse_hex)?;
+    Some(JsonCfi {
+        address,
+        size,
+        init: toks.next().unwrap_or_default().to_string(),
+        deltas: Vec::new(),
+    })
+}
+
+impl Serialize for JsonCfiRecords<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        // An entry is serialized once all its deltas have been read.
+        let mut current: Option<JsonCfi> = None;
+        for line in self.0.lines().map_err(S::Error::custom)? {
+            let line = line.map_err(S::Error::custom)?;
+            if let Some(rest) = line.strip_prefix("STACK CFI INIT ") {
+                if let Some(previous) = get_cfi(rest).and_then(|cfi| current.replace(cfi)) {
+                    seq.serialize_element(&previous)?;
+                }
+            } else if let Some(rest) = line.strip_prefix("STACK CFI ") {
+                let (address, rules) = rest.split_once(' ').unwrap_or((rest, ""));
+                if let (Some(address), Some(cfi)) = (parse_hex(address), current.as_mut()) {
+                    cfi.deltas.push(JsonCfiDelta {
+                        address,
+                        rules: rules.to_string(),
+                    });
+                }
+            }
+        }
+        if let Some(last) = current {
+            seq.serialize_element(&last)?;
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for JsonStackWinRecords<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(None)?;
+        for line in self.0.lines().map_err(S::Error::custom)? {
+            let line = line.map_err(S::Error::custom)?;
+            if let Some(rest) = line.strip_prefix("STACK WIN ") {
+                seq.serialize_element(rest)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl<'a> JsonModule<'a> {
+    pub(crate) fn new(info: &'a ObjectInfo) -> Self {
+        let (functions, publics): (Vec<_>, Vec<_>) =
+            info.symbols.values().partition(|sym| !sym.is_public);
+
+        JsonModule {
+            module: JsonHeader {
+                os: info.platform.to_string(),
+                arch: info.cpu,
+                debug_id: &info.debug_id,
+                name: &info.file_name,
+                code_id: info.code_id.as_deref(),
+                code_file: info.pe_name.as_deref(),
+            },
+            info: info.extra_info().map(String::as_str).collect(),
+            files: info.files.get_mapping(),
+            inline_origins: info
+                .inline_origins
+                .iter()
+                .map(|name| {
+                    if name.trim().is_empty() {
+                        "<name omitted>"
+                    } else {
+                        name.as_str()
+                    }
+                })
+                .collect(),
+            functions: functions.into_iter().map(get_function).collect(),
+            publics: publics.into_iter().map(get_public).collect(),
+            cfi: JsonCfiRecords(&info.stack),
+            stack_win: JsonStackWinRecords(&info.stack),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stack::StackWriter;
+    use std::io::Write;
+
+    #[test]
+    fn test_stack_records() {
+        let records = "STACK CFI INIT 1020 10 .cfa: $rsp 16 + .ra: .cfa -8 + ^\n\
+                       STACK CFI 1026 .cfa: $rsp 24 +\n\
+                       STACK CFI 1030 .cfa: $rsp 8 +\n\
+                       STACK CFI INIT 1040 zz .cfa: $rsp 8 +\n\
+                       STACK CFI INIT 1050 4 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n\
+                       STACK WIN 4 6be0 18 4 0 4 0 0 0 1 $T0 .raSearch = $eip $T0 ^ =\n";
+        let mut writer = StackWriter::with_max_buffered_size(16);
+        writer.write_all(records.as_bytes()).unwrap();
+        let spilled = writer.finish().unwrap();
+
+        for stack in [Stack::from(records.to_string()), spilled] {
+            let cfi = serde_json::to_value(JsonCfiRecords(&stack)).unwrap();
+            assert_eq!(
+                cfi,
+                serde_json::json!([
+                    {
+                        "address": 0x1020,
+                        "size": 0x10,
+                        "init": ".cfa: $rsp 16 + .ra: .cfa -8 + ^",
+                        "deltas": [
+                            { "address": 0x1026, "rules": ".cfa: $rsp 24 +" },
+                            { "address": 0x1030, "rules": ".cfa: $rsp 8 +" },
+                        ],
+                    },
+                    {
+                        "address": 0x1050,
+                        "size": 4,
+                        "init": ".cfa: $rsp 8 + .ra: .cfa -8 + ^",
+                        "deltas": [],
+                    },
+                ])
+            );
+            let win = serde_json::to_value(JsonStackWinRecords(&stack)).unwrap();
+            assert_eq!(
+                win,
+                serde_json::json!(["4 6be0 18 4 0 4 0 0 0 1 $T0 .raSearch = $eip $T0 ^ ="])
+            );
+        }
+    }
+}
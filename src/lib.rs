@@ -3,19 +3,36 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+mod breakpad;
+pub mod buffer;
 #[cfg(feature = "http")]
 pub mod cache;
+pub mod cfi_coverage;
 pub mod collector;
 pub mod common;
+mod compact_unwind;
+pub mod diff;
 pub mod dumper;
+pub mod error;
+mod git;
 pub mod inline_origins;
+pub mod input;
+pub mod json;
 mod line;
 pub mod linux;
+pub mod lookup;
 pub mod mac;
 pub mod mapping;
 pub mod object_info;
 pub mod platform;
+pub mod report;
+pub mod reproducible;
 mod source;
+mod split_dwarf;
+mod stack;
 mod symbol;
+#[cfg(feature = "http")]
+pub mod upload;
 pub mod utils;
+pub mod verify;
 pub mod windows;
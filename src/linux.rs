@@ -3,31 +3,337 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::sync::Arc;
+use log::{info, warn};
+use std::fs;
+use std::path::{Path, PathBuf};
+use symbolic::debuginfo::elf::ElfObject;
 use symbolic::debuginfo::Object;
 
+#[cfg(feature = "http")]
+use crate::cache::{self, DebuginfodArtifact, SymbolServer};
 use crate::common;
-use crate::mapping::PathMappings;
-use crate::object_info::ObjectInfo;
+use crate::error::Error;
+use crate::object_info::{CollectOptions, ObjectInfo};
 use crate::platform::Platform;
+use crate::split_dwarf::SplitDwarf;
+use crate::utils::{self, FileData};
+
+/// The default directory where the separate debug files are installed.
+pub const DEFAULT_DEBUG_DIR: &str = "/usr/lib/debug";
+
+/// Find the separate debug file of a stripped ELF file.
+///
+/// As gdb does, the debug file is first looked up using the build id in
+/// `<dir>/.build-id/xx/yyyy.debug` and then using the `.gnu_debuglink`
+/// section in the directory of the ELF file, in its `.debug` subdirectory
+/// and in `<dir>/<absolute directory of the ELF file>`. Finally, it's fetched
+/// from the debuginfod servers, if any.
+#[derive(Clone, Debug)]
+pub struct DebugFileFinder {
+    dirs: Vec<PathBuf>,
+    // The files which mustn't be used as debug files.
+    ignored: Vec<PathBuf>,
+    #[cfg(feature = "http")]
+    servers: Option<Vec<SymbolServer>>,
+}
+
+impl Default for DebugFileFinder {
+    fn default() -> Self {
+        Self::new([DEFAULT_DEBUG_DIR])
+    }
+}
+
+impl DebugFileFinder {
+    /// Create a finder using the given global debug directories.
+    pub fn new<P: Into<PathBuf>>(dirs: impl IntoIterator<Item = P>) -> Self {
+        Self {
+            dirs: dirs.into_iter().map(Into::into).collect(),
+            ignored: Vec::new(),
+            #[cfg(feature = "http")]
+            servers: None,
+        }
+    }
+
+    /// Fetch the debug files from the debuginfod servers in `servers`.
+    #[cfg(feature = "http")]
+    pub fn with_symbol_servers(mut self, servers: Option<Vec<SymbolServer>>) -> Self {
+        self.servers = servers;
+        self
+    }
+
+    /// Never use the given file as a debug file (e.g. because it's already
+    /// dumped with the ELF file).
+    pub fn ignore<P: AsRef<Path>>(&mut self, path: P) {
+        if let Ok(path) = fs::canonicalize(path) {
+            self.ignored.push(path);
+        }
+    }
+
+    fn read_candidate(&self, path: &Path) -> Option<FileData> {
+        let path = fs::canonicalize(path).ok()?;
+        if !path.is_file() || self.ignored.contains(&path) {
+            return None;
+        }
+        utils::read_file(path).ok()
+    }
+
+    fn find_by_build_id(&self, elf: &ElfObject) -> Option<(PathBuf, FileData)> {
+        let build_id = elf.code_id()?;
+        let build_id = build_id.as_str();
+        if build_id.len() <= 2 {
+            return None;
+        }
+
+        self.dirs.iter().find_map(|dir| {
+            let path = dir
+                .join(".build-id")
+                .join(&build_id[..2])
+                .join(format!("{}.debug", &build_id[2..]));
+            let buf = self.read_candidate(&path)?;
+            check_debug_id(elf, path, buf)
+        })
+    }
+
+    #[cfg(feature = "http")]
+    fn fetch_by_build_id(&self, elf: &ElfObject) -> Option<(PathBuf, FileData)> {
+        let build_id = elf.code_id()?;
+        let buf = cache::search_debuginfod(
+            build_id.as_str(),
+            DebuginfodArtifact::DebugInfo,
+            self.servers.as_ref(),
+        )
+        .unwrap_or_else(|e| {
+            log::error!("debuginfod: {e}");
+            None
+        })?;
+        check_debug_id(elf, PathBuf::from(format!("{build_id}.debug")), buf)
+    }
+
+    #[cfg(not(feature = "http"))]
+    fn fetch_by_build_id(&self, _elf: &ElfObject) -> Option<(PathBuf, FileData)> {
+        None
+    }
+
+    fn find_by_debug_link(&self, elf: &ElfObject, path: &Path) -> Option<(PathBuf, FileData)> {
+        let link = elf.debug_link().ok()??;
+        let name = link.filename().to_str().ok()?;
+        let path = fs::canonicalize(path).ok()?;
+        let dir = path.parent()?;
+
+        let mut candidates = vec![dir.join(name), dir.join(".debug").join(name)];
+        let relative_dir = dir.strip_prefix("/").unwrap_or(dir);
+        candidates.extend(self.dirs.iter().map(|d| d.join(relative_dir).join(name)));
+
+        candidates.into_iter().find_map(|candidate| {
+            if fs::canonicalize(&candidate).ok()? == path {
+                return None;
+            }
+            let buf = self.read_candidate(&candidate)?;
+            if crc32fast::hash(&buf) == link.crc() {
+                Some((candidate, buf))
+            } else {
+                warn!("Debug file {} has a bad CRC", candidate.display());
+                None
+            }
+        })
+    }
+
+    /// Get the path and the content of the debug file for the ELF file at
+    /// `path`, if the ELF file doesn't contain any debug information.
+    pub fn find(&self, elf: &ElfObject, path: &Path) -> Option<(PathBuf, FileData)> {
+        if elf.has_debug_info() {
+            return None;
+        }
+        self.find_by_build_id(elf)
+            .or_else(|| self.find_by_debug_link(elf, path))
+            .or_else(|| self.fetch_by_build_id(elf))
+    }
+}
+
+fn check_debug_id(elf: &ElfObject, path: PathBuf, buf: FileData) -> Option<(PathBuf, FileData)> {
+    let same_id = Object::parse(&buf).is_ok_and(|o| o.debug_id() == elf.debug_id());
+    if same_id {
+        Some((path, buf))
+    } else {
+        warn!("Debug file {} doesn't match the build id", path.display());
+        None
+    }
+}
 
 impl ObjectInfo {
     pub fn from_elf(
         buf: &[u8],
         file_name: &str,
         platform: Platform,
-        mapping: Option<Arc<PathMappings>>,
-        collect_inlines: bool,
+        options: &CollectOptions,
+    ) -> common::Result<Self> {
+        let o = Object::parse(buf).map_err(|e| Error::parse(file_name, e))?;
+        let split_dwarf = match &o {
+            Object::Elf(elf) => SplitDwarf::load(elf, None),
+            _ => None,
+        };
+        Self::from_object_and_split_dwarf(
+            &o,
+            file_name,
+            None,
+            None,
+            platform,
+            options,
+            split_dwarf.as_ref(),
+        )
+    }
+
+    /// Same as [`ObjectInfo::from_elf`] but if the ELF file at `path` is
+    /// stripped, its separate debug file is looked up with `finder` and
+    /// merged with it.
+    pub fn from_elf_with_debug_file(
+        buf: &[u8],
+        path: &Path,
+        file_name: &str,
+        platform: Platform,
+        options: &CollectOptions,
+        finder: &DebugFileFinder,
     ) -> common::Result<Self> {
-        let o = Object::parse(buf)?;
-        Self::from_object(
+        let o = Object::parse(buf).map_err(|e| Error::parse(file_name, e))?;
+        let (debug_file, split_dwarf) = match &o {
+            Object::Elf(elf) => (finder.find(elf, path), SplitDwarf::load(elf, Some(path))),
+            _ => (None, None),
+        };
+        let object_info = Self::from_object_and_split_dwarf(
             &o,
             file_name,
             None,
             None,
             platform,
-            mapping,
-            collect_inlines,
+            options,
+            split_dwarf.as_ref(),
+        )?;
+
+        if let Some((debug_path, debug_buf)) = debug_file {
+            info!("Use the debug file {}", debug_path.display());
+            let debug_info = Self::from_elf(
+                &debug_buf,
+                &utils::get_filename(&debug_path),
+                platform,
+                options,
+            )?;
+            return Self::merge(object_info, debug_info);
+        }
+
+        Ok(object_info)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_info::Type;
+    use tempfile::Builder;
+
+    fn from_elf(path: &Path, finder: &DebugFileFinder) -> ObjectInfo {
+        let buf = fs::read(path).unwrap();
+        ObjectInfo::from_elf_with_debug_file(
+            &buf,
+            path,
+            &utils::get_filename(path),
+            Platform::Linux,
+            &CollectOptions {
+                collect_inlines: true,
+                ..Default::default()
+            },
+            finder,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_build_id() {
+        let tmp_dir = Builder::new().prefix("build_id").tempdir().unwrap();
+        let stripped = Path::new("./test_data/linux/basic.stripped");
+        let finder = DebugFileFinder::new([tmp_dir.path()]);
+
+        let info = from_elf(stripped, &finder);
+        assert_eq!(info.bin_type, Type::Stripped);
+
+        let dir = tmp_dir.path().join(".build-id").join("b0");
+        fs::create_dir_all(&dir).unwrap();
+        fs::copy(
+            "./test_data/linux/basic.dbg",
+            dir.join("60ad20c6b47781552708aa192e7739fac7c84a.debug"),
+        )
+        .unwrap();
+
+        let info = from_elf(stripped, &finder);
+        assert_eq!(info.bin_type, Type::DebugInfo);
+        assert!(info.symbols.values().any(|sym| !sym.is_public));
+    }
+
+    #[test]
+    fn test_debug_link() {
+        let debuglink = Path::new("./test_data/linux/basic.debuglink");
+        let finder = DebugFileFinder::new(Vec::<PathBuf>::new());
+        let info = from_elf(debuglink, &finder);
+        assert_eq!(info.bin_type, Type::DebugInfo);
+
+        let mut finder = DebugFileFinder::new(Vec::<PathBuf>::new());
+        finder.ignore("./test_data/linux/basic.dbg");
+        let info = from_elf(debuglink, &finder);
+        assert_eq!(info.bin_type, Type::Stripped);
+    }
+
+    #[test]
+    fn test_debug_link_bad_crc() {
+        let tmp_dir = Builder::new().prefix("debug_link").tempdir().unwrap();
+        let debuglink = tmp_dir.path().join("basic.debuglink");
+        fs::copy("./test_data/linux/basic.debuglink", &debuglink).unwrap();
+        fs::create_dir(tmp_dir.path().join(".debug")).unwrap();
+        fs::copy(
+            "./test_data/linux/basic.full",
+            tmp_dir.path().join(".debug").join("basic.dbg"),
+        )
+        .unwrap();
+
+        let info = from_elf(&debuglink, &DebugFileFinder::new(Vec::<PathBuf>::new()));
+        assert_eq!(info.bin_type, Type::Stripped);
+    }
+
+    #[test]
+    fn test_split_dwarf_dwo() {
+        let tmp_dir = Builder::new().prefix("split_dwarf").tempdir().unwrap();
+        let split = tmp_dir.path().join("basic.split");
+        fs::copy("./test_data/linux/basic.split", &split).unwrap();
+        fs::copy(
+            "./test_data/linux/basic.split-basic.dwo",
+            tmp_dir.path().join("basic.split-basic.dwo"),
+        )
+        .unwrap();
+
+        let info = from_elf(&split, &DebugFileFinder::new(Vec::<PathBuf>::new()));
+        let foo = info
+            .symbols
+            .values()
+            .find(|sym| sym.name == "foo(int)")
+            .unwrap();
+        assert!(!foo.is_public);
+        assert!(!foo.source.lines.is_empty());
+        assert_eq!(foo.source.inlines.len(), 7);
+        assert_eq!(info.inline_origins.len(), 4);
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let buf = fs::read("./test_data/linux/basic.full").unwrap();
+        let err = ObjectInfo::from_elf(
+            &buf[..64],
+            "basic.full",
+            Platform::Linux,
+            &CollectOptions::default(),
         )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<Error>(),
+            Some(Error::Parse { file, .. }) if file == "basic.full"
+        ));
+        assert_eq!(crate::error::exit_code(&err), 6);
     }
 }
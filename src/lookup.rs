@@ -0,0 +1,204 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+use crate::common;
+use crate::line::{InlineSite, Line};
+use crate::object_info::ObjectInfo;
+use crate::symbol::Symbol;
+
+/// A function in the inline stack for an address.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Frame {
+    pub function: String,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+/// The result of the symbolication of an address.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Location {
+    pub address: u32,
+    /// The name of the FUNC or PUBLIC containing the address.
+    pub symbol: String,
+    /// The offset of the address in the symbol.
+    pub offset: u32,
+    /// The frames, from the innermost inlined call to the outer function.
+    pub frames: Vec<Frame>,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        for (i, frame) in self.frames.iter().enumerate() {
+            if i == 0 {
+                write!(f, "{:x} {}", self.address, frame.function)?;
+            } else {
+                write!(f, "{:width$} {}", "", frame.function, width = 8)?;
+            }
+            if let Some(file) = frame.file.as_ref() {
+                write!(f, " {file}")?;
+                if let Some(line) = frame.line {
+                    write!(f, ":{line}")?;
+                }
+            }
+            if i + 1 < self.frames.len() {
+                write!(f, " (inlined)")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse an address such as `1a2b`, `0x1a2b` or `module+0x1a2b`.
+pub fn parse_address(s: &str) -> common::Result<u32> {
+    let s = s.trim();
+    let s = s.rsplit_once('+').map_or(s, |(_, offset)| offset);
+    let s = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    u32::from_str_radix(s, 16).map_err(|e| anyhow::anyhow!("Invalid address {}: {}", s, e))
+}
+
+fn find_line(lines: &[Line], address: u32) -> Option<&Line> {
+    let index = lines.partition_point(|line| line.rva <= address);
+    let line = lines.get(index.checked_sub(1)?)?;
+    (address - line.rva < line.len.max(1)).then_some(line)
+}
+
+impl ObjectInfo {
+    fn file_name(&self, id: u32) -> Option<String> {
+        self.files.get_mapping().get(id as usize).cloned()
+    }
+
+    fn origin_name(&self, id: u32) -> String {
+        self.inline_origins
+            .get(id as usize)
+            .cloned()
+            .unwrap_or_else(|| "<name omitted>".to_string())
+    }
+
+    fn get_symbol(&self, address: u32) -> Option<&Symbol> {
+        let (_, sym) = self.symbols.range(..=address).next_back()?;
+        if sym.is_public || address - sym.rva < sym.len {
+            Some(sym)
+        } else {
+            None
+        }
+    }
+
+    /// Get the function, file, line and inline stack for the given address,
+    /// relative to the module base.
+    pub fn lookup(&self, address: u32) -> Option<Location> {
+        let sym = self.get_symbol(address)?;
+
+        // The inline sites covering the address, outermost first.
+        let mut sites: Vec<&InlineSite> = sym
+            .source
+            .inlines
+            .iter()
+            .filter(|(_, ranges)| {
+                ranges
+                    .iter()
+                    .any(|r| r.rva <= address && address - r.rva < r.len)
+            })
+            .map(|(site, _)| site)
+            .collect();
+        sites.sort_by_key(|site| site.call_depth);
+
+        let function_at = |depth: usize| {
+            if depth == 0 {
+                sym.name.clone()
+            } else {
+                self.origin_name(sites[depth - 1].inline_origin_id)
+            }
+        };
+
+        let line = find_line(&sym.source.lines, address);
+        let mut frames = vec![Frame {
+            function: function_at(sites.len()),
+            file: line.and_then(|l| self.file_name(l.file_id)),
+            line: line.map(|l| l.num),
+        }];
+        for (depth, site) in sites.iter().enumerate().rev() {
+            frames.push(Frame {
+                function: function_at(depth),
+                file: self.file_name(site.call_file_id),
+                line: Some(site.call_line_number),
+            });
+        }
+
+        Some(Location {
+            address,
+            symbol: sym.name.clone(),
+            offset: address - sym.rva,
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read;
+
+    #[test]
+    fn test_parse_address() {
+        assert_eq!(parse_address("1a2b").unwrap(), 0x1a2b);
+        assert_eq!(parse_address("0x1a2b").unwrap(), 0x1a2b);
+        assert_eq!(parse_address(" libxul.so+0x1a2b\n").unwrap(), 0x1a2b);
+        assert!(parse_address("xyz").is_err());
+    }
+
+    #[test]
+    fn test_lookup() {
+        let data = read("./test_data/linux/basic.full.inlines.sym").unwrap();
+        let info = ObjectInfo::from_breakpad(&data).unwrap();
+        let file = Some(
+            "/home/calixte/dev/mozilla/dump_syms.calixteman/test_data/linux/basic.cpp".to_string(),
+        );
+
+        // No inlines.
+        let loc = info.lookup(0x112d).unwrap();
+        assert_eq!(loc.symbol, "inline_1(int)");
+        assert_eq!(loc.offset, 0x8);
+        assert_eq!(
+            loc.frames,
+            vec![Frame {
+                function: "inline_1(int)".to_string(),
+                file,
+                line: Some(3),
+            }]
+        );
+
+        // inline_1 is inlined in inline_2 which is inlined in inline_3.
+        let loc = info.lookup(0x118a).unwrap();
+        assert_eq!(loc.symbol, "inline_3(int)");
+        let frames: Vec<_> = loc
+            .frames
+            .iter()
+            .map(|f| (f.function.as_str(), f.line.unwrap()))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![
+                ("inline_1(int)", 3),
+                ("inline_2(int)", 10),
+                ("inline_3(int)", 15)
+            ]
+        );
+
+        // A PUBLIC.
+        let loc = info.lookup(0x1045).unwrap();
+        assert_eq!(loc.symbol, "_start");
+        assert_eq!(loc.offset, 5);
+        assert_eq!(loc.frames[0].file, None);
+
+        assert!(info.lookup(0x10).is_none());
+    }
+}
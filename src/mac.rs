@@ -3,13 +3,12 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::sync::Arc;
 use symbolic::common::Arch;
-use symbolic::debuginfo::Archive;
+use symbolic::debuginfo::{Archive, Object};
 
 use crate::common;
-use crate::mapping::PathMappings;
-use crate::object_info::ObjectInfo;
+use crate::error::Error;
+use crate::object_info::{CollectOptions, ObjectInfo};
 use crate::platform::Platform;
 
 impl ObjectInfo {
@@ -17,13 +16,12 @@ impl ObjectInfo {
         buf: &[u8],
         file_name: &str,
         arch: Arch,
-        mapping: Option<Arc<PathMappings>>,
-        collect_inlines: bool,
+        options: &CollectOptions,
     ) -> common::Result<Self> {
         // Fat files may contain several objects for different architectures
         // So if there is only one object, then we don't care about the arch (as argument)
         // and if several then we use arch (by default it's compile-time arch).
-        let archive = Archive::parse(buf)?;
+        let archive = Archive::parse(buf).map_err(|e| Error::parse(file_name, e))?;
         let object = if archive.object_count() == 1 {
             archive.object_by_index(0).unwrap()
         } else {
@@ -34,15 +32,7 @@ impl ObjectInfo {
         };
 
         if let Some(object) = object {
-            ObjectInfo::from_object(
-                &object,
-                file_name,
-                None,
-                None,
-                Platform::Mac,
-                mapping,
-                collect_inlines,
-            )
+            Self::from_macho_object(&object, file_name, options)
         } else {
             anyhow::bail!(
                 "Cannot find a valid object for architecture {} in file {}",
@@ -51,15 +41,40 @@ impl ObjectInfo {
             );
         }
     }
+
+    /// Get the [`ObjectInfo`] for each object in a fat file, whatever its
+    /// architecture.
+    pub fn from_macho_all(
+        buf: &[u8],
+        file_name: &str,
+        options: &CollectOptions,
+    ) -> common::Result<Vec<Self>> {
+        let archive = Archive::parse(buf).map_err(|e| Error::parse(file_name, e))?;
+        archive
+            .objects()
+            .map(|object| {
+                let object = object.map_err(|e| Error::parse(file_name, e))?;
+                Self::from_macho_object(&object, file_name, options)
+            })
+            .collect()
+    }
+
+    fn from_macho_object(
+        object: &Object,
+        file_name: &str,
+        options: &CollectOptions,
+    ) -> common::Result<Self> {
+        ObjectInfo::from_object(object, file_name, None, None, Platform::Mac, options)
+    }
 }
 
 /// Print on screen the cpu arch for the different objects present in the fat file
 pub fn print_macho_architectures(buf: &[u8], file_name: String) -> common::Result<()> {
-    let archive = Archive::parse(buf)?;
+    let archive = Archive::parse(buf).map_err(|e| Error::parse(&file_name, e))?;
     let archs = archive
         .objects()
-        .map(|o| o.unwrap().arch().name())
-        .collect::<Vec<_>>();
+        .map(|o| Ok(o.map_err(|e| Error::parse(&file_name, e))?.arch().name()))
+        .collect::<common::Result<Vec<_>>>()?;
     println!(
         "{} contains the following architecture{}:",
         file_name,
@@ -11,12 +11,15 @@ use regex::Regex;
 use simplelog::{ColorChoice, ConfigBuilder, LevelFilter, TermLogger, TerminalMode};
 use std::ops::Deref;
 use std::panic;
+use std::path::Path;
 
 mod action;
 
 use action::Action;
 use dump_syms::common::{self, EXTRA_INFO};
 use dump_syms::dumper;
+use dump_syms::input::Filter;
+use dump_syms::report::Report;
 
 fn cli() -> Command {
     Command::new("dump_syms")
@@ -25,22 +28,90 @@ fn cli() -> Command {
     .about("Dump debug symbols to breakpad symbols")
     .arg(
         Arg::new("filenames")
-            .help("Files to dump (.dll, .exe, .pdb, .pd_, .so, .dbg)")
-            .required(true)
+            .help("Files to dump (.dll, .exe, .pdb, .pd_, .so, .dbg), directories where to find them or .sym files to verify")
+            .required_unless_present("manifest")
             .num_args(1..)
     )
+    .arg(
+        Arg::new("manifest")
+            .help("A file listing the files and the directories to dump, one per line\n(each module is dumped independently in the store directory)")
+            .long("manifest")
+            .value_name("FILE")
+            .requires("store")
+    )
+    .arg(
+        Arg::new("include")
+            .help("Only dump the files matching this glob pattern in the directories (e.g. \"*.so\" or \"lib/**/*.dll\")")
+            .long("include")
+            .value_name("GLOB")
+            .action(ArgAction::Append)
+    )
+    .arg(
+        Arg::new("exclude")
+            .help("Don't dump the files and don't walk the directories matching this glob pattern")
+            .long("exclude")
+            .value_name("GLOB")
+            .action(ArgAction::Append)
+    )
     .arg(
         Arg::new("output")
             .help("Output file or - for stdout")
             .short('o')
             .long("output")
     )
+    .arg(
+        Arg::new("format")
+            .help("Output format")
+            .long("format")
+            .value_parser(["breakpad", "json"])
+            .default_value("breakpad")
+    )
     .arg(
         Arg::new("store")
             .help("Store output file as FILENAME.pdb/DEBUG_ID/FILENAME.sym in the given directory")
             .short('s')
             .long("store")
     )
+    .arg(
+        Arg::new("incremental")
+            .help("Don't dump the modules already in the store directory\n(with \"generator\", dump them again if they've been generated by another version of dump_syms)")
+            .long("incremental")
+            .value_parser(["skip", "generator"])
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("skip")
+            .requires("store")
+            .conflicts_with("output")
+    )
+    .arg(
+        Arg::new("upload")
+            .help("Upload the symbol files written in the store directory to a Tecken-compatible symbol server\n(e.g. https://symbols.mozilla.org/upload/)\nThe auth token is read from the DUMP_SYMS_UPLOAD_TOKEN environment variable or from --upload-token-file")
+            .long("upload")
+            .value_name("URL")
+            .requires("store")
+    )
+    .arg(
+        Arg::new("upload_token_file")
+            .help("A file containing the auth token for --upload")
+            .long("upload-token-file")
+            .value_name("FILE")
+            .requires("upload")
+    )
+    .arg(
+        Arg::new("upload_skip_existing")
+            .help("Don't upload the files already on this symbol server (e.g. https://symbols.mozilla.org/)")
+            .long("upload-skip-existing")
+            .value_name("URL")
+            .requires("upload")
+    )
+    .arg(
+        Arg::new("upload_batch_size")
+            .help("The maximum size in MB of the symbol files uploaded in one zip file")
+            .long("upload-batch-size")
+            .value_name("MB")
+            .value_parser(clap::value_parser!(u64).range(1..))
+            .requires("upload")
+    )
     .arg(
         Arg::new("debug_id")
             .help("Get the pdb file passed as argument from the cache or from symbol server using the debug id")
@@ -53,7 +124,7 @@ fn cli() -> Command {
     )
     .arg(
         Arg::new("symbol_server")
-            .help("Symbol Server configuration\n(e.g. \"SRV*c:\\symcache\\*https://symbols.mozilla.org/\")\nIt can be in file $HOME/.dump_syms/config too.")
+            .help("Symbol Server configuration\n(e.g. \"SRV*c:\\symcache\\*https://symbols.mozilla.org/\")\nor debuginfod server configuration for ELF files\n(e.g. \"DEBUGINFOD*~/.cache/debuginfod*https://debuginfod.elfutils.org/\")\nIt can be in file $HOME/.dump_syms/config too.")
             .long("symbol-server")
     )
     .arg(
@@ -62,6 +133,13 @@ fn cli() -> Command {
             .long("check-cfi")
             .action(ArgAction::SetTrue)
     )
+    .arg(
+        Arg::new("cfi_threshold")
+            .help("Fail if the percentage of the functions with some CFI data is below PERCENT\n(the functions without CFI data are logged at the info level)")
+            .long("cfi-threshold")
+            .value_name("PERCENT")
+            .value_parser(parse_percent)
+    )
     .arg(
         Arg::new("verbose")
             .help("Set the level of verbosity (off, error (default), warn, info, debug, trace)")
@@ -81,12 +159,49 @@ fn cli() -> Command {
             .short('t')
             .long("type")
             .default_value("")
+    )
+    .arg(
+        Arg::new("all_arch")
+            .help("Dump all the architectures of the fat binaries, each one in the store directory")
+            .long("all-arch")
+            .action(ArgAction::SetTrue)
+            .requires("store")
+            .conflicts_with("output")
     ).arg(
         Arg::new("list_arch")
             .help("List the architectures present in the fat binaries")
             .long("list-arch")
             .action(ArgAction::SetTrue)
     )
+    .arg(
+        Arg::new("diff")
+            .help("Compare the symbols of two files (.sym or any dumpable file) and report the differences\n(use --format json for a JSON report)")
+            .long("diff")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["list_arch", "verify"])
+    )
+    .arg(
+        Arg::new("check_reproducible")
+            .help("Dump the files twice, with one job and then with several ones and the files in the reverse order,\nand fail if the outputs aren't the same (nothing is written)")
+            .long("check-reproducible")
+            .action(ArgAction::SetTrue)
+            .conflicts_with_all(["list_arch", "verify", "diff", "lookup"])
+    )
+    .arg(
+        Arg::new("lookup")
+            .help("Look up the function, file, line and inline stack for the given address, can be repeated\n(e.g. 0x1a2b or libxul.so+0x1a2b, - to read them from stdin)")
+            .long("lookup")
+            .value_name("ADDRESS")
+            .action(ArgAction::Append)
+            .conflicts_with_all(["list_arch", "diff", "verify"])
+    )
+    .arg(
+        Arg::new("verify")
+            .help("Check that the files are valid Breakpad symbol files")
+            .long("verify")
+            .action(ArgAction::SetTrue)
+            .conflicts_with("list_arch")
+    )
     .arg(
         Arg::new("num_jobs")
             .help("Number of jobs")
@@ -119,12 +234,44 @@ For example with --mapping-var="rev=123abc" --mapping-src="/foo/bar/(.*)" --mapp
             .help("A json file containing mapping")
             .long("mapping-file")
     )
+    .arg(
+        Arg::new("mapping_source_link")
+            .help("A SourceLink json file mapping the path prefixes to URLs\n(the SourceLink streams of the PDB files are used too)")
+            .long("mapping-source-link")
+            .value_name("FILE")
+    )
+    .arg(
+        Arg::new("mapping_git")
+            .help("A local git checkout: its tracked files are mapped to git:host/repository:path:revision\nand the other ones to sha512_of_the_file/path")
+            .long("mapping-git")
+            .value_name("DIR")
+            .action(ArgAction::Append)
+    )
+    .arg(
+        Arg::new("debug_dir")
+            .help("A directory where to look for the separate debug files of the stripped ELF files\n(/usr/lib/debug by default)")
+            .long("debug-dir")
+            .value_name("DIR")
+            .action(ArgAction::Append)
+    )
+    .arg(
+        Arg::new("report")
+            .help("Write a JSON report of the results of each input file (modules, outputs, FUNC/PUBLIC/INLINE counts, warnings and errors)")
+            .long("report")
+            .value_name("FILE")
+    )
     .arg(
         Arg::new("inlines")
             .help("Whether to emit INLINE and INLINE_ORIGIN directives")
             .long("inlines")
             .action(ArgAction::SetTrue)
     )
+    .arg(
+        Arg::new("prune_unreferenced")
+            .help("Remove the FILE and INLINE_ORIGIN records which aren't referenced by any line or INLINE record\n(e.g. the ones of the functions dropped when several files are merged)")
+            .long("prune-unreferenced")
+            .action(ArgAction::SetTrue)
+    )
     .arg(Arg::new("extra_info")
              .help("Add an INFO line with the value passed to this argument")
              .long("extra-info")
@@ -182,24 +329,52 @@ fn main() {
     }));
 
     let output = matches.get_one::<String>("output").map(String::as_str);
-    let filenames = to_vec(matches.get_many::<String>("filenames").unwrap());
+    let filenames = matches
+        .get_many::<String>("filenames")
+        .map(to_vec)
+        .unwrap_or_default();
+    let manifest = matches.get_one::<String>("manifest").map(String::as_str);
     let symbol_server = matches
         .get_one::<String>("symbol_server")
         .map(String::as_str);
     let store = matches.get_one::<String>("store").map(String::as_str);
+    let format = matches
+        .get_one::<String>("format")
+        .unwrap()
+        .parse::<dumper::Format>()
+        .unwrap();
+    let incremental = match matches.get_one::<String>("incremental").map(String::as_str) {
+        Some("generator") => dumper::Incremental::SkipSameGenerator,
+        Some(_) => dumper::Incremental::Skip,
+        None => dumper::Incremental::Off,
+    };
     let debug_id = matches.get_one::<String>("debug_id").map(String::as_str);
     let code_id = matches.get_one::<String>("code_id").map(String::as_str);
     let arch = matches.get_one::<String>("arch").unwrap().as_str();
+    let all_archs = matches.get_flag("all_arch");
     let check_cfi = matches.get_flag("check_cfi");
+    let cfi_threshold = matches.get_one::<f64>("cfi_threshold").copied();
     let emit_inlines = matches.get_flag("inlines");
+    let prune_unreferenced = matches.get_flag("prune_unreferenced");
     let mapping_var = matches.get_many("mapping_var").map(to_vec);
     let mapping_src = matches.get_many("mapping_src").map(to_vec);
     let mapping_dest = matches.get_many("mapping_dest").map(to_vec);
     let mapping_file = matches
         .get_one::<String>("mapping_file")
         .map(String::as_str);
+    let mapping_source_link = matches
+        .get_one::<String>("mapping_source_link")
+        .map(String::as_str);
+    let mapping_git = matches.get_many("mapping_git").map(to_vec);
+    let debug_dirs = matches.get_many("debug_dir").map(to_vec);
+    let report = matches.get_one::<String>("report").map(String::as_str);
     get_extra_info(&matches);
 
+    if let Err(e) = check_upload_format(&matches) {
+        eprintln!("{e}");
+        std::process::exit(dump_syms::error::exit_code(&e));
+    }
+
     let num_jobs = if let Ok(num_jobs) = matches
         .get_one::<String>("num_jobs")
         .unwrap()
@@ -210,45 +385,157 @@ fn main() {
         num_cpus::get()
     };
 
+    let output = match (output, store) {
+        (Some(out), Some(store)) => dumper::Output::FileAndStore {
+            file: out.into(),
+            store_directory: store.into(),
+        },
+        (Some(out), None) => dumper::Output::File(out.into()),
+        (None, Some(store)) => dumper::Output::Store(store.into()),
+        (None, None) => dumper::Output::File(dumper::FileOutput::Stdout),
+    };
+
+    let config = dumper::Config {
+        output,
+        format,
+        incremental,
+        symbol_server,
+        debug_id,
+        code_id,
+        arch,
+        all_archs,
+        num_jobs,
+        check_cfi,
+        cfi_threshold,
+        emit_inlines,
+        prune_unreferenced,
+        mapping_var,
+        mapping_src,
+        mapping_dest,
+        mapping_file,
+        mapping_source_link,
+        mapping_git,
+        debug_dirs,
+        report,
+    };
+
     let action = if matches.get_flag("list_arch") {
         Action::ListArch
+    } else if matches.get_flag("verify") {
+        Action::Verify
+    } else if matches.get_flag("diff") {
+        Action::Diff(config)
+    } else if matches.get_flag("check_reproducible") {
+        Action::CheckReproducible(config)
+    } else if let Some(addresses) = matches.get_many::<String>("lookup") {
+        Action::Lookup(config, to_vec(addresses))
+    } else if manifest.is_some() || filenames.iter().any(|f| Path::new(f).is_dir()) {
+        let include = matches.get_many("include").map(to_vec).unwrap_or_default();
+        let exclude = matches.get_many("exclude").map(to_vec).unwrap_or_default();
+        match Filter::new(&include, &exclude) {
+            Ok(filter) => Action::DumpModules(config, filter, manifest),
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(dump_syms::error::exit_code(&e));
+            }
+        }
     } else {
-        let output = match (output, store) {
-            (Some(out), Some(store)) => dumper::Output::FileAndStore {
-                file: out.into(),
-                store_directory: store.into(),
-            },
-            (Some(out), None) => dumper::Output::File(out.into()),
-            (None, Some(store)) => dumper::Output::Store(store.into()),
-            (None, None) => dumper::Output::File(dumper::FileOutput::Stdout),
-        };
-
-        Action::Dump(dumper::Config {
-            output,
-            symbol_server,
-            debug_id,
-            code_id,
-            arch,
-            num_jobs,
-            check_cfi,
-            emit_inlines,
-            mapping_var,
-            mapping_src,
-            mapping_dest,
-            mapping_file,
-        })
+        Action::Dump(config)
     };
 
-    if let Err(e) = action.action(&filenames) {
+    if let Err(e) = action
+        .action(&filenames)
+        .and_then(|report| upload(&matches, store, report))
+    {
         eprintln!("{e}");
-        std::process::exit(1);
+        std::process::exit(dump_syms::error::exit_code(&e));
+    }
+}
+
+/// Only the Breakpad files are uploaded so `--upload` can't be used with
+/// another format.
+fn check_upload_format(matches: &clap::ArgMatches) -> common::Result<()> {
+    let format = matches.get_one::<String>("format").map(String::as_str);
+    if matches.contains_id("upload") && format != Some("breakpad") {
+        return Err(dump_syms::error::Error::Config(
+            "--upload requires the breakpad format: the json files aren't uploaded".to_string(),
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Upload the symbol files written in the store directory by the dump.
+#[cfg(feature = "http")]
+fn upload(
+    matches: &clap::ArgMatches,
+    store: Option<&str>,
+    report: Option<Report>,
+) -> common::Result<()> {
+    use dump_syms::error::Error;
+    use dump_syms::upload;
+
+    let (Some(url), Some(store), Some(report)) =
+        (matches.get_one::<String>("upload"), store, report)
+    else {
+        return Ok(());
+    };
+    let token = match matches.get_one::<String>("upload_token_file") {
+        Some(file) => std::fs::read_to_string(file)
+            .map_err(|source| Error::Io {
+                path: file.into(),
+                source,
+            })?
+            .trim()
+            .to_string(),
+        None => std::env::var("DUMP_SYMS_UPLOAD_TOKEN").map_err(|_| {
+            Error::Config("no auth token for the upload (see --upload-token-file)".to_string())
+        })?,
+    };
+
+    let mut options = upload::Options::new(url, &token);
+    options.check_url = matches.get_one::<String>("upload_skip_existing").cloned();
+    if let Some(size) = matches.get_one::<u64>("upload_batch_size") {
+        options.max_batch_size = size << 20;
     }
+
+    let files = upload::store_files_from_paths(store, report.outputs())?;
+    let report = upload::upload(files, &options)?;
+    log::info!(
+        "{} files uploaded in {} zip files, {} files already on the server",
+        report.uploaded.len(),
+        report.batches,
+        report.skipped.len()
+    );
+    Ok(())
+}
+
+#[cfg(not(feature = "http"))]
+fn upload(
+    matches: &clap::ArgMatches,
+    _store: Option<&str>,
+    _report: Option<Report>,
+) -> common::Result<()> {
+    anyhow::ensure!(
+        !matches.contains_id("upload"),
+        "dump_syms has been built without the http feature: --upload isn't available"
+    );
+    Ok(())
 }
 
 fn to_vec(values: clap::parser::ValuesRef<'_, String>) -> Vec<&str> {
     values.map(String::as_str).collect()
 }
 
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    if (0. ..=100.).contains(&percent) {
+        Ok(percent)
+    } else {
+        Err(format!("{percent} isn't in 0..=100"))
+    }
+}
+
 fn get_extra_info(matches: &clap::ArgMatches) {
     static INFO_LINE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Z_]+ .*").unwrap());
 
@@ -267,10 +554,7 @@ fn get_extra_info(matches: &clap::ArgMatches) {
     }).unwrap_or_default();
 
     if !*matches.get_one::<bool>("no-generator").unwrap() {
-        extra_info.push(format!(
-            "GENERATOR mozilla/dump_syms {}",
-            env!("CARGO_PKG_VERSION")
-        ));
+        extra_info.push(format!("GENERATOR {}", common::GENERATOR));
     }
 
     EXTRA_INFO.set(extra_info).unwrap();
@@ -280,3 +564,65 @@ fn get_extra_info(matches: &clap::ArgMatches) {
 fn verify_cli() {
     cli().debug_assert();
 }
+
+#[test]
+fn lookup_cli() {
+    let matches = cli()
+        .try_get_matches_from([
+            "dump_syms",
+            "--lookup",
+            "0x10",
+            "--lookup",
+            "0x20",
+            "libxul.so",
+        ])
+        .unwrap();
+    let addresses: Vec<_> = matches.get_many::<String>("lookup").unwrap().collect();
+    let filenames: Vec<_> = matches.get_many::<String>("filenames").unwrap().collect();
+    assert_eq!(addresses, ["0x10", "0x20"]);
+    assert_eq!(filenames, ["libxul.so"]);
+
+    assert!(cli()
+        .try_get_matches_from(["dump_syms", "--verify", "--lookup", "0x10", "a.sym"])
+        .is_err());
+}
+
+#[test]
+fn cfi_threshold_cli() {
+    let threshold = |value: &str| {
+        cli()
+            .try_get_matches_from(["dump_syms", "--cfi-threshold", value, "libxul.so"])
+            .map(|matches| *matches.get_one::<f64>("cfi_threshold").unwrap())
+    };
+    assert_eq!(threshold("0").unwrap(), 0.);
+    assert_eq!(threshold("99.5").unwrap(), 99.5);
+    assert_eq!(threshold("100").unwrap(), 100.);
+    assert!(threshold("-1").is_err());
+    assert!(threshold("100.1").is_err());
+    assert!(threshold("NaN").is_err());
+    assert!(threshold("all").is_err());
+}
+
+#[test]
+fn upload_format_cli() {
+    let check = |format: &str| {
+        let matches = cli()
+            .try_get_matches_from([
+                "dump_syms",
+                "--store",
+                "symbols",
+                "--upload",
+                "https://symbols.mozilla.org/upload/",
+                "--format",
+                format,
+                "libxul.so",
+            ])
+            .unwrap();
+        check_upload_format(&matches)
+    };
+    assert!(check("breakpad").is_ok());
+    assert_eq!(
+        check("json").unwrap_err().to_string(),
+        "Invalid configuration: --upload requires the breakpad format: the json files aren't uploaded"
+    );
+}
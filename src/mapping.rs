@@ -9,9 +9,10 @@ use regex::Regex;
 use serde::Deserialize;
 use sha2::{Digest, Sha512};
 use std::path::Path;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
 use crate::common;
+use crate::git::Checkout;
 use crate::utils;
 
 #[derive(Debug, Default, Deserialize)]
@@ -27,6 +28,17 @@ struct Mappings {
     mappings: Vec<Mapping>,
 }
 
+/// A SourceLink file, as embedded in the PDB files built with `/SOURCELINK`:
+/// see <https://github.com/dotnet/designs/blob/main/accepted/2020/diagnostics/source-link.md>.
+///
+/// ```text
+/// {"documents": {"C:\\src\\*": "https://raw.githubusercontent.com/org/repo/abcdef/*"}}
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct SourceLink {
+    documents: HashMap<String, String>,
+}
+
 impl Mappings {
     fn new(path: &str) -> common::Result<Self> {
         let data = utils::read(path)?;
@@ -37,7 +49,20 @@ impl Mappings {
     }
 }
 
-fn get_digest(file: &Path, uppercase: bool) -> common::Result<String> {
+impl SourceLink {
+    fn get_mappings(data: &[u8]) -> common::Result<Vec<PathMappingGenerator>> {
+        let source_link = serde_json::from_slice::<Self>(data)?;
+        let mut documents: Vec<_> = source_link.documents.into_iter().collect();
+        // The most specific path wins.
+        documents.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+        documents
+            .iter()
+            .map(|(path, url)| PathMappingGenerator::from_source_link(path, url))
+            .collect()
+    }
+}
+
+pub(crate) fn get_digest(file: &Path, uppercase: bool) -> common::Result<String> {
     let data = utils::read(file)?;
     let sha = Sha512::digest(data);
     Ok(if uppercase {
@@ -68,6 +93,8 @@ struct PathMappingGenerator {
     actions: Vec<Action>,
     mapping: Vec<u8>,
     files: HashSet<String>,
+    /// Replace the backslashes by slashes in the groups
+    forward_slashes: bool,
 }
 
 /*
@@ -137,6 +164,39 @@ impl PathMappingGenerator {
             actions,
             mapping,
             files,
+            forward_slashes: false,
+        })
+    }
+
+    /// Create a generator for a SourceLink document: the path is matched
+    /// case-insensitively and the part matching its trailing `*`, if any,
+    /// replaces the `*` in the url (with slashes as path separators).
+    pub(crate) fn from_source_link(path: &str, url: &str) -> common::Result<Self> {
+        let mapping = url.replacen('*', "", 1).into_bytes();
+        let (re, mut actions) = match (path.strip_suffix('*'), url.find('*')) {
+            (Some(prefix), Some(star)) => (
+                format!("(?i)^{}(.*)$", regex::escape(prefix)),
+                vec![Action {
+                    kind: ActionKind::Group(1),
+                    start: 0,
+                    end: star,
+                }],
+            ),
+            (None, None) => (format!("(?i)^{}$", regex::escape(path)), Vec::new()),
+            _ => anyhow::bail!("Invalid SourceLink document {path}: {url}"),
+        };
+        actions.push(Action {
+            kind: ActionKind::None,
+            start: actions.last().map_or(0, |a| a.end),
+            end: mapping.len(),
+        });
+
+        Ok(PathMappingGenerator {
+            re: Regex::new(&re)?,
+            actions,
+            mapping,
+            files: HashSet::default(),
+            forward_slashes: true,
         })
     }
 
@@ -151,7 +211,7 @@ impl PathMappingGenerator {
                             if let Some(group) = caps.get(group) {
                                 let group = group.as_str();
 
-                                if cfg!(windows) {
+                                if cfg!(windows) || self.forward_slashes {
                                     let group = group.replace('\\', "/");
                                     buf.extend_from_slice(group.as_bytes());
                                 } else {
@@ -180,6 +240,13 @@ impl PathMappingGenerator {
 #[derive(Debug, Default)]
 pub struct PathMappings {
     mappings: Vec<PathMappingGenerator>,
+    /// The git checkouts, the innermost ones first
+    checkouts: Vec<Checkout>,
+    /// The mappings of some given files (the keys are in lowercase)
+    files: HashMap<String, String>,
+    /// The mappings given on the command line, which win over the ones above
+    /// (e.g. the ones from the streams of a PDB file)
+    explicit: Option<Arc<PathMappings>>,
     cache: Mutex<HashMap<String, String>>,
 }
 
@@ -189,23 +256,71 @@ impl PathMappings {
         sources: &Option<Vec<&str>>,
         destinations: &Option<Vec<&str>>,
         file: &Option<&str>,
+        source_link: &Option<&str>,
+        git_checkouts: &Option<Vec<&str>>,
     ) -> common::Result<Option<Self>> {
         let vars = Self::get_variables(variables)?;
         let mut mappings = Vec::new();
 
         Self::get_mappings_from_file(&vars, file, &mut mappings)?;
         Self::get_mappings(&vars, sources, destinations, &mut mappings)?;
+        if let Some(source_link) = source_link {
+            mappings.extend(SourceLink::get_mappings(&utils::read(source_link)?)?);
+        }
+
+        let checkouts = Self::get_checkouts(git_checkouts)?;
 
-        Ok(if mappings.is_empty() {
+        Ok(if mappings.is_empty() && checkouts.is_empty() {
             None
         } else {
             Some(PathMappings {
                 mappings,
-                cache: Mutex::new(HashMap::default()),
+                checkouts,
+                ..Default::default()
             })
         })
     }
 
+    /// Map the given files (the case of the paths is ignored) when the
+    /// `explicit` mappings don't map them.
+    pub(crate) fn with_files(
+        files: HashMap<String, String>,
+        explicit: Option<Arc<PathMappings>>,
+    ) -> Self {
+        PathMappings {
+            files: files
+                .into_iter()
+                .map(|(path, dest)| (path.to_ascii_lowercase(), dest))
+                .collect(),
+            explicit,
+            ..Default::default()
+        }
+    }
+
+    /// Map the files with the SourceLink file `data` when the `explicit`
+    /// mappings don't map them.
+    pub(crate) fn with_source_link(
+        data: &[u8],
+        explicit: Option<Arc<PathMappings>>,
+    ) -> common::Result<Self> {
+        Ok(PathMappings {
+            mappings: SourceLink::get_mappings(data)?,
+            explicit,
+            ..Default::default()
+        })
+    }
+
+    fn get_checkouts(dirs: &Option<Vec<&str>>) -> common::Result<Vec<Checkout>> {
+        let mut checkouts = dirs
+            .iter()
+            .flatten()
+            .map(Checkout::new)
+            .collect::<common::Result<Vec<_>>>()?;
+        // A checkout can be in another one (e.g. a submodule).
+        checkouts.sort_by_key(|c| std::cmp::Reverse(c.root().as_os_str().len()));
+        Ok(checkouts)
+    }
+
     fn get_variables(vars: &Option<Vec<&str>>) -> common::Result<HashMap<String, String>> {
         let mut variables = HashMap::default();
         if let Some(vars) = vars {
@@ -308,6 +423,21 @@ impl PathMappings {
         #[cfg(windows)]
         let file_str = file_str.strip_prefix(r"\\?\").unwrap_or(file_str);
 
+        let res = self.find(file, file_str)?;
+        if res.is_none() {
+            warn!("Cannot find a mapping for file {file_str}");
+        }
+
+        Ok(res)
+    }
+
+    fn find(&self, file: &Path, file_str: &str) -> common::Result<Option<String>> {
+        if let Some(explicit) = self.explicit.as_ref() {
+            if let Some(res) = explicit.find(file, file_str)? {
+                return Ok(Some(res));
+            }
+        }
+
         {
             let cache = self.cache.lock().unwrap();
             if let Some(cached) = cache.get(file_str) {
@@ -315,24 +445,31 @@ impl PathMappings {
             }
         }
 
-        let mut res = None;
-        for mapping in self.mappings.iter() {
-            let mapping = mapping.apply(file, file_str)?;
-            if let Some(mapping) = mapping {
-                res = Some(mapping);
-                break;
+        let mut res = self.files.get(&file_str.to_ascii_lowercase()).cloned();
+        if res.is_none() {
+            for mapping in self.mappings.iter() {
+                let mapping = mapping.apply(file, file_str)?;
+                if let Some(mapping) = mapping {
+                    res = Some(mapping);
+                    break;
+                }
+            }
+        }
+        if res.is_none() {
+            for checkout in self.checkouts.iter() {
+                res = checkout.map(file)?;
+                if res.is_some() {
+                    break;
+                }
             }
         }
 
-        if let Some(res) = res {
+        if let Some(res) = res.as_ref() {
             let mut cache = self.cache.lock().unwrap();
             cache.insert(file_str.to_string(), res.clone());
-            return Ok(Some(res));
         }
 
-        warn!("Cannot find a mapping for file {file_str}");
-
-        Ok(None)
+        Ok(res)
     }
 }
 
@@ -361,9 +498,16 @@ mod tests {
 
     #[test]
     fn test_path_mapping_file() {
-        let mappings = PathMappings::new(&None, &None, &None, &Some("./test_data/mapping.json"))
-            .unwrap()
-            .unwrap();
+        let mappings = PathMappings::new(
+            &None,
+            &None,
+            &None,
+            &Some("./test_data/mapping.json"),
+            &None,
+            &None,
+        )
+        .unwrap()
+        .unwrap();
 
         let files = vec![
             ("/home/worker/a/c/ddd.cpp", "hg:hg.mozilla.org/mozilla-central:a/c/ddd.cpp:6639deb894172375b05d6791f5f8c7d53ca79723"),
@@ -378,4 +522,36 @@ mod tests {
             assert_eq!(mappings.map(path).unwrap().unwrap(), expected.to_string())
         }
     }
+
+    #[test]
+    fn test_source_link() {
+        let mappings = PathMappings::new(
+            &None,
+            &None,
+            &None,
+            &None,
+            &Some("./test_data/source_link.json"),
+            &None,
+        )
+        .unwrap()
+        .unwrap();
+
+        let files = vec![
+            (r"C:\build\src\dom\base\Document.cpp", "https://raw.githubusercontent.com/mozilla/gecko-dev/6639deb894172375b05d6791f5f8c7d53ca79723/dom/base/Document.cpp"),
+            (r"c:\build\src\xpcom\base\nsCOMPtr.h", "https://raw.githubusercontent.com/mozilla/gecko-dev/6639deb894172375b05d6791f5f8c7d53ca79723/xpcom/base/nsCOMPtr.h"),
+            (r"C:\build\src\third_party\rust\log\src\lib.rs", "https://static.crates.io/sources/log/src/lib.rs?raw=true"),
+            (r"C:\build\obj\generated.cpp", "https://example.com/generated.cpp"),
+        ];
+
+        for (path, expected) in files {
+            assert_eq!(mappings.map(path).unwrap().unwrap(), expected.to_string())
+        }
+        assert!(mappings
+            .map(r"C:\build\obj\generated.cpp.orig")
+            .unwrap()
+            .is_none());
+
+        let invalid = br#"{"documents": {"C:\\src\\*": "https://example.com/src"}}"#;
+        assert!(PathMappings::with_source_link(invalid, None).is_err());
+    }
 }
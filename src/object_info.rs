@@ -4,9 +4,11 @@
 // copied, modified, or distributed except according to those terms.
 
 use log::error;
+use std::cmp::Ordering;
 use std::collections::btree_map;
 use std::fmt::{Display, Formatter};
 use std::io::Write;
+use std::ops::Range;
 use std::sync::Arc;
 use symbolic::cfi::AsciiCfiWriter;
 use symbolic::debuginfo::{FileFormat, Object};
@@ -15,9 +17,38 @@ use super::source::{SourceFiles, SourceMap};
 use super::symbol::{ContainsSymbol, Symbols};
 use crate::collector::Collector;
 use crate::common::{self, EXTRA_INFO};
-use crate::inline_origins::{merge_inline_origins, InlineOrigins};
+use crate::compact_unwind;
+use crate::error::Error;
+use crate::inline_origins::{merge_inline_origins, prune_inline_origins, InlineOrigins};
+use crate::json::JsonModule;
 use crate::mapping::PathMappings;
 use crate::platform::Platform;
+use crate::split_dwarf::SplitDwarf;
+use crate::stack::{Stack, StackWriter};
+
+/// The options to collect the symbols of an object.
+#[derive(Clone, Debug)]
+pub struct CollectOptions {
+    pub mapping: Option<Arc<PathMappings>>,
+    pub collect_inlines: bool,
+    /// Resolve the source file paths against the filesystem (e.g. to follow
+    /// the symlinks) when the object has been built for the host platform
+    pub canonicalize: bool,
+    /// The number of threads collecting the functions (the debug information
+    /// is still parsed by a single one)
+    pub num_threads: usize,
+}
+
+impl Default for CollectOptions {
+    fn default() -> Self {
+        Self {
+            mapping: None,
+            collect_inlines: false,
+            canonicalize: true,
+            num_threads: 1,
+        }
+    }
+}
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Type {
@@ -27,44 +58,48 @@ pub enum Type {
 
 #[derive(Debug)]
 pub struct ObjectInfo {
-    symbols: Symbols,
-    files: SourceMap,
-    inline_origins: Vec<String>,
-    file_name: String,
-    cpu: &'static str,
-    debug_id: String,
-    code_id: Option<String>,
-    pe_name: Option<String>,
-    stack: String,
-    bin_type: Type,
-    platform: Platform,
+    pub(crate) symbols: Symbols,
+    pub(crate) files: SourceMap,
+    pub(crate) inline_origins: Vec<String>,
+    pub(crate) file_name: String,
+    pub(crate) cpu: &'static str,
+    pub(crate) debug_id: String,
+    pub(crate) code_id: Option<String>,
+    pub(crate) pe_name: Option<String>,
+    pub(crate) stack: Stack,
+    pub(crate) bin_type: Type,
+    pub(crate) platform: Platform,
+    /// The extra INFO lines, [`EXTRA_INFO`] is used when `None`.
+    pub(crate) extra_info: Option<Vec<String>>,
 }
 
-impl Display for ObjectInfo {
+/// The MODULE, INFO, FILE and INLINE_ORIGIN records.
+struct Header<'a>(&'a ObjectInfo);
+
+impl Display for Header<'_> {
     fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        let info = self.0;
         writeln!(
             f,
             "MODULE {} {} {} {}",
-            self.platform, self.cpu, self.debug_id, self.file_name
+            info.platform, info.cpu, info.debug_id, info.file_name
         )?;
 
-        if let Some(code_id) = self.code_id.as_ref() {
-            let pe_name = self.pe_name.as_deref().unwrap_or_default();
+        if let Some(code_id) = info.code_id.as_ref() {
+            let pe_name = info.pe_name.as_deref().unwrap_or_default();
             let line = format!("INFO CODE_ID {code_id} {pe_name}");
             writeln!(f, "{}", line.trim())?;
         }
 
-        if let Some(extra_info) = EXTRA_INFO.get() {
-            for line in extra_info {
-                writeln!(f, "INFO {line:}")?;
-            }
+        for line in info.extra_info() {
+            writeln!(f, "INFO {line:}")?;
         }
 
-        for (n, file_name) in self.files.get_mapping().iter().enumerate() {
+        for (n, file_name) in info.files.get_mapping().iter().enumerate() {
             writeln!(f, "FILE {n} {file_name}")?;
         }
 
-        for (n, function_name) in self.inline_origins.iter().enumerate() {
+        for (n, function_name) in info.inline_origins.iter().enumerate() {
             let function_name = if function_name.trim().is_empty() {
                 "<name omitted>"
             } else {
@@ -73,6 +108,14 @@ impl Display for ObjectInfo {
             writeln!(f, "INLINE_ORIGIN {n} {function_name}")?;
         }
 
+        Ok(())
+    }
+}
+
+impl Display for ObjectInfo {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "{}", Header(self))?;
+
         for (_, sym) in self.symbols.iter() {
             write!(f, "{sym}")?;
         }
@@ -83,9 +126,15 @@ impl Display for ObjectInfo {
     }
 }
 
-fn get_stack_info(pdb: Option<&Object>, pe: Option<&Object>) -> String {
-    let mut buf = Vec::new();
-    let mut cfi_writer = AsciiCfiWriter::new(&mut buf);
+/// Get the STACK records from the unwind information of the PE file or, when
+/// it has none (e.g. on x86), from the PDB file: its FPO and frame data are
+/// turned into STACK WIN records (types 0 and 4).
+///
+/// The records are written in a temporary file when they're too big to be
+/// kept in memory (see [`crate::stack`]).
+fn get_stack_info(pdb: Option<&Object>, pe: Option<&Object>) -> Stack {
+    let mut writer = StackWriter::new();
+    let mut cfi_writer = AsciiCfiWriter::new(&mut writer);
 
     let result = match (pdb, pe) {
         (_, Some(pe)) if pe.has_unwind_info() => cfi_writer.process(pe),
@@ -97,7 +146,29 @@ fn get_stack_info(pdb: Option<&Object>, pe: Option<&Object>) -> String {
         error!("CFI: {e:?}");
     }
 
-    String::from_utf8(buf).unwrap()
+    let mut stack = writer.finish().unwrap_or_else(|e| {
+        error!("CFI: {e}");
+        Stack::default()
+    });
+    if let Some(Object::MachO(macho)) = pdb {
+        compact_unwind::add_stack_records(macho, &mut stack);
+    }
+    stack
+}
+
+/// Get the address range of a STACK CFI INIT or STACK WIN record.
+fn get_stack_range(line: &str) -> Option<Range<u64>> {
+    let parse_hex = |s: Option<&str>| s.and_then(|s| u64::from_str_radix(s, 16).ok());
+    let (start, size) = if let Some(rest) = line.strip_prefix("STACK CFI INIT ") {
+        let mut toks = rest.split(' ');
+        (parse_hex(toks.next())?, parse_hex(toks.next())?)
+    } else if let Some(rest) = line.strip_prefix("STACK WIN ") {
+        let mut toks = rest.split(' ').skip(1);
+        (parse_hex(toks.next())?, parse_hex(toks.next())?)
+    } else {
+        return None;
+    };
+    Some(start..start.saturating_add(size))
 }
 
 impl ObjectInfo {
@@ -107,20 +178,44 @@ impl ObjectInfo {
         pe_object: Option<&Object>,
         pe_file_name: Option<&str>,
         platform: Platform,
-        mapping: Option<Arc<PathMappings>>,
-        collect_inlines: bool,
+        options: &CollectOptions,
+    ) -> common::Result<Self> {
+        Self::from_object_and_split_dwarf(
+            main_object,
+            main_file_name,
+            pe_object,
+            pe_file_name,
+            platform,
+            options,
+            None,
+        )
+    }
+
+    /// Same as [`ObjectInfo::from_object`] but the functions are collected
+    /// from the split DWARF too.
+    pub(crate) fn from_object_and_split_dwarf(
+        main_object: &Object,
+        main_file_name: &str,
+        pe_object: Option<&Object>,
+        pe_file_name: Option<&str>,
+        platform: Platform,
+        options: &CollectOptions,
+        split_dwarf: Option<&SplitDwarf>,
     ) -> common::Result<Self> {
         let mut collector = Collector {
             platform,
-            collect_inlines,
+            collect_inlines: options.collect_inlines,
+            num_threads: options.num_threads,
             syms: Symbols::default(),
         };
 
-        let ds = main_object.debug_session()?;
+        let ds = main_object
+            .debug_session()
+            .map_err(|e| Error::parse(main_file_name, e))?;
         let mut source = SourceFiles::new(
-            mapping,
+            options.mapping.clone(),
             platform,
-            Self::canonicalize(&platform, main_object),
+            options.canonicalize && Self::canonicalize(&platform, main_object),
         );
         let mut inline_origins = InlineOrigins::default();
         let debug_id = format!("{}", main_object.debug_id().breakpad());
@@ -136,6 +231,10 @@ impl ObjectInfo {
         };
 
         collector.collect_functions(&ds, &mut source, &mut inline_origins)?;
+        let split_functions = split_dwarf.map(SplitDwarf::functions).unwrap_or_default();
+        for fun in split_functions.iter() {
+            collector.collect_function(fun, &mut source, &mut inline_origins);
+        }
 
         if let Object::Pe(pe) = &main_object {
             if let Some(exception_data) = pe.exception_data() {
@@ -179,6 +278,7 @@ impl ObjectInfo {
             stack,
             bin_type,
             platform,
+            extra_info: None,
         })
     }
 
@@ -199,6 +299,27 @@ impl ObjectInfo {
         file_name.rsplit('/').next().unwrap_or(file_name)
     }
 
+    /// The order in which the objects with the same debug id are merged, the
+    /// biggest one first, so the result doesn't depend on the order of the
+    /// inputs.
+    pub fn cmp_for_merge(&self, other: &ObjectInfo) -> Ordering {
+        let key = |info: &ObjectInfo| {
+            (
+                info.symbols.len(),
+                info.bin_type == Type::DebugInfo,
+                info.files.get_mapping().len(),
+                info.inline_origins.len(),
+                info.file_name.clone(),
+                info.pe_name.clone(),
+            )
+        };
+        key(other).cmp(&key(self))
+    }
+
+    /// Merge two objects with the same debug id (e.g. a stripped binary and
+    /// its debug file): the FILE and INLINE_ORIGIN ids of the biggest one are
+    /// kept and the ones of the other are added after them, in the same
+    /// order, so `merge(a, b)` and `merge(b, a)` are the same.
     pub fn merge(left: ObjectInfo, right: ObjectInfo) -> common::Result<ObjectInfo> {
         anyhow::ensure!(
             left.debug_id == right.debug_id,
@@ -208,20 +329,17 @@ impl ObjectInfo {
         );
 
         // Just to avoid to iterate on the bigger
-        let (mut left, mut right) = if left.symbols.len() > right.symbols.len() {
+        let (mut left, mut right) = if left.cmp_for_merge(&right) != Ordering::Greater {
             (left, right)
         } else {
             (right, left)
         };
 
-        // merge the CFIs
+        // merge the CFIs (in memory)
         if left.stack.is_empty() {
             std::mem::swap(&mut left.stack, &mut right.stack);
         } else if !right.stack.is_empty() {
-            if *left.stack.as_bytes().last().unwrap() != b'\n' {
-                left.stack.push('\n');
-            }
-            left.stack.push_str(&right.stack);
+            left.stack.append(std::mem::take(&mut right.stack))?;
         }
 
         // If the two files contains some FUNC they may have differents FILE number associated with
@@ -284,6 +402,10 @@ impl ObjectInfo {
             left.code_id = right.code_id;
         }
 
+        if left.extra_info.is_none() {
+            left.extra_info = right.extra_info;
+        }
+
         if right.bin_type == Type::Stripped {
             left.file_name = right.file_name;
         }
@@ -291,11 +413,102 @@ impl ObjectInfo {
         Ok(left)
     }
 
+    /// Remove the FILE and INLINE_ORIGIN records which aren't referenced by
+    /// any line or INLINE record (e.g. the ones of the functions which have
+    /// been dropped by a merge), the others keeping their order.
+    ///
+    /// A reference to an unknown FILE or INLINE_ORIGIN record is an error.
+    pub fn prune_unreferenced(&mut self) -> common::Result<()> {
+        let mut used_files = vec![false; self.files.get_mapping().len()];
+        let mut used_inline_origins = vec![false; self.inline_origins.len()];
+        let mark_used =
+            |used: &mut [bool], kind: &str, id: u32, func: &str| match used.get_mut(id as usize) {
+                Some(used) => {
+                    *used = true;
+                    Ok(())
+                }
+                None => Err(Error::parse(
+                    &self.file_name,
+                    format!("Unknown {kind} id {id} in {func}"),
+                )),
+            };
+        for sym in self.symbols.values() {
+            for line in sym.source.lines.iter() {
+                mark_used(&mut used_files, "FILE", line.file_id, &sym.name)?;
+            }
+            for site in sym.source.inlines.keys() {
+                mark_used(&mut used_files, "FILE", site.call_file_id, &sym.name)?;
+                mark_used(
+                    &mut used_inline_origins,
+                    "INLINE_ORIGIN",
+                    site.inline_origin_id,
+                    &sym.name,
+                )?;
+            }
+        }
+        if used_files
+            .iter()
+            .chain(used_inline_origins.iter())
+            .all(|used| *used)
+        {
+            return Ok(());
+        }
+
+        let file_remapping = self.files.prune(&used_files);
+        let inline_origin_remapping =
+            prune_inline_origins(&mut self.inline_origins, &used_inline_origins);
+        for sym in self.symbols.values_mut() {
+            sym.remap_lines(Some(&file_remapping));
+            sym.remap_inlines(Some(&file_remapping), &inline_origin_remapping);
+        }
+
+        Ok(())
+    }
+
     pub fn dump<W: Write>(&self, mut writer: W) -> common::Result<()> {
         write!(writer, "{self}")?;
         Ok(())
     }
 
+    /// Same as [`ObjectInfo::dump`] but the symbols are dropped once they're
+    /// written and the STACK records are copied from their temporary file, if
+    /// any, so the memory of the symbols is released as they're written (but
+    /// they've all been collected before).
+    pub fn write_breakpad<W: Write>(self, mut writer: W) -> common::Result<()> {
+        write!(writer, "{}", Header(&self))?;
+
+        let ObjectInfo { symbols, stack, .. } = self;
+        for (_, sym) in symbols {
+            write!(writer, "{sym}")?;
+        }
+
+        stack.write_to(&mut writer)?;
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Dump the data as JSON, see [`crate::json`] for the schema.
+    pub fn dump_json<W: Write>(&self, writer: W) -> common::Result<()> {
+        serde_json::to_writer(writer, &JsonModule::new(self))?;
+        Ok(())
+    }
+
+    pub(crate) fn extra_info(&self) -> impl Iterator<Item = &String> {
+        match self.extra_info.as_ref() {
+            Some(extra_info) => Some(extra_info),
+            None => EXTRA_INFO.get(),
+        }
+        .into_iter()
+        .flatten()
+    }
+
+    /// Set the extra INFO lines (e.g. `GENERATOR mozilla/dump_syms 2.3.7`)
+    /// instead of using the global [`EXTRA_INFO`].
+    pub fn set_extra_info(&mut self, extra_info: Vec<String>) {
+        self.extra_info = Some(extra_info);
+    }
+
     pub fn get_debug_id(&self) -> &str {
         &self.debug_id
     }
@@ -307,4 +520,110 @@ impl ObjectInfo {
     pub fn has_stack(&self) -> bool {
         !self.stack.is_empty()
     }
+
+    /// Get the sorted and merged address ranges covered by the STACK CFI INIT
+    /// and STACK WIN records.
+    pub fn get_stack_ranges(&self) -> Vec<Range<u64>> {
+        let mut ranges = Vec::new();
+        let res = self.stack.for_each_line(|line| {
+            ranges.extend(get_stack_range(line));
+        });
+        if let Err(e) = res {
+            error!("Cannot read the STACK records: {e}");
+        }
+
+        ranges.sort_unstable_by_key(|r| r.start);
+        ranges.dedup_by(|next, current| {
+            if next.start <= current.end {
+                current.end = current.end.max(next.end);
+                true
+            } else {
+                false
+            }
+        });
+        ranges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Get the file, and the inline origin, of each line and INLINE record.
+    fn get_names(info: &ObjectInfo) -> Vec<String> {
+        let files = info.files.get_mapping();
+        info.symbols
+            .values()
+            .flat_map(|sym| {
+                let lines = sym
+                    .source
+                    .lines
+                    .iter()
+                    .map(|line| files[line.file_id as usize].clone());
+                let inlines = sym.source.inlines.keys().map(|site| {
+                    format!(
+                        "{} {}",
+                        files[site.call_file_id as usize],
+                        info.inline_origins[site.inline_origin_id as usize]
+                    )
+                });
+                lines.chain(inlines).collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_prune_unreferenced() {
+        let buf = fs::read("./test_data/linux/basic.full").unwrap();
+        let mut info = ObjectInfo::from_elf(
+            &buf,
+            "basic.full",
+            Platform::Linux,
+            &CollectOptions {
+                collect_inlines: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let expected = info.to_string();
+        info.prune_unreferenced().unwrap();
+        assert_eq!(info.to_string(), expected);
+
+        // Add an unused file before the others.
+        let mut paths = vec!["unused.h".to_string()];
+        paths.extend(info.files.get_mapping().iter().cloned());
+        let file_remapping: Vec<u32> = (1..paths.len() as u32).collect();
+        let inline_origin_remapping: Vec<u32> = (0..info.inline_origins.len() as u32).collect();
+        info.files = SourceMap::from_paths(paths);
+        for sym in info.symbols.values_mut() {
+            sym.remap_lines(Some(&file_remapping));
+            sym.remap_inlines(Some(&file_remapping), &inline_origin_remapping);
+        }
+        assert!(info.to_string().contains("FILE 0 unused.h"));
+        info.prune_unreferenced().unwrap();
+        assert_eq!(info.to_string(), expected);
+
+        // Drop the functions with some inlines.
+        let origins = info.inline_origins.len();
+        info.symbols.retain(|_, sym| sym.source.inlines.is_empty());
+        let names = get_names(&info);
+        info.prune_unreferenced().unwrap();
+        assert_eq!(get_names(&info), names);
+        assert!(origins != 0 && info.inline_origins.is_empty());
+
+        // A dangling id.
+        let sym = info
+            .symbols
+            .values_mut()
+            .find(|sym| !sym.is_public)
+            .unwrap();
+        let name = sym.name.clone();
+        sym.source.lines[0].file_id = 7;
+        let err = info.prune_unreferenced().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("Unable to parse basic.full: Unknown FILE id 7 in {name}")
+        );
+    }
 }
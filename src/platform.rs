@@ -4,6 +4,7 @@
 // copied, modified, or distributed except according to those terms.
 
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Platform {
@@ -74,6 +75,19 @@ impl Display for Platform {
     }
 }
 
+impl FromStr for Platform {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "Linux" => Ok(Self::Linux),
+            "Mac" => Ok(Self::Mac),
+            "windows" => Ok(Self::Win),
+            _ => anyhow::bail!("Unknown platform: {}", s),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use crate::platform::Platform;
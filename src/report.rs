@@ -0,0 +1,314 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A summary of a dump, with the results for each input file.
+//!
+//! ```text
+//! {
+//!   "inputs": [{
+//!     "input": "libxul.so",
+//!     "error": null,
+//!     "modules": [{
+//!       "name": "libxul.so",
+//!       "debug_id": "20AD60B0B4C68177552708AA192E77390",
+//!       "code_id": "B060AD20C6B47781552708AA192E7739FAC7C84A",
+//!       "outputs": ["store/libxul.so/20AD60B0B4C68177552708AA192E77390/libxul.so.sym"],
+//!       "functions": 12, "publics": 3, "inlines": 7, "cfi": true, "cfi_coverage": 100.0,
+//!       "warnings": [],
+//!       "error": null
+//!     }]
+//!   }],
+//!   "failures": 0
+//! }
+//! ```
+//!
+//! A module made of several inputs (e.g. a PE file and its PDB file) is listed
+//! for each of them.
+
+use hashbrown::HashMap;
+use serde::Serialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::cfi_coverage::CfiCoverage;
+use crate::common;
+use crate::error::Error;
+use crate::object_info::ObjectInfo;
+
+#[derive(Debug, Default, Serialize)]
+pub struct Report {
+    pub inputs: Vec<InputReport>,
+    /// The number of inputs with an error, or with a module with an error
+    pub failures: usize,
+    #[serde(skip)]
+    error: Option<anyhow::Error>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InputReport {
+    pub input: String,
+    pub error: Option<String>,
+    pub modules: Vec<ModuleReport>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct ModuleReport {
+    pub name: String,
+    pub debug_id: String,
+    pub code_id: Option<String>,
+    /// The files where the symbols have been written
+    pub outputs: Vec<String>,
+    /// The number of FUNC records
+    pub functions: usize,
+    /// The number of PUBLIC records
+    pub publics: usize,
+    /// The number of INLINE records
+    pub inlines: usize,
+    /// Whether there are some STACK records
+    pub cfi: bool,
+    /// The percentage of the functions covered by some STACK records
+    pub cfi_coverage: Option<f64>,
+    pub warnings: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl ModuleReport {
+    /// The report of a module without its statistics (see
+    /// [`Self::add_stats`]): they're only required when the report is
+    /// written.
+    pub(crate) fn new(object_info: &ObjectInfo) -> Self {
+        Self {
+            name: object_info.get_name().to_string(),
+            debug_id: object_info.get_debug_id().to_string(),
+            code_id: object_info.code_id.clone(),
+            outputs: Vec::new(),
+            functions: 0,
+            publics: 0,
+            inlines: 0,
+            cfi: false,
+            cfi_coverage: None,
+            warnings: Vec::new(),
+            error: None,
+        }
+    }
+
+    /// Count the records of the module and add the warnings about its
+    /// debug information and its CFI data.
+    pub(crate) fn add_stats(&mut self, object_info: &ObjectInfo, coverage: &CfiCoverage) {
+        for sym in object_info.symbols.values() {
+            if sym.is_public {
+                self.publics += 1;
+            } else {
+                self.functions += 1;
+                self.inlines += sym.source.inlines.len();
+            }
+        }
+        self.cfi = object_info.has_stack();
+        self.cfi_coverage = Some(coverage.percent());
+
+        if self.functions == 0 {
+            self.warnings
+                .push("No debug information: only PUBLIC records".to_string());
+        }
+        if !self.cfi {
+            self.warnings.push("No CFI data".to_string());
+        } else if !coverage.uncovered.is_empty() {
+            self.warnings.push(format!(
+                "No CFI data for {} of {} functions",
+                coverage.uncovered.len(),
+                coverage.functions
+            ));
+        }
+    }
+
+    /// The report for a module which has been skipped because it's already
+    /// in the store directory.
+    pub(crate) fn stored(name: &str, debug_id: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            debug_id: debug_id.to_string(),
+            code_id: None,
+            outputs: Vec::new(),
+            functions: 0,
+            publics: 0,
+            inlines: 0,
+            cfi: false,
+            cfi_coverage: None,
+            warnings: vec!["Already in the store directory".to_string()],
+            error: None,
+        }
+    }
+}
+
+/// Collect the results of the inputs and of the modules, which can be made
+/// of several inputs (the ones with the same debug id).
+#[derive(Debug, Default)]
+pub(crate) struct ReportBuilder {
+    /// The inputs with the debug ids of their modules and their error
+    inputs: Vec<(String, Vec<String>, Option<String>)>,
+    modules: HashMap<String, ModuleReport>,
+    error: Option<anyhow::Error>,
+}
+
+impl ReportBuilder {
+    /// Add an input with the debug ids of its modules.
+    pub(crate) fn add_input(&mut self, input: &str, debug_ids: Vec<String>) {
+        self.inputs.push((input.to_string(), debug_ids, None));
+    }
+
+    /// Add an input which can't be dumped, or set the error of an input
+    /// which has already been added (e.g. when its modules can't be merged
+    /// with the ones of another input).
+    pub(crate) fn add_input_error(&mut self, input: &str, error: anyhow::Error) {
+        match self.inputs.iter_mut().find(|(name, _, _)| name == input) {
+            Some((_, _, message)) => {
+                message.get_or_insert_with(|| error.to_string());
+            }
+            None => self
+                .inputs
+                .push((input.to_string(), Vec::new(), Some(error.to_string()))),
+        }
+        self.error.get_or_insert(error);
+    }
+
+    /// Add the inputs of a module which can't be dumped.
+    pub(crate) fn add_inputs_error(
+        &mut self,
+        inputs: impl IntoIterator<Item = String>,
+        error: anyhow::Error,
+    ) {
+        let message = error.to_string();
+        self.inputs.extend(
+            inputs
+                .into_iter()
+                .map(|input| (input, Vec::new(), Some(message.clone()))),
+        );
+        self.error.get_or_insert(error);
+    }
+
+    pub(crate) fn add_module(&mut self, module: ModuleReport) {
+        self.modules.insert(module.debug_id.clone(), module);
+    }
+
+    /// Set the error of the module which has been added with `report`.
+    pub(crate) fn add_module_error(&mut self, mut report: ModuleReport, error: anyhow::Error) {
+        report.error = Some(error.to_string());
+        self.add_module(report);
+        self.error.get_or_insert(error);
+    }
+
+    pub(crate) fn finish(self) -> Report {
+        let modules = self.modules;
+        let mut inputs: Vec<_> = self
+            .inputs
+            .into_iter()
+            .map(|(input, debug_ids, error)| InputReport {
+                input,
+                error,
+                modules: debug_ids
+                    .iter()
+                    .filter_map(|id| modules.get(id).cloned())
+                    .collect(),
+            })
+            .collect();
+        // The inputs are dumped in parallel so they're added in any order.
+        inputs.sort_by(|a, b| a.input.cmp(&b.input));
+
+        let failures = inputs
+            .iter()
+            .filter(|i| i.error.is_some() || i.modules.iter().any(|m| m.error.is_some()))
+            .count();
+
+        Report {
+            inputs,
+            failures,
+            error: self.error,
+        }
+    }
+}
+
+impl Report {
+    /// Write the report as a JSON document.
+    pub fn write<W: Write>(&self, writer: W) -> common::Result<()> {
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    pub fn write_to_file<P: AsRef<Path>>(&self, path: P) -> common::Result<()> {
+        let path = path.as_ref();
+        let file = File::create(path).map_err(|e| Error::io(path, e))?;
+        let mut writer = BufWriter::new(file);
+        self.write(&mut writer)?;
+        writer.flush().map_err(|e| Error::io(path, e))?;
+        Ok(())
+    }
+
+    /// Get the files written for all the modules, without duplicates.
+    pub fn outputs(&self) -> Vec<&str> {
+        let mut outputs: Vec<_> = self
+            .inputs
+            .iter()
+            .flat_map(|input| input.modules.iter())
+            .flat_map(|module| module.outputs.iter().map(String::as_str))
+            .collect();
+        outputs.sort_unstable();
+        outputs.dedup();
+        outputs
+    }
+
+    /// Get an error if some inputs failed (its kind is the one of the first
+    /// failure).
+    pub fn into_result(self) -> common::Result<Self> {
+        if self.failures == 0 {
+            return Ok(self);
+        }
+        if self.inputs.len() == 1 {
+            if let Some(error) = self.error {
+                return Err(error);
+            }
+        }
+        let message = format!(
+            "{} of {} input{} failed",
+            self.failures,
+            self.inputs.len(),
+            if self.inputs.len() == 1 { "" } else { "s" }
+        );
+        Err(match self.error {
+            Some(error) => error.context(message),
+            None => anyhow::anyhow!(message),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_error() {
+        let mut builder = ReportBuilder::default();
+        builder.add_input("xul.dll", vec!["A".to_string()]);
+        builder.add_input("xul.pdb", vec!["A".to_string()]);
+        builder.add_input_error("xul.pdb", anyhow::anyhow!("Unable to merge"));
+        builder.add_input_error("xul.exe", anyhow::anyhow!("Unable to read"));
+
+        let report = builder.finish();
+        let inputs: Vec<_> = report
+            .inputs
+            .iter()
+            .map(|i| (i.input.as_str(), i.error.as_deref()))
+            .collect();
+        assert_eq!(
+            inputs,
+            [
+                ("xul.dll", None),
+                ("xul.exe", Some("Unable to read")),
+                ("xul.pdb", Some("Unable to merge"))
+            ]
+        );
+        assert_eq!(report.failures, 2);
+    }
+}
@@ -0,0 +1,147 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Check that the output is reproducible: the files are dumped twice in some
+//! temporary store directories, with one job and then with several ones and
+//! the files in the reverse order, and the two directories must be the same.
+
+use log::{error, info};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::common;
+use crate::dumper::{self, Config, Incremental, Output};
+use crate::error::Error;
+
+type Files = BTreeMap<PathBuf, Vec<u8>>;
+
+/// Read the files in `dir`, by path relative to `root`.
+fn read_files(root: &Path, dir: &Path, files: &mut Files) -> common::Result<()> {
+    for entry in fs::read_dir(dir).map_err(|e| Error::io(dir, e))? {
+        let path = entry.map_err(|e| Error::io(dir, e))?.path();
+        if path.is_dir() {
+            read_files(root, &path, files)?;
+        } else {
+            let data = fs::read(&path).map_err(|e| Error::io(&path, e))?;
+            files.insert(path.strip_prefix(root).unwrap().to_path_buf(), data);
+        }
+    }
+    Ok(())
+}
+
+fn dump(config: &Config, filenames: &[&str], num_jobs: usize) -> common::Result<Files> {
+    let dir = tempfile::Builder::new()
+        .prefix("dump_syms")
+        .tempdir()
+        .map_err(|e| Error::io(std::env::temp_dir(), e))?;
+    let config = Config {
+        output: Output::Store(dir.path().to_path_buf()),
+        incremental: Incremental::Off,
+        num_jobs,
+        report: None,
+        ..config.clone()
+    };
+
+    if let [filename] = filenames {
+        dumper::single_file(&config, filename)?;
+    } else {
+        dumper::several_files(&config, filenames)?;
+    }
+
+    let mut files = Files::new();
+    read_files(dir.path(), dir.path(), &mut files)?;
+    Ok(files)
+}
+
+/// Describe the differences between two dumps.
+fn get_differences(first: &Files, second: &Files) -> Vec<String> {
+    let mut differences = Vec::new();
+    for (path, data) in first {
+        let other = match second.get(path) {
+            Some(other) => other,
+            None => {
+                differences.push(format!("{} is only in the first dump", path.display()));
+                continue;
+            }
+        };
+        if data == other {
+            continue;
+        }
+        let lines = || data.split(|c| *c == b'\n');
+        let other_lines = || other.split(|c| *c == b'\n');
+        // When a file is a prefix of the other, the first extra line differs.
+        let line = lines()
+            .zip(other_lines())
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| lines().count().min(other_lines().count()));
+        differences.push(format!("{} differs from line {}", path.display(), line + 1));
+    }
+    for path in second.keys().filter(|path| !first.contains_key(*path)) {
+        differences.push(format!("{} is only in the second dump", path.display()));
+    }
+    differences
+}
+
+/// Dump the files twice, with one job and with `config.num_jobs` ones (at
+/// least 2) in the reverse order, and fail if the outputs aren't the same.
+///
+/// The output of `config` isn't used.
+pub fn check(config: &Config, filenames: &[&str]) -> common::Result<()> {
+    let first = dump(config, filenames, 1)?;
+    let reversed: Vec<_> = filenames.iter().rev().copied().collect();
+    let second = dump(config, &reversed, config.num_jobs.max(2))?;
+
+    let differences = get_differences(&first, &second);
+    for difference in differences.iter() {
+        error!("{difference}");
+    }
+    if !differences.is_empty() {
+        return Err(Error::NotReproducible(format!(
+            "{} of {} files differ",
+            differences.len(),
+            first.len().max(second.len())
+        ))
+        .into());
+    }
+
+    info!("Both dumps are the same ({} files)", first.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_differences() {
+        let first: Files = vec![
+            (PathBuf::from("a.sym"), b"MODULE a\nFILE 0 a.c\n".to_vec()),
+            (PathBuf::from("b.sym"), b"MODULE b\n".to_vec()),
+            (PathBuf::from("d.sym"), b"MODULE d\nFILE 0 d.c".to_vec()),
+        ]
+        .into_iter()
+        .collect();
+        let mut second = first.clone();
+        assert!(get_differences(&first, &second).is_empty());
+
+        second.insert(PathBuf::from("a.sym"), b"MODULE a\nFILE 0 b.c\n".to_vec());
+        second.remove(Path::new("b.sym"));
+        second.insert(PathBuf::from("c.sym"), Vec::new());
+        second.insert(
+            PathBuf::from("d.sym"),
+            b"MODULE d\nFILE 0 d.c\nFILE 1 e.c".to_vec(),
+        );
+        assert_eq!(
+            get_differences(&first, &second),
+            [
+                "a.sym differs from line 2",
+                "b.sym is only in the first dump",
+                "d.sym differs from line 3",
+                "c.sym is only in the second dump",
+            ]
+        );
+    }
+}
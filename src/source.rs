@@ -14,7 +14,8 @@ use crate::mapping::PathMappings;
 use crate::platform::Platform;
 use crate::utils;
 
-type SliceRef = (*const u8, usize);
+/// The address and the length of a slice.
+type SliceRef = (usize, usize);
 
 #[derive(Debug)]
 pub struct SourceFiles {
@@ -52,7 +53,7 @@ impl SourceFiles {
 
     #[inline(always)]
     fn cast_ptr(name: &[u8]) -> SliceRef {
-        (name.as_ptr(), name.len())
+        (name.as_ptr() as usize, name.len())
     }
 
     #[inline(always)]
@@ -132,6 +133,49 @@ impl SourceFiles {
         }
     }
 
+    /// Get an empty set of files with the same mapping.
+    pub(super) fn new_empty(&self) -> Self {
+        Self::new(self.mapping.clone(), self.platform, self.canonicalize)
+    }
+
+    /// Append the files of `other`, collected from the functions following
+    /// the ones of `self`, and get the new ids of its files.
+    ///
+    /// The ids are the same as if the functions had been collected in `self`.
+    pub(super) fn merge(&mut self, other: SourceFiles) -> Vec<u32> {
+        let mut paths = vec![""; other.fake_id_to_ref.len()];
+        for (path, id) in other.ref_to_id.iter() {
+            paths[*id as usize] = path;
+        }
+
+        let mut true_to_fake = vec![0; other.id_to_ref.len()];
+        let ref_to_id = &mut self.ref_to_id;
+        let fake_id_to_ref = &mut self.fake_id_to_ref;
+        let fake_remapping: Vec<u32> = paths
+            .into_iter()
+            .zip(other.fake_id_to_ref)
+            .enumerate()
+            .map(|(fake_id, (path, (true_id, new_path)))| {
+                if let Some(true_id) = true_id {
+                    true_to_fake[true_id as usize] = fake_id;
+                }
+                match ref_to_id.entry(path.to_string()) {
+                    hash_map::Entry::Occupied(e) => *e.get(),
+                    hash_map::Entry::Vacant(e) => {
+                        let id = fake_id_to_ref.len() as u32;
+                        fake_id_to_ref.push((None, new_path));
+                        *e.insert(id)
+                    }
+                }
+            })
+            .collect();
+
+        true_to_fake
+            .into_iter()
+            .map(|fake_id| self.get_true_id(fake_remapping[fake_id]))
+            .collect()
+    }
+
     // Some file_ids are not consumed because they correspond to some inlinee stuff
     // So in order to have consecutive ids just return an id for really used files
     pub(super) fn get_true_id(&mut self, fake_id: u32) -> u32 {
@@ -147,18 +191,46 @@ impl SourceFiles {
     }
 
     pub(super) fn get_mapping(self) -> SourceMap {
-        SourceMap {
-            ref_to_id: self.ref_to_id,
-            id_to_ref: self.id_to_ref,
-        }
+        // The ids in `self.ref_to_id` are the fake ones for the unmapped
+        // paths so the map is built again from the used files.
+        SourceMap::from_paths(self.id_to_ref)
     }
 }
 
 impl SourceMap {
+    pub(super) fn from_paths(id_to_ref: Vec<String>) -> Self {
+        let mut ref_to_id = HashMap::default();
+        for (id, path) in id_to_ref.iter().enumerate() {
+            ref_to_id.entry(path.clone()).or_insert(id as u32);
+        }
+        SourceMap {
+            ref_to_id,
+            id_to_ref,
+        }
+    }
+
     pub(super) fn get_mapping(&self) -> &[String] {
         &self.id_to_ref
     }
 
+    /// Remove the files which aren't `used` and get the new ids (the removed
+    /// ones are mapped to `u32::MAX`).
+    pub(super) fn prune(&mut self, used: &[bool]) -> Vec<u32> {
+        let paths = std::mem::take(&mut self.id_to_ref);
+        let mut remapping = Vec::with_capacity(paths.len());
+        let mut id_to_ref = Vec::with_capacity(paths.len());
+        for (path, &used) in paths.into_iter().zip(used) {
+            if used {
+                remapping.push(id_to_ref.len() as u32);
+                id_to_ref.push(path);
+            } else {
+                remapping.push(u32::MAX);
+            }
+        }
+        *self = Self::from_paths(id_to_ref);
+        remapping
+    }
+
     pub(super) fn merge(&mut self, other: &mut SourceMap) -> Option<Vec<u32>> {
         // No FUNC so nothing to do
         if other.id_to_ref.is_empty() {
@@ -173,20 +245,22 @@ impl SourceMap {
             return None;
         }
 
-        // will contain the new ids
-        let mut remapping = vec![0; other.id_to_ref.len()];
+        // will contain the new ids: the new files are added in the order of
+        // their ids so the result doesn't depend on the hash map
+        let mut remapping = Vec::with_capacity(other.id_to_ref.len());
         self.id_to_ref.reserve(other.id_to_ref.len());
 
-        for (path, id) in other.ref_to_id.iter() {
-            let id = *id as usize;
-            if let Some(an_id) = self.ref_to_id.get(path) {
+        for path in other.id_to_ref.iter() {
+            let id = match self.ref_to_id.entry(path.clone()) {
                 // self has already this path so map the id to the existing one
-                remapping[id] = *an_id;
-            } else {
-                let new_id = self.id_to_ref.len() as u32;
-                remapping[id] = new_id;
-                self.id_to_ref.push(other.id_to_ref[id].clone());
-            }
+                hash_map::Entry::Occupied(e) => *e.get(),
+                hash_map::Entry::Vacant(e) => {
+                    let new_id = self.id_to_ref.len() as u32;
+                    self.id_to_ref.push(path.clone());
+                    *e.insert(new_id)
+                }
+            };
+            remapping.push(id);
         }
 
         Some(remapping)
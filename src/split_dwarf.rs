@@ -0,0 +1,607 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Support for the split DWARF (`-gsplit-dwarf`).
+//!
+//! The main file only contains skeleton units with the line tables and the
+//! addresses, the functions and their inlinees are described in `.dwo` files
+//! (one per compilation unit) or in a `.dwp` package. symbolic doesn't read
+//! them so the functions are built here in the same way it does.
+
+use gimli::{
+    constants, AttributeValue, DebuggingInformationEntry, DwarfPackageSections, DwarfSections,
+    DwoId, EndianSlice, EntriesTreeNode, RunTimeEndian, SectionId, Unit, UnitOffset,
+};
+use log::{error, warn};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use symbolic::common::{Language, Name, NameMangling};
+use symbolic::debuginfo::dwarf::Dwarf as _;
+use symbolic::debuginfo::elf::ElfObject;
+use symbolic::debuginfo::function_builder::FunctionBuilder;
+use symbolic::debuginfo::{FileInfo, Function, Object};
+
+use crate::common;
+
+type Slice<'d> = EndianSlice<'d, RunTimeEndian>;
+type Dwarf<'d> = gimli::Dwarf<Slice<'d>>;
+
+// Avoid an infinite recursion with some bogus DW_AT_abstract_origin.
+const MAX_NAME_DEPTH: usize = 16;
+
+fn load_section(elf: &ElfObject, name: Option<&str>) -> Vec<u8> {
+    name.and_then(|name| elf.section(&name[1..]))
+        .map(|section| section.data.into_owned())
+        .unwrap_or_default()
+}
+
+fn parse_elf(buf: &[u8]) -> Option<ElfObject<'_>> {
+    match Object::parse(buf) {
+        Ok(Object::Elf(elf)) => Some(elf),
+        _ => None,
+    }
+}
+
+/// The DWARF data of a file using the split DWARF.
+pub(crate) struct SplitDwarf {
+    endian: RunTimeEndian,
+    load_address: u64,
+    main: DwarfSections<Vec<u8>>,
+    dwp: Option<DwarfPackageSections<Vec<u8>>>,
+    dwos: Vec<DwarfSections<Vec<u8>>>,
+}
+
+/// The name of a `.dwo` file and the compilation directory of its unit.
+struct DwoName {
+    name: String,
+    comp_dir: Option<String>,
+}
+
+impl SplitDwarf {
+    /// Read the skeleton units of the ELF file, `None` if it doesn't use the
+    /// split DWARF.
+    fn skeleton(elf: &ElfObject) -> Option<(Self, Vec<DwoName>)> {
+        let endian = elf.endianity();
+        let main: DwarfSections<Vec<u8>> =
+            DwarfSections::load(|id| Ok::<_, gimli::Error>(load_section(elf, Some(id.name()))))
+                .ok()?;
+
+        let dwo_names = {
+            let dwarf = main.borrow(|s| EndianSlice::new(s, endian));
+            let mut dwo_names = Vec::new();
+            let mut headers = dwarf.units();
+            while let Ok(Some(header)) = headers.next() {
+                let Ok(unit) = dwarf.unit(header) else {
+                    continue;
+                };
+                if unit.dwo_id.is_none() {
+                    continue;
+                }
+                let name = unit
+                    .dwo_name()
+                    .ok()
+                    .flatten()
+                    .and_then(|name| dwarf.attr_string(&unit, name).ok());
+                if let Some(name) = name {
+                    dwo_names.push(DwoName {
+                        name: name.to_string_lossy().into_owned(),
+                        comp_dir: unit.comp_dir.map(|d| d.to_string_lossy().into_owned()),
+                    });
+                }
+            }
+            dwo_names
+        };
+
+        if dwo_names.is_empty() {
+            return None;
+        }
+
+        let split = Self {
+            endian,
+            load_address: elf.load_address(),
+            main,
+            dwp: None,
+            dwos: Vec::new(),
+        };
+        Some((split, dwo_names))
+    }
+
+    /// Use `buf` as the `.dwp` package, if it's one.
+    fn add_dwp(&mut self, buf: &[u8]) -> bool {
+        let Some(dwp) = parse_elf(buf).filter(|dwp| dwp.section("debug_cu_index").is_some()) else {
+            return false;
+        };
+        self.dwp = DwarfPackageSections::load(|id: SectionId| {
+            Ok::<_, gimli::Error>(load_section(&dwp, id.dwo_name()))
+        })
+        .ok();
+        self.dwp.is_some()
+    }
+
+    /// Add `buf` to the `.dwo` files, if it's one.
+    fn add_dwo(&mut self, buf: &[u8]) -> bool {
+        let Some(dwo) = parse_elf(buf).filter(|dwo| dwo.section("debug_info.dwo").is_some()) else {
+            return false;
+        };
+        let sections = DwarfSections::load(|id: SectionId| {
+            Ok::<_, gimli::Error>(load_section(&dwo, id.dwo_name()))
+        });
+        match sections {
+            Ok(sections) => {
+                self.dwos.push(sections);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Load the `.dwp` package next to the ELF file at `path` or the `.dwo`
+    /// files for its skeleton units.
+    ///
+    /// The `.dwo` files are looked up in the compilation directory and then
+    /// in the directory of the ELF file.
+    pub(crate) fn load(elf: &ElfObject, path: Option<&Path>) -> Option<Self> {
+        let (mut split, dwo_names) = Self::skeleton(elf)?;
+
+        let dir = path.and_then(Path::parent);
+        if let Some(path) = path {
+            let mut dwp_path = path.as_os_str().to_owned();
+            dwp_path.push(".dwp");
+            if let Ok(buf) = std::fs::read(&dwp_path) {
+                if split.add_dwp(&buf) {
+                    return Some(split);
+                }
+            }
+        }
+
+        for DwoName { name, comp_dir } in dwo_names {
+            let name = PathBuf::from(name);
+            let mut candidates = Vec::new();
+            if let Some(comp_dir) = comp_dir {
+                candidates.push(PathBuf::from(comp_dir).join(&name));
+            }
+            if let Some(dir) = dir {
+                candidates.push(dir.join(&name));
+                if let Some(file_name) = name.file_name() {
+                    candidates.push(dir.join(file_name));
+                }
+            }
+
+            let found = candidates
+                .iter()
+                .any(|path| std::fs::read(path).is_ok_and(|buf| split.add_dwo(&buf)));
+            if !found {
+                warn!("Unable to find the dwo file {}", name.display());
+            }
+        }
+
+        Some(split)
+    }
+
+    /// Same as [`SplitDwarf::load`] but the `.dwp` package or the `.dwo` files
+    /// are taken from `bufs` (the other buffers are ignored).
+    pub(crate) fn from_buffers(elf: &ElfObject, bufs: &[&[u8]]) -> Option<Self> {
+        let (mut split, _) = Self::skeleton(elf)?;
+        for buf in bufs {
+            if !split.add_dwp(buf) {
+                split.add_dwo(buf);
+            }
+        }
+        Some(split)
+    }
+
+    /// Get the functions described in the `.dwo` files or the `.dwp` package.
+    pub(crate) fn functions<'s>(&'s self) -> Vec<Function<'s>> {
+        let endian = self.endian;
+        let borrow = |s: &'s Vec<u8>| -> Slice<'s> { EndianSlice::new(s.as_slice(), endian) };
+        let main = self.main.borrow(borrow);
+        let dwp = self.dwp.as_ref().and_then(|dwp| {
+            dwp.borrow(borrow, EndianSlice::new(&[], self.endian))
+                .map_err(|e| error!("Invalid dwp file: {e}"))
+                .ok()
+        });
+        let dwos: Vec<(DwoId, Dwarf)> = self
+            .dwos
+            .iter()
+            .filter_map(|dwo| {
+                let mut dwo = dwo.borrow(borrow);
+                dwo.make_dwo(&main);
+                let header = dwo.units().next().ok()??;
+                let id = dwo.unit(header).ok()?.dwo_id?;
+                Some((id, dwo))
+            })
+            .collect();
+
+        let mut functions = Vec::new();
+        let mut headers = main.units();
+        while let Ok(Some(header)) = headers.next() {
+            let Ok(skeleton) = main.unit(header) else {
+                continue;
+            };
+            let Some(dwo_id) = skeleton.dwo_id else {
+                continue;
+            };
+
+            let from_dwp;
+            let dwo = if let Some(dwp) = dwp.as_ref() {
+                from_dwp = dwp.find_cu(dwo_id, &main).ok().flatten();
+                from_dwp.as_ref()
+            } else {
+                dwos.iter()
+                    .find(|(id, _)| *id == dwo_id)
+                    .map(|(_, dwo)| dwo)
+            };
+            let Some(dwo) = dwo else {
+                continue;
+            };
+
+            if let Err(e) = self.unit_functions(&main, &skeleton, dwo, &mut functions) {
+                error!("Split DWARF: {e}");
+            }
+        }
+
+        functions
+    }
+
+    fn unit_functions<'d>(
+        &self,
+        main: &Dwarf<'d>,
+        skeleton: &Unit<Slice<'d>>,
+        dwo: &Dwarf<'d>,
+        functions: &mut Vec<Function<'d>>,
+    ) -> common::Result<()> {
+        let header = dwo
+            .units()
+            .next()?
+            .ok_or_else(|| anyhow::anyhow!("No unit in the dwo"))?;
+        let mut unit = dwo.unit(header)?;
+        unit.copy_relocated_attributes(skeleton);
+
+        let walker = UnitWalker {
+            main,
+            skeleton,
+            dwo,
+            unit: &unit,
+            language: get_language(&unit),
+            rows: get_rows(skeleton),
+            load_address: self.load_address,
+        };
+        let mut tree = unit.entries_tree(None)?;
+        walker.walk(tree.root()?, functions)?;
+
+        Ok(())
+    }
+}
+
+fn get_language(unit: &Unit<Slice>) -> Language {
+    let mut entries = unit.entries();
+    let lang = match entries.next_dfs() {
+        Ok(Some((_, entry))) => entry.attr_value(constants::DW_AT_language).ok().flatten(),
+        _ => None,
+    };
+    match lang {
+        Some(AttributeValue::Language(lang)) => match lang {
+            constants::DW_LANG_C
+            | constants::DW_LANG_C89
+            | constants::DW_LANG_C99
+            | constants::DW_LANG_C11
+            | constants::DW_LANG_C17 => Language::C,
+            constants::DW_LANG_C_plus_plus
+            | constants::DW_LANG_C_plus_plus_03
+            | constants::DW_LANG_C_plus_plus_11
+            | constants::DW_LANG_C_plus_plus_14
+            | constants::DW_LANG_C_plus_plus_17
+            | constants::DW_LANG_C_plus_plus_20 => Language::Cpp,
+            constants::DW_LANG_ObjC => Language::ObjC,
+            constants::DW_LANG_ObjC_plus_plus => Language::ObjCpp,
+            constants::DW_LANG_Rust => Language::Rust,
+            constants::DW_LANG_Swift => Language::Swift,
+            _ => Language::Unknown,
+        },
+        _ => Language::Unknown,
+    }
+}
+
+/// A row of the line table.
+struct Row {
+    address: u64,
+    size: Option<u64>,
+    file: u64,
+    line: u64,
+}
+
+/// Get the rows of the line table of the skeleton unit, sorted by address.
+fn get_rows(skeleton: &Unit<Slice>) -> Vec<Row> {
+    let program = match skeleton.line_program.clone() {
+        Some(program) => program,
+        None => return Vec::new(),
+    };
+
+    let mut rows: Vec<Row> = Vec::new();
+    // The index of the first row of the current sequence.
+    let mut start = 0;
+    let mut state_machine = program.rows();
+    while let Ok(Some((_, row))) = state_machine.next_row() {
+        let address = row.address();
+        if address == 0 {
+            continue;
+        }
+        if let Some(last) = rows[start..].last_mut() {
+            if address >= last.address {
+                last.size = Some(address - last.address);
+            }
+            if row.end_sequence() {
+                start = rows.len();
+                continue;
+            }
+            if address == last.address {
+                last.file = row.file_index();
+                last.line = row.line().map_or(0, |l| l.get());
+                continue;
+            }
+            if address < last.address {
+                continue;
+            }
+        } else if row.end_sequence() {
+            continue;
+        }
+        rows.push(Row {
+            address,
+            size: None,
+            file: row.file_index(),
+            line: row.line().map_or(0, |l| l.get()),
+        });
+    }
+
+    rows.sort_by_key(|row| row.address);
+    rows
+}
+
+struct UnitWalker<'a, 'd> {
+    main: &'a Dwarf<'d>,
+    skeleton: &'a Unit<Slice<'d>>,
+    dwo: &'a Dwarf<'d>,
+    unit: &'a Unit<Slice<'d>>,
+    language: Language,
+    rows: Vec<Row>,
+    load_address: u64,
+}
+
+type Entry<'a, 'd> = DebuggingInformationEntry<'a, 'a, Slice<'d>>;
+type Node<'a, 'b, 'd> = EntriesTreeNode<'a, 'a, 'b, Slice<'d>>;
+
+impl<'a, 'd> UnitWalker<'a, 'd> {
+    fn compilation_dir(&self) -> &'d [u8] {
+        self.skeleton.comp_dir.map_or(&[], |d| d.slice())
+    }
+
+    fn file_info(&self, index: u64) -> FileInfo<'d> {
+        let Some(program) = self.skeleton.line_program.as_ref() else {
+            return FileInfo::default();
+        };
+        let header = program.header();
+        let Some(file) = header.file(index) else {
+            return FileInfo::default();
+        };
+        let string = |attr: Option<AttributeValue<Slice<'d>>>| -> &'d [u8] {
+            attr.and_then(|attr| self.main.attr_string(self.skeleton, attr).ok())
+                .map_or(&[], |s| s.slice())
+        };
+        FileInfo::new(
+            Cow::Borrowed(string(file.directory(header))),
+            Cow::Borrowed(string(Some(file.path_name()))),
+        )
+    }
+
+    fn string(&self, value: AttributeValue<Slice<'d>>) -> Option<Cow<'d, str>> {
+        let s = self.dwo.attr_string(self.unit, value).ok()?;
+        Some(String::from_utf8_lossy(s.slice()))
+    }
+
+    fn name(&self, entry: &Entry<'_, 'd>, depth: usize) -> Option<Name<'d>> {
+        let mut name = None;
+        let mut reference = None;
+        let mut attrs = entry.attrs();
+        while let Ok(Some(attr)) = attrs.next() {
+            match attr.name() {
+                constants::DW_AT_linkage_name | constants::DW_AT_MIPS_linkage_name => {
+                    return self
+                        .string(attr.value())
+                        .map(|n| Name::new(n, NameMangling::Mangled, self.language));
+                }
+                constants::DW_AT_name => name = Some(attr.value()),
+                constants::DW_AT_abstract_origin | constants::DW_AT_specification => {
+                    reference = Some(attr.value())
+                }
+                _ => {}
+            }
+        }
+
+        if let Some(name) = name {
+            return self
+                .string(name)
+                .map(|n| Name::new(n, NameMangling::Unmangled, self.language));
+        }
+
+        match reference {
+            Some(AttributeValue::UnitRef(offset)) if depth < MAX_NAME_DEPTH => {
+                self.name_at(offset, depth + 1)
+            }
+            _ => None,
+        }
+    }
+
+    fn name_at(&self, offset: UnitOffset, depth: usize) -> Option<Name<'d>> {
+        let mut entries = self.unit.entries_at_offset(offset).ok()?;
+        let (_, entry) = entries.next_dfs().ok()??;
+        self.name(entry, depth)
+    }
+
+    fn ranges(&self, entry: &Entry<'_, 'd>) -> common::Result<Vec<gimli::Range>> {
+        let mut ranges = Vec::new();
+        let mut iter = self.dwo.die_ranges(self.unit, entry)?;
+        while let Some(range) = iter.next()? {
+            // A range at 0 is some code eliminated by the linker.
+            if range.begin != 0 && range.begin < range.end {
+                ranges.push(range);
+            }
+        }
+        Ok(ranges)
+    }
+
+    /// Collect the functions in the children of `node`.
+    fn walk(
+        &self,
+        node: Node<'a, '_, 'd>,
+        functions: &mut Vec<Function<'d>>,
+    ) -> common::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            if child.entry().tag() == constants::DW_TAG_subprogram {
+                self.function(child, functions)?;
+            } else {
+                self.walk(child, functions)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn function(
+        &self,
+        node: Node<'a, '_, 'd>,
+        functions: &mut Vec<Function<'d>>,
+    ) -> common::Result<()> {
+        let ranges = self.ranges(node.entry())?;
+        if ranges.is_empty() {
+            return self.walk(node, functions);
+        }
+
+        let name = self
+            .name(node.entry(), 0)
+            .unwrap_or_else(|| Name::new("", NameMangling::Unmangled, self.language));
+        // The ranges below the load address (e.g. in some invalid DWARF) are
+        // skipped.
+        let mut builders: Vec<_> = ranges
+            .into_iter()
+            .filter_map(|range| {
+                let builder = FunctionBuilder::new(
+                    name.clone(),
+                    self.compilation_dir(),
+                    range.begin.checked_sub(self.load_address)?,
+                    range.end - range.begin,
+                );
+                Some((range, builder))
+            })
+            .collect();
+
+        self.inlinees(node, 0, &mut builders, functions)?;
+
+        for (range, builder) in builders.iter_mut() {
+            // Start with the row containing the beginning of the function.
+            let from = self
+                .rows
+                .partition_point(|row| row.address <= range.begin)
+                .saturating_sub(1);
+            for row in self.rows[from..]
+                .iter()
+                .take_while(|row| row.address < range.end)
+            {
+                let Some(address) = row.address.checked_sub(self.load_address) else {
+                    continue;
+                };
+                builder.add_leaf_line(address, row.size, self.file_info(row.file), row.line);
+            }
+        }
+
+        functions.extend(builders.into_iter().map(|(_, builder)| builder.finish()));
+        Ok(())
+    }
+
+    fn inlinees(
+        &self,
+        node: Node<'a, '_, 'd>,
+        depth: u32,
+        builders: &mut [(gimli::Range, FunctionBuilder<'d>)],
+        functions: &mut Vec<Function<'d>>,
+    ) -> common::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            match child.entry().tag() {
+                constants::DW_TAG_subprogram => self.function(child, functions)?,
+                constants::DW_TAG_inlined_subroutine => {
+                    let entry = child.entry();
+                    let ranges = self.ranges(entry)?;
+                    if ranges.is_empty() {
+                        self.walk(child, functions)?;
+                        continue;
+                    }
+
+                    let name = self
+                        .name(entry, 0)
+                        .unwrap_or_else(|| Name::new("", NameMangling::Unmangled, self.language));
+                    let call_file = match entry.attr_value(constants::DW_AT_call_file)? {
+                        Some(AttributeValue::FileIndex(index)) => self.file_info(index),
+                        Some(AttributeValue::Udata(index)) => self.file_info(index),
+                        _ => FileInfo::default(),
+                    };
+                    let call_line = match entry.attr_value(constants::DW_AT_call_line)? {
+                        Some(AttributeValue::Udata(line)) => line,
+                        _ => 0,
+                    };
+
+                    for range in ranges {
+                        let builder = builders.iter_mut().find(|(outer, _)| {
+                            range.begin >= outer.begin && range.begin < outer.end
+                        });
+                        let address = range.begin.checked_sub(self.load_address);
+                        if let (Some((_, builder)), Some(address)) = (builder, address) {
+                            builder.add_inlinee(
+                                depth,
+                                name.clone(),
+                                address,
+                                range.end - range.begin,
+                                call_file.clone(),
+                                call_line,
+                            );
+                        }
+                    }
+
+                    self.inlinees(child, depth + 1, builders, functions)?;
+                }
+                _ => self.inlinees(child, depth, builders, functions)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_below_load_address() {
+        let elf_buf = fs::read("./test_data/linux/basic.split").unwrap();
+        let dwp = fs::read("./test_data/linux/basic.split.dwp").unwrap();
+        let elf = parse_elf(&elf_buf).unwrap();
+        let mut split = SplitDwarf::from_buffers(&elf, &[&dwp]).unwrap();
+
+        let mut addresses: Vec<_> = split.functions().iter().map(|f| f.address).collect();
+        addresses.sort_unstable();
+        assert!(addresses.len() > 1);
+
+        // The functions below the load address are skipped.
+        let shift = addresses[1];
+        split.load_address += shift;
+        let mut shifted: Vec<_> = split.functions().iter().map(|f| f.address).collect();
+        shifted.sort_unstable();
+        let expected: Vec<_> = addresses
+            .iter()
+            .filter_map(|address| address.checked_sub(shift))
+            .collect();
+        assert_eq!(shifted, expected);
+    }
+}
@@ -0,0 +1,270 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! The STACK records of a module.
+//!
+//! The CFI of a big library (e.g. libxul) is hundreds of megabytes of text, so
+//! the records are written in an anonymous temporary file once they exceed
+//! [`MAX_BUFFERED_SIZE`] bytes and they're copied from there when the symbols
+//! are written. The records of the modules which are merged (e.g. a binary and
+//! its debug file) are read back in memory.
+//!
+//! Only the STACK records are kept out of memory. The FUNC records aren't
+//! streamed: the symbols, with their lines and inlines, the source files and
+//! the inline origins of a module are all collected before anything is
+//! written, since the publics, the `m` flags and the FILE ids are only known
+//! once all the functions have been collected.
+
+use std::borrow::Cow;
+use std::fmt::{self, Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+
+/// The size of the records kept in memory.
+pub(crate) const MAX_BUFFERED_SIZE: usize = 16 * 1024 * 1024;
+
+#[derive(Debug)]
+pub(crate) enum Stack {
+    Buffered(String),
+    /// The records are in an anonymous temporary file
+    File(File),
+}
+
+impl Default for Stack {
+    fn default() -> Self {
+        Self::Buffered(String::new())
+    }
+}
+
+impl From<String> for Stack {
+    fn from(stack: String) -> Self {
+        Self::Buffered(stack)
+    }
+}
+
+/// Write the records in memory and then in a temporary file when they become
+/// too big.
+pub(crate) struct StackWriter {
+    buf: Vec<u8>,
+    file: Option<File>,
+    max_buffered_size: usize,
+}
+
+impl StackWriter {
+    pub(crate) fn new() -> Self {
+        Self::with_max_buffered_size(MAX_BUFFERED_SIZE)
+    }
+
+    pub(crate) fn with_max_buffered_size(max_buffered_size: usize) -> Self {
+        Self {
+            buf: Vec::new(),
+            file: None,
+            max_buffered_size,
+        }
+    }
+
+    fn spill(&mut self) -> io::Result<()> {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => self.file.insert(tempfile::tempfile()?),
+        };
+        file.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<Stack> {
+        if self.file.is_none() {
+            let stack = String::from_utf8(self.buf)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            return Ok(Stack::Buffered(stack));
+        }
+        self.spill()?;
+        Ok(Stack::File(self.file.take().unwrap()))
+    }
+}
+
+impl Write for StackWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(data);
+        if self.buf.len() >= self.max_buffered_size {
+            self.spill()?;
+        }
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Stack {
+    pub(crate) fn is_empty(&self) -> bool {
+        match self {
+            Self::Buffered(stack) => stack.is_empty(),
+            Self::File(file) => file.metadata().map_or(true, |m| m.len() == 0),
+        }
+    }
+
+    /// Get a reader on the records of the file from its beginning.
+    fn read_file(mut file: &File) -> io::Result<BufReader<&File>> {
+        file.seek(SeekFrom::Start(0))?;
+        Ok(BufReader::new(file))
+    }
+
+    /// Get the lines (the file is read line by line).
+    pub(crate) fn lines(&self) -> io::Result<Lines<'_>> {
+        Ok(match self {
+            Self::Buffered(stack) => Lines::Buffered(stack.lines()),
+            Self::File(file) => Lines::File(Self::read_file(file)?.lines()),
+        })
+    }
+
+    /// Call `f` on each line (the file is read line by line).
+    pub(crate) fn for_each_line<F: FnMut(&str)>(&self, mut f: F) -> io::Result<()> {
+        for line in self.lines()? {
+            f(&line?);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        match self {
+            Self::Buffered(stack) => writer.write_all(stack.as_bytes()),
+            Self::File(file) => io::copy(&mut Self::read_file(file)?, &mut writer).map(|_| ()),
+        }
+    }
+
+    /// Get the records in memory.
+    pub(crate) fn into_string(self) -> io::Result<String> {
+        match self {
+            Self::Buffered(stack) => Ok(stack),
+            Self::File(file) => {
+                let mut stack = String::new();
+                Self::read_file(&file)?.read_to_string(&mut stack)?;
+                Ok(stack)
+            }
+        }
+    }
+
+    /// Append the records of `other` (they're read back in memory).
+    pub(crate) fn append(&mut self, other: Stack) -> io::Result<()> {
+        let mut stack = std::mem::take(self).into_string()?;
+        let other = other.into_string()?;
+        if !stack.is_empty() && !other.is_empty() && !stack.ends_with('\n') {
+            stack.push('\n');
+        }
+        stack.push_str(&other);
+        *self = Self::Buffered(stack);
+        Ok(())
+    }
+}
+
+/// An iterator on the lines of a [`Stack`].
+pub(crate) enum Lines<'a> {
+    Buffered(std::str::Lines<'a>),
+    File(io::Lines<BufReader<&'a File>>),
+}
+
+impl<'a> Iterator for Lines<'a> {
+    type Item = io::Result<Cow<'a, str>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Self::Buffered(lines) => lines.next().map(|line| Ok(Cow::Borrowed(line))),
+            Self::File(lines) => lines.next().map(|line| line.map(Cow::Owned)),
+        }
+    }
+}
+
+impl Display for Stack {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Buffered(stack) => f.write_str(stack),
+            Self::File(file) => {
+                let mut reader = Self::read_file(file).map_err(|_| fmt::Error)?;
+                loop {
+                    let buf = reader.fill_buf().map_err(|_| fmt::Error)?;
+                    if buf.is_empty() {
+                        return Ok(());
+                    }
+                    let len = buf.len();
+                    // The records are ASCII so a chunk is always valid UTF-8.
+                    f.write_str(std::str::from_utf8(buf).map_err(|_| fmt::Error)?)?;
+                    reader.consume(len);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::object_info::{CollectOptions, ObjectInfo};
+    use crate::platform::Platform;
+
+    #[test]
+    fn test_stack_writer() {
+        let records = "STACK CFI INIT 10 20 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n\
+                       STACK CFI 11 .cfa: $rsp 16 +\n\
+                       STACK CFI INIT 30 4 .cfa: $rsp 8 + .ra: .cfa -8 + ^\n";
+
+        let mut writer = StackWriter::new();
+        writer.write_all(records.as_bytes()).unwrap();
+        let buffered = writer.finish().unwrap();
+        assert!(matches!(buffered, Stack::Buffered(_)));
+
+        let mut writer = StackWriter::with_max_buffered_size(16);
+        for line in records.split_inclusive('\n') {
+            writer.write_all(line.as_bytes()).unwrap();
+        }
+        let spilled = writer.finish().unwrap();
+        assert!(matches!(spilled, Stack::File(_)));
+
+        for stack in [&buffered, &spilled] {
+            assert!(!stack.is_empty());
+            assert_eq!(stack.to_string(), records);
+            let mut lines = Vec::new();
+            stack.for_each_line(|l| lines.push(l.to_string())).unwrap();
+            assert_eq!(lines, records.lines().collect::<Vec<_>>());
+            let mut out = Vec::new();
+            stack.write_to(&mut out).unwrap();
+            assert_eq!(out, records.as_bytes());
+        }
+
+        let mut stack = Stack::default();
+        assert!(stack.is_empty());
+        stack.append(spilled).unwrap();
+        stack.append(buffered).unwrap();
+        assert_eq!(stack.into_string().unwrap(), records.repeat(2));
+    }
+
+    #[test]
+    fn test_spilled_module() {
+        let buf = std::fs::read("./test_data/linux/basic.full").unwrap();
+        let mut info = ObjectInfo::from_elf(
+            &buf,
+            "basic.full",
+            Platform::Linux,
+            &CollectOptions {
+                collect_inlines: true,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let expected = info.to_string();
+
+        let mut writer = StackWriter::with_max_buffered_size(64);
+        info.stack.write_to(&mut writer).unwrap();
+        info.stack = writer.finish().unwrap();
+        assert!(matches!(info.stack, Stack::File(_)));
+        assert!(!info.get_stack_ranges().is_empty());
+
+        let mut output = Vec::new();
+        info.write_breakpad(&mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+}
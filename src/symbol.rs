@@ -3,7 +3,7 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
-use std::collections::BTreeMap;
+use std::collections::{btree_map, BTreeMap};
 use std::fmt::{Display, Formatter};
 use std::ops::Bound::{Excluded, Included};
 use symbolic::debuginfo::Object;
@@ -81,17 +81,24 @@ impl Symbol {
         inline_origin_remapping: &[u32],
     ) {
         let inlines = std::mem::take(&mut self.source.inlines);
-        self.source.inlines = inlines
-            .into_iter()
-            .map(|(mut inline_site, address_ranges)| {
-                if let Some(file_remapping) = file_remapping {
-                    inline_site.call_file_id = file_remapping[inline_site.call_file_id as usize];
+        for (mut inline_site, address_ranges) in inlines {
+            if let Some(file_remapping) = file_remapping {
+                inline_site.call_file_id = file_remapping[inline_site.call_file_id as usize];
+            }
+            inline_site.inline_origin_id =
+                inline_origin_remapping[inline_site.inline_origin_id as usize];
+            // Two sites are the same once their inline origins are deduplicated.
+            match self.source.inlines.entry(inline_site) {
+                btree_map::Entry::Vacant(e) => {
+                    e.insert(address_ranges);
+                }
+                btree_map::Entry::Occupied(mut e) => {
+                    let ranges = e.get_mut();
+                    ranges.extend(address_ranges);
+                    ranges.sort_by_key(|range| range.rva);
                 }
-                inline_site.inline_origin_id =
-                    inline_origin_remapping[inline_site.inline_origin_id as usize];
-                (inline_site, address_ranges)
-            })
-            .collect();
+            }
+        }
     }
 }
 
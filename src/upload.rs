@@ -0,0 +1,495 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Upload the symbol files of a store directory to a Tecken-compatible
+//! symbol server (e.g. <https://symbols.mozilla.org/upload/>).
+//!
+//! The files are bundled into zip files with the layout of the store
+//! directory (`xul.pdb/DEBUG_ID/xul.sym`) and each zip is posted as a
+//! multipart form with an `Auth-Token` header.
+
+use bytes::Bytes;
+use log::{info, warn};
+use reqwest::blocking::{Body, Client};
+use reqwest::header::{CONTENT_TYPE, USER_AGENT};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::common;
+use crate::error::Error;
+
+/// A symbol file in a store directory.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoreFile {
+    /// The path of the file on the disk
+    pub path: PathBuf,
+    /// The path in the store (e.g. `xul.pdb/DEBUG_ID/xul.sym`)
+    pub name: String,
+    pub size: u64,
+}
+
+impl StoreFile {
+    fn new(store: &Path, path: PathBuf) -> common::Result<Self> {
+        let name = path
+            .strip_prefix(store)
+            .map_err(|_| {
+                Error::Config(format!(
+                    "{} isn't in the store directory {}",
+                    path.display(),
+                    store.display()
+                ))
+            })?
+            .iter()
+            .map(|c| c.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+        let size = path.metadata().map_err(|e| Error::io(&path, e))?.len();
+        Ok(Self { path, name, size })
+    }
+
+    fn is_symbol_file(path: &Path) -> bool {
+        path.is_file() && path.extension().is_some_and(|e| e == "sym")
+    }
+}
+
+/// The options to upload some symbol files.
+#[derive(Clone, Debug)]
+pub struct Options {
+    /// The upload url (e.g. `https://symbols.mozilla.org/upload/`)
+    pub url: String,
+    pub auth_token: String,
+    /// The symbol server used to check if a file has already been uploaded
+    /// (e.g. `https://symbols.mozilla.org/`), nothing is checked if `None`
+    pub check_url: Option<String>,
+    /// The number of retries after a network failure or a server error
+    pub max_retries: u32,
+    /// The delay before the first retry, doubled after each failure
+    pub retry_delay: Duration,
+    /// The maximum size of the files put in one zip (a bigger file is
+    /// uploaded alone)
+    pub max_batch_size: u64,
+}
+
+impl Options {
+    /// Create [`Options`] with 5 retries and batches of at most 1GB.
+    pub fn new(url: &str, auth_token: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            auth_token: auth_token.to_string(),
+            check_url: None,
+            max_retries: 5,
+            retry_delay: Duration::from_secs(2),
+            max_batch_size: 1 << 30,
+        }
+    }
+}
+
+/// What has been done by [`upload`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Report {
+    /// The files which have been uploaded
+    pub uploaded: Vec<String>,
+    /// The files which were already on the symbol server
+    pub skipped: Vec<String>,
+    /// The number of uploaded zip files
+    pub batches: usize,
+}
+
+/// Get the symbol files (`NAME/DEBUG_ID/NAME.sym`) in the store directory.
+pub fn store_files<P: AsRef<Path>>(store: P) -> common::Result<Vec<StoreFile>> {
+    let store = store.as_ref();
+    let read_dir = |dir: &Path| -> common::Result<Vec<PathBuf>> {
+        let entries = fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
+        let mut paths = entries
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()
+            .map_err(|e| Error::io(dir, e))?;
+        paths.sort();
+        Ok(paths)
+    };
+
+    let mut files = Vec::new();
+    for module in read_dir(store)?.iter().filter(|p| p.is_dir()) {
+        for id in read_dir(module)?.iter().filter(|p| p.is_dir()) {
+            for path in read_dir(id)? {
+                if StoreFile::is_symbol_file(&path) {
+                    files.push(StoreFile::new(store, path)?);
+                }
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Get the symbol files among the `paths` in the store directory (e.g. the
+/// outputs of a dump, see [`crate::report::Report::outputs`]), the other
+/// files being ignored.
+pub fn store_files_from_paths<P, I>(store: P, paths: I) -> common::Result<Vec<StoreFile>>
+where
+    P: AsRef<Path>,
+    I: IntoIterator,
+    I::Item: AsRef<Path>,
+{
+    let store = store.as_ref();
+    paths
+        .into_iter()
+        .map(|path| path.as_ref().to_path_buf())
+        .filter(|path| path.starts_with(store) && StoreFile::is_symbol_file(path))
+        .map(|path| StoreFile::new(store, path))
+        .collect()
+}
+
+/// Split the files in batches of at most `max_size` bytes.
+fn make_batches(files: Vec<StoreFile>, max_size: u64) -> Vec<Vec<StoreFile>> {
+    let mut batches: Vec<Vec<StoreFile>> = Vec::new();
+    let mut batch_size = 0;
+    for file in files {
+        match batches.last_mut() {
+            Some(batch) if batch_size + file.size <= max_size => {
+                batch_size += file.size;
+                batch.push(file);
+            }
+            _ => {
+                batch_size = file.size;
+                batches.push(vec![file]);
+            }
+        }
+    }
+    batches
+}
+
+fn make_zip(files: &[StoreFile]) -> common::Result<Vec<u8>> {
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .large_file(files.iter().any(|f| f.size >= u32::MAX as u64));
+    for file in files {
+        let buf = fs::read(&file.path).map_err(|e| Error::io(&file.path, e))?;
+        zip.start_file(file.name.as_str(), options)?;
+        zip.write_all(&buf)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+/// A multipart form with the zip file `name`: the zip isn't copied in the
+/// body and it's shared by the retries.
+struct Form {
+    content_type: String,
+    head: Bytes,
+    zip: Bytes,
+    tail: Bytes,
+}
+
+impl Form {
+    fn new(name: &str, zip: Vec<u8>) -> Self {
+        // The boundary can't be in the zip file.
+        let boundary = format!("dump_syms-{:x}", Sha256::digest(&zip));
+        let head = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{name}\"; filename=\"{name}\"\r\nContent-Type: application/zip\r\n\r\n"
+        );
+        let tail = format!("\r\n--{boundary}--\r\n");
+
+        Self {
+            content_type: format!("multipart/form-data; boundary={boundary}"),
+            head: head.into(),
+            zip: zip.into(),
+            tail: tail.into(),
+        }
+    }
+
+    fn body(&self) -> Body {
+        let len = self.head.len() + self.zip.len() + self.tail.len();
+        let reader = Cursor::new(self.head.clone())
+            .chain(Cursor::new(self.zip.clone()))
+            .chain(Cursor::new(self.tail.clone()));
+        Body::sized(reader, len as u64)
+    }
+}
+
+fn should_retry(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+struct Uploader<'a> {
+    client: Client,
+    options: &'a Options,
+}
+
+impl Uploader<'_> {
+    /// Send a request built by `request` until it succeeds or the retries
+    /// are exhausted.
+    fn send<F>(&self, what: &str, request: F) -> common::Result<StatusCode>
+    where
+        F: Fn(&Client) -> reqwest::blocking::RequestBuilder,
+    {
+        let mut delay = self.options.retry_delay;
+        for attempt in 0..=self.options.max_retries {
+            if attempt != 0 {
+                thread::sleep(delay);
+                delay *= 2;
+            }
+            match request(&self.client)
+                .header(USER_AGENT, common::GENERATOR)
+                .send()
+            {
+                Ok(resp) if should_retry(resp.status()) => {
+                    warn!("{what}: the server returned {}", resp.status());
+                }
+                Ok(resp) => return Ok(resp.status()),
+                Err(e) => warn!("{what}: {e}"),
+            }
+        }
+
+        Err(Error::Network(format!(
+            "{what}: failed after {} retries",
+            self.options.max_retries
+        ))
+        .into())
+    }
+
+    fn exists(&self, check_url: &str, file: &StoreFile) -> common::Result<bool> {
+        let url = format!("{}/{}", check_url.trim_end_matches('/'), file.name);
+        let status = self.send(&url, |client| client.head(&url))?;
+        Ok(status.is_success())
+    }
+
+    fn upload_batch(&self, index: usize, files: &[StoreFile]) -> common::Result<()> {
+        let name = format!("symbols-{index}.zip");
+        let form = Form::new(&name, make_zip(files)?);
+
+        info!(
+            "Upload {name} ({} files, {} bytes)",
+            files.len(),
+            form.zip.len()
+        );
+        let status = self.send(&self.options.url, |client| {
+            client
+                .post(&self.options.url)
+                .header("Auth-Token", &self.options.auth_token)
+                .header(CONTENT_TYPE, &form.content_type)
+                .body(form.body())
+        })?;
+        if !status.is_success() {
+            return Err(Error::Network(format!(
+                "{}: unable to upload {name}: the server returned {status}",
+                self.options.url
+            ))
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// Upload the `files` to the symbol server.
+pub fn upload(files: Vec<StoreFile>, options: &Options) -> common::Result<Report> {
+    let uploader = Uploader {
+        client: Client::new(),
+        options,
+    };
+    let mut report = Report::default();
+
+    let mut to_upload = Vec::with_capacity(files.len());
+    for file in files {
+        match &options.check_url {
+            Some(url) if uploader.exists(url, &file)? => {
+                info!("{} is already on the symbol server", file.name);
+                report.skipped.push(file.name);
+            }
+            _ => to_upload.push(file),
+        }
+    }
+
+    for (i, batch) in make_batches(to_upload, options.max_batch_size)
+        .iter()
+        .enumerate()
+    {
+        uploader.upload_batch(i, batch)?;
+        report.batches += 1;
+        report
+            .uploaded
+            .extend(batch.iter().map(|file| file.name.clone()));
+    }
+
+    Ok(report)
+}
+
+/// Upload all the symbol files of the `store` directory, see [`upload`].
+pub fn upload_store<P: AsRef<Path>>(store: P, options: &Options) -> common::Result<Report> {
+    upload(store_files(store)?, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use tempfile::Builder;
+    use zip::ZipArchive;
+
+    struct Request {
+        method: String,
+        path: String,
+        token: Option<String>,
+        body: Vec<u8>,
+    }
+
+    /// Start a minimal symbol server which has `existing` and fails the
+    /// first upload, and return its url and the received requests.
+    fn start_server(existing: &'static str) -> (String, Arc<Mutex<Vec<Request>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let received = Arc::clone(&requests);
+
+        thread::spawn(move || {
+            let mut failed = false;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut toks = line.split(' ');
+                let method = toks.next().unwrap().to_string();
+                let path = toks.next().unwrap().to_string();
+
+                let mut token = None;
+                let mut length = 0;
+                loop {
+                    line.clear();
+                    if reader.read_line(&mut line).unwrap_or_default() <= 2 {
+                        break;
+                    }
+                    let (key, value) = line.trim().split_once(": ").unwrap();
+                    match key.to_lowercase().as_str() {
+                        "auth-token" => token = Some(value.to_string()),
+                        "content-length" => length = value.parse().unwrap(),
+                        _ => {}
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let status = match method.as_str() {
+                    "HEAD" if path.strip_prefix('/') == Some(existing) => "200 OK",
+                    "HEAD" => "404 Not Found",
+                    _ if !failed => {
+                        failed = true;
+                        "503 Service Unavailable"
+                    }
+                    _ => "201 Created",
+                };
+                requests.lock().unwrap().push(Request {
+                    method,
+                    path,
+                    token,
+                    body,
+                });
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 {status}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                );
+            }
+        });
+
+        (url, received)
+    }
+
+    fn zip_names(body: &[u8]) -> Vec<String> {
+        let start = body.windows(4).position(|w| w == b"PK\x03\x04").unwrap();
+        let zip = ZipArchive::new(Cursor::new(&body[start..])).unwrap();
+        zip.file_names().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_upload() {
+        let tmp_dir = Builder::new().prefix("upload").tempdir().unwrap();
+        let store = tmp_dir.path();
+        for (name, size) in [
+            ("a.pdb/0123/a.sym", 10),
+            ("b.so/4567/b.so.sym", 10),
+            ("c.dylib/89AB/c.dylib.sym", 30),
+            ("c.dylib/89AB/c.dylib.json", 30),
+        ] {
+            let path = store.join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, vec![b'x'; size]).unwrap();
+        }
+
+        let files = store_files(store).unwrap();
+        let names: Vec<_> = files.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "a.pdb/0123/a.sym",
+                "b.so/4567/b.so.sym",
+                "c.dylib/89AB/c.dylib.sym"
+            ]
+        );
+
+        // Only the symbol files in the store directory are kept.
+        let paths = [
+            store.join("c.dylib/89AB/c.dylib.json"),
+            store.join("b.so/4567/b.so.sym"),
+            tmp_dir.path().parent().unwrap().join("b.so.sym"),
+        ];
+        let from_paths = store_files_from_paths(store, &paths).unwrap();
+        assert_eq!(from_paths, [files[1].clone()]);
+
+        let (url, requests) = start_server("a.pdb/0123/a.sym");
+        let options = Options {
+            check_url: Some(url.clone()),
+            retry_delay: Duration::ZERO,
+            max_batch_size: 20,
+            ..Options::new(&format!("{url}/upload/"), "secret")
+        };
+        let report = upload(files, &options).unwrap();
+        assert_eq!(report.skipped, ["a.pdb/0123/a.sym"]);
+        assert_eq!(
+            report.uploaded,
+            ["b.so/4567/b.so.sym", "c.dylib/89AB/c.dylib.sym"]
+        );
+        assert_eq!(report.batches, 2);
+
+        let requests = requests.lock().unwrap();
+        let heads = requests.iter().filter(|r| r.method == "HEAD").count();
+        assert_eq!(heads, 3);
+        // The first upload is retried.
+        let posts: Vec<_> = requests.iter().filter(|r| r.method == "POST").collect();
+        assert_eq!(posts.len(), 3);
+        assert!(posts
+            .iter()
+            .all(|r| r.path == "/upload/" && r.token.as_deref() == Some("secret")));
+        assert_eq!(zip_names(&posts[1].body), ["b.so/4567/b.so.sym"]);
+        assert_eq!(zip_names(&posts[2].body), ["c.dylib/89AB/c.dylib.sym"]);
+    }
+
+    #[test]
+    fn test_make_batches() {
+        let files: Vec<_> = [5, 5, 5, 20, 1]
+            .iter()
+            .enumerate()
+            .map(|(i, size)| StoreFile {
+                path: PathBuf::new(),
+                name: i.to_string(),
+                size: *size,
+            })
+            .collect();
+        let batches: Vec<Vec<_>> = make_batches(files, 10)
+            .iter()
+            .map(|b| b.iter().map(|f| f.name.as_str().to_owned()).collect())
+            .collect();
+        assert_eq!(batches, [vec!["0", "1"], vec!["2"], vec!["3"], vec!["4"]]);
+    }
+}
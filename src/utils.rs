@@ -4,33 +4,90 @@
 // copied, modified, or distributed except according to those terms.
 
 use cab::Cabinet;
+use memmap2::Mmap;
+use std::fmt::{self, Debug, Formatter};
 use std::fs::{self, File, Metadata};
 use std::io::{Cursor, Read};
+use std::ops::Deref;
 use std::path::{Component, Path, PathBuf};
 
 use crate::common;
+use crate::error::Error;
 
-pub fn read_file<P: AsRef<Path>>(path: P) -> Vec<u8> {
-    let metadata = fs::metadata(&path).unwrap_or_else(|_| {
-        panic!(
-            "Unable to open the file {}",
-            path.as_ref().to_str().unwrap()
-        )
-    });
+/// The content of an input file: the files on the disk are mapped in memory
+/// and the uncompressed cabinet files or the fetched ones are in a buffer.
+pub enum FileData {
+    Mapped(Mmap),
+    Buffer(Vec<u8>),
+}
 
-    let (metadata, path) = get_mac_bundle(&metadata, &path)
-        .unwrap_or_else(|| (metadata, PathBuf::from(path.as_ref())));
+impl Deref for FileData {
+    type Target = [u8];
 
-    let file_size = metadata.len() as usize;
-    let mut file = File::open(&path)
-        .unwrap_or_else(|_| panic!("Unable to open the file {}", path.to_str().unwrap()));
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Mapped(mmap) => mmap,
+            Self::Buffer(buf) => buf,
+        }
+    }
+}
 
-    let mut buf = Vec::with_capacity(file_size + 1);
-    file.read_to_end(&mut buf)
-        .unwrap_or_else(|_| panic!("Unable to read the file {}", path.to_str().unwrap()));
+impl AsRef<[u8]> for FileData {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
 
-    read_cabinet(buf, path.clone())
-        .unwrap_or_else(|| panic!("Unable to read the cabinet file {}", path.to_str().unwrap()))
+impl From<Vec<u8>> for FileData {
+    fn from(buf: Vec<u8>) -> Self {
+        Self::Buffer(buf)
+    }
+}
+
+impl Debug for FileData {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let kind = match self {
+            Self::Mapped(_) => "Mapped",
+            Self::Buffer(_) => "Buffer",
+        };
+        write!(f, "FileData::{kind}({} bytes)", self.len())
+    }
+}
+
+fn map_file(mut file: &File, len: u64) -> std::io::Result<FileData> {
+    // An empty file can't be mapped.
+    if len != 0 {
+        // SAFETY: the file mustn't be modified while it's dumped, as for any
+        // other tool reading it.
+        if let Ok(mmap) = unsafe { Mmap::map(file) } {
+            return Ok(FileData::Mapped(mmap));
+        }
+    }
+
+    let mut buf = Vec::with_capacity(len as usize + 1);
+    file.read_to_end(&mut buf)?;
+    Ok(FileData::Buffer(buf))
+}
+
+/// Read a file, or the DWARF file of a dSYM bundle, by mapping it in memory.
+///
+/// The cabinet files (e.g. `xul.pd_`) are uncompressed in memory.
+pub fn read_file<P: AsRef<Path>>(path: P) -> common::Result<FileData> {
+    let metadata = fs::metadata(&path).map_err(|e| Error::io(&path, e))?;
+
+    let (metadata, path) = get_mac_bundle(&metadata, &path)?
+        .unwrap_or_else(|| (metadata, PathBuf::from(path.as_ref())));
+
+    let file = File::open(&path).map_err(|e| Error::io(&path, e))?;
+    let data = map_file(&file, metadata.len()).map_err(|e| Error::io(&path, e))?;
+
+    let cab = Cabinet::new(Cursor::new(&*data)).ok();
+    match cab {
+        Some(cab) => read_cabinet_file(cab, path.clone())
+            .map(FileData::Buffer)
+            .ok_or_else(|| Error::parse(path.display().to_string(), "invalid cabinet file").into()),
+        None => Ok(data),
+    }
 }
 
 pub(crate) fn get_base(file_name: &str) -> PathBuf {
@@ -73,39 +130,43 @@ pub fn get_path_for_sym(file_name: &str, id: &str) -> PathBuf {
     pb
 }
 
-pub fn get_mac_bundle<P: AsRef<Path>>(metadata: &Metadata, path: P) -> Option<(Metadata, PathBuf)> {
-    if metadata.is_dir() {
-        // We may have a dSYM bundle
-        let dwarf_path = path.as_ref().to_path_buf().join("Contents/Resources/DWARF");
-        if dwarf_path.is_dir() {
-            let entries: Vec<_> = fs::read_dir(&dwarf_path).unwrap().collect();
-            match entries.len() {
-                0 => panic!(
-                    "Unable to find DWARF-bearing file in bundle: {}",
-                    dwarf_path.to_str().unwrap()
-                ),
-                1 => {
-                    let entry = entries[0].as_ref().unwrap();
-                    Some((
-                        entry.metadata().unwrap_or_else(|_| {
-                            panic!("Unable to open the file {}", entry.path().to_str().unwrap())
-                        }),
-                        entry.path(),
-                    ))
-                }
-                _ => panic!(
-                    "Too many DWARF files in bundle: {}",
-                    dwarf_path.to_str().unwrap()
-                ),
-            }
-        } else {
-            panic!(
-                "File {} is a directory and not a mac bundle",
-                path.as_ref().to_str().unwrap()
-            )
+pub fn get_mac_bundle<P: AsRef<Path>>(
+    metadata: &Metadata,
+    path: P,
+) -> common::Result<Option<(Metadata, PathBuf)>> {
+    if !metadata.is_dir() {
+        return Ok(None);
+    }
+
+    // We may have a dSYM bundle
+    let path = path.as_ref();
+    let dwarf_path = path.join("Contents/Resources/DWARF");
+    if !dwarf_path.is_dir() {
+        return Err(Error::UnknownFormat(format!(
+            "{} is a directory and not a mac bundle",
+            path.display()
+        ))
+        .into());
+    }
+
+    let entries = fs::read_dir(&dwarf_path)
+        .and_then(|entries| entries.collect::<std::io::Result<Vec<_>>>())
+        .map_err(|e| Error::io(&dwarf_path, e))?;
+    match entries.as_slice() {
+        [entry] => {
+            let metadata = entry.metadata().map_err(|e| Error::io(entry.path(), e))?;
+            Ok(Some((metadata, entry.path())))
         }
-    } else {
-        None
+        [] => Err(Error::parse(
+            path.display().to_string(),
+            "no DWARF-bearing file in the bundle",
+        )
+        .into()),
+        _ => Err(Error::parse(
+            path.display().to_string(),
+            "too many DWARF files in the bundle",
+        )
+        .into()),
     }
 }
 
@@ -114,12 +175,14 @@ pub fn read_cabinet(buf: Vec<u8>, path: PathBuf) -> Option<Vec<u8>> {
     // if not a cabinet just return the buffer
     // else return None on error
 
-    let cursor = Cursor::new(&buf);
-    let mut cab = match Cabinet::new(cursor) {
-        Ok(cab) => cab,
-        _ => return Some(buf),
-    };
+    let cab = Cabinet::new(Cursor::new(buf.as_slice())).ok();
+    match cab {
+        Some(cab) => read_cabinet_file(cab, path),
+        None => Some(buf),
+    }
+}
 
+fn read_cabinet_file(mut cab: Cabinet<Cursor<&[u8]>>, path: PathBuf) -> Option<Vec<u8>> {
     let (file, size) = match get_cabinet_files(&cab, path) {
         Some(file) => file,
         _ => return None,
@@ -153,7 +216,7 @@ fn get_corrected_path(path: PathBuf) -> PathBuf {
     }
 }
 
-fn get_cabinet_files(cab: &Cabinet<Cursor<&Vec<u8>>>, path: PathBuf) -> Option<(String, usize)> {
+fn get_cabinet_files(cab: &Cabinet<Cursor<&[u8]>>, path: PathBuf) -> Option<(String, usize)> {
     // Try to find in the cabinet the same path with pdb extension
     let path = get_corrected_path(path);
     let file_name = path.file_name().unwrap();
@@ -203,21 +266,22 @@ pub fn get_filename(path: &Path) -> String {
 }
 
 pub fn read<P: AsRef<Path>>(path: P) -> common::Result<Vec<u8>> {
-    let file_size = fs::metadata(&path)?.len() as usize;
-    let mut file = File::open(&path).unwrap_or_else(|_| {
-        panic!(
-            "Unable to open the file {}",
-            path.as_ref().to_str().unwrap()
-        )
-    });
+    Ok(fs::read(&path).map_err(|e| Error::io(&path, e))?)
+}
 
-    let mut buf = Vec::with_capacity(file_size + 1);
-    file.read_to_end(&mut buf).unwrap_or_else(|_| {
-        panic!(
-            "Unable to read the file {}",
-            path.as_ref().to_str().unwrap()
-        )
-    });
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use symbolic::debuginfo::{peek, FileFormat};
 
-    Ok(buf)
+    #[test]
+    fn test_read_file() {
+        let data = read_file("./test_data/windows/basic32.dll").unwrap();
+        assert!(matches!(data, FileData::Mapped(_)));
+        assert_eq!(&*data, fs::read("./test_data/windows/basic32.dll").unwrap());
+
+        let data = read_file("./test_data/windows/basic32.pd_").unwrap();
+        assert!(matches!(data, FileData::Buffer(_)));
+        assert_eq!(peek(&data, false), FileFormat::Pdb);
+    }
 }
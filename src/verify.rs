@@ -0,0 +1,353 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+use hashbrown::HashSet;
+use std::fmt::{Display, Formatter};
+
+/// A problem found in a Breakpad symbol file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Problem {
+    /// 1-based line number.
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+#[derive(Default)]
+struct Checker {
+    problems: Vec<Problem>,
+    file_ids: HashSet<u32>,
+    origin_ids: HashSet<u32>,
+    // The FILE and INLINE_ORIGIN references to check once all the ids are known.
+    file_refs: Vec<(usize, u32)>,
+    origin_refs: Vec<(usize, u32)>,
+    // (line number, address, size) of the current FUNC.
+    func: Option<(usize, u64, u64)>,
+    // (line number, address, size) of the previous FUNC.
+    prev_func: Option<(usize, u64, u64)>,
+    last_func_address: Option<u64>,
+    last_public_address: Option<u64>,
+    last_line_address: Option<u64>,
+}
+
+fn parse_hex(s: &str) -> Option<u64> {
+    u64::from_str_radix(s, 16).ok()
+}
+
+fn parse_dec(s: &str) -> Option<u32> {
+    s.parse::<u32>().ok()
+}
+
+impl Checker {
+    fn error(&mut self, line: usize, message: impl Into<String>) {
+        self.problems.push(Problem {
+            line,
+            message: message.into(),
+        });
+    }
+
+    fn check_order(&mut self, n: usize, kind: &str, address: u64, last: Option<u64>) {
+        if let Some(last) = last {
+            if address < last {
+                self.error(
+                    n,
+                    format!("{kind} at {address:x} is before the previous {kind} at {last:x}"),
+                );
+            }
+        }
+    }
+
+    fn module(&mut self, n: usize, rest: &str) {
+        if n != 1 {
+            self.error(n, "MODULE record must be the first line");
+        }
+        if rest.splitn(4, ' ').filter(|t| !t.is_empty()).count() != 4 {
+            self.error(n, "Invalid MODULE record: expected <os> <arch> <id> <name>");
+        }
+    }
+
+    fn info(&mut self, n: usize, rest: &str) {
+        if rest.trim().is_empty() {
+            self.error(n, "Invalid INFO record: missing content");
+        }
+    }
+
+    fn file(&mut self, n: usize, rest: &str) {
+        match rest.split_once(' ').and_then(|(id, _)| parse_dec(id)) {
+            Some(id) => {
+                if !self.file_ids.insert(id) {
+                    self.error(n, format!("Duplicated FILE id {id}"));
+                }
+            }
+            None => self.error(n, "Invalid FILE record: expected <id> <name>"),
+        }
+    }
+
+    fn inline_origin(&mut self, n: usize, rest: &str) {
+        match rest.split_once(' ').and_then(|(id, _)| parse_dec(id)) {
+            Some(id) => {
+                if !self.origin_ids.insert(id) {
+                    self.error(n, format!("Duplicated INLINE_ORIGIN id {id}"));
+                }
+            }
+            None => self.error(n, "Invalid INLINE_ORIGIN record: expected <id> <name>"),
+        }
+    }
+
+    fn func(&mut self, n: usize, rest: &str) {
+        let rest = rest.strip_prefix("m ").unwrap_or(rest);
+        let toks: Vec<_> = rest.splitn(4, ' ').collect();
+        let (address, size) = match toks[..] {
+            [address, size, param_size, _] => (
+                parse_hex(address),
+                parse_hex(size).filter(|_| parse_hex(param_size).is_some()),
+            ),
+            _ => (None, None),
+        };
+        let (address, size) = match (address, size) {
+            (Some(address), Some(size)) => (address, size),
+            _ => {
+                self.error(
+                    n,
+                    "Invalid FUNC record: expected [m] <address> <size> <param_size> <name>",
+                );
+                self.func = None;
+                return;
+            }
+        };
+
+        self.check_order(n, "FUNC", address, self.last_func_address);
+        self.last_func_address = Some(address);
+        if let Some((prev_n, prev_address, prev_size)) = self.prev_func {
+            if prev_address <= address && prev_address.saturating_add(prev_size) > address {
+                self.error(
+                    n,
+                    format!(
+                        "FUNC at {address:x} overlaps the FUNC at {prev_address:x} (line {prev_n})"
+                    ),
+                );
+            }
+        }
+
+        self.func = Some((n, address, size));
+        self.prev_func = self.func;
+        self.last_line_address = None;
+    }
+
+    fn public(&mut self, n: usize, rest: &str) {
+        let rest = rest.strip_prefix("m ").unwrap_or(rest);
+        let toks: Vec<_> = rest.splitn(3, ' ').collect();
+        match toks[..] {
+            [address, param_size, _] if parse_hex(param_size).is_some() => {
+                if let Some(address) = parse_hex(address) {
+                    self.check_order(n, "PUBLIC", address, self.last_public_address);
+                    self.last_public_address = Some(address);
+                    self.func = None;
+                    return;
+                }
+            }
+            _ => {}
+        }
+        self.error(
+            n,
+            "Invalid PUBLIC record: expected [m] <address> <param_size> <name>",
+        );
+        self.func = None;
+    }
+
+    fn inline(&mut self, n: usize, rest: &str) {
+        if self.func.is_none() {
+            self.error(n, "INLINE record outside of a FUNC");
+        }
+        let toks: Vec<_> = rest.split(' ').collect();
+        let valid = toks.len() >= 6
+            && toks.len() % 2 == 0
+            && toks[..4].iter().all(|t| parse_dec(t).is_some())
+            && toks[4..].iter().all(|t| parse_hex(t).is_some());
+        if !valid {
+            self.error(
+                n,
+                "Invalid INLINE record: expected <depth> <call_line> <call_file> <origin> [<address> <size>]+",
+            );
+            return;
+        }
+        self.file_refs.push((n, parse_dec(toks[2]).unwrap()));
+        self.origin_refs.push((n, parse_dec(toks[3]).unwrap()));
+    }
+
+    fn line_record(&mut self, n: usize, line: &str) {
+        let toks: Vec<_> = line.split(' ').collect();
+        let parsed = match toks[..] {
+            [address, size, num, file] => parse_hex(address)
+                .zip(parse_hex(size))
+                .zip(parse_dec(num).zip(parse_dec(file))),
+            _ => None,
+        };
+        let ((address, _), (_, file_id)) = match parsed {
+            Some(parsed) => parsed,
+            None => {
+                self.error(n, format!("Invalid record: {line}"));
+                return;
+            }
+        };
+
+        if self.func.is_none() {
+            self.error(n, "Line record outside of a FUNC");
+            return;
+        }
+        if let Some(last) = self.last_line_address {
+            if address < last {
+                self.error(
+                    n,
+                    format!("Line at {address:x} is before the previous line at {last:x}"),
+                );
+            }
+        }
+        self.last_line_address = Some(address);
+        self.file_refs.push((n, file_id));
+    }
+
+    fn stack(&mut self, n: usize, rest: &str) {
+        self.func = None;
+        if let Some(rest) = rest.strip_prefix("CFI INIT ") {
+            let toks: Vec<_> = rest.splitn(3, ' ').collect();
+            if toks.len() != 3 || parse_hex(toks[0]).is_none() || parse_hex(toks[1]).is_none() {
+                self.error(
+                    n,
+                    "Invalid STACK CFI INIT record: expected <address> <size> <rules>",
+                );
+            }
+        } else if let Some(rest) = rest.strip_prefix("CFI ") {
+            let toks: Vec<_> = rest.splitn(2, ' ').collect();
+            if toks.len() != 2 || parse_hex(toks[0]).is_none() {
+                self.error(n, "Invalid STACK CFI record: expected <address> <rules>");
+            }
+        } else if let Some(rest) = rest.strip_prefix("WIN ") {
+            // type rva code_size prologue_size epilogue_size parameter_size
+            // saved_register_size local_size max_stack_size has_program program_string_or_allocates_base_pointer
+            let toks: Vec<_> = rest.splitn(11, ' ').collect();
+            let valid = toks.len() == 11
+                && matches!(toks[0], "0" | "1" | "2" | "3" | "4")
+                && toks[1..9].iter().all(|t| parse_hex(t).is_some())
+                && matches!(toks[9], "0" | "1");
+            if !valid {
+                self.error(n, "Invalid STACK WIN record");
+            }
+        } else {
+            self.error(n, "Invalid STACK record: expected STACK CFI or STACK WIN");
+        }
+    }
+
+    fn check_ids(&mut self) {
+        let file_refs = std::mem::take(&mut self.file_refs);
+        for (n, id) in file_refs {
+            if !self.file_ids.contains(&id) {
+                self.error(n, format!("Unknown FILE id {id}"));
+            }
+        }
+        let origin_refs = std::mem::take(&mut self.origin_refs);
+        for (n, id) in origin_refs {
+            if !self.origin_ids.contains(&id) {
+                self.error(n, format!("Unknown INLINE_ORIGIN id {id}"));
+            }
+        }
+    }
+}
+
+/// Check the content of a Breakpad symbol file and return all the problems found.
+pub fn verify(buf: &[u8]) -> Vec<Problem> {
+    let mut checker = Checker::default();
+    let data = String::from_utf8_lossy(buf);
+
+    if data.is_empty() {
+        checker.error(1, "Empty file");
+        return checker.problems;
+    }
+
+    for (i, line) in data.lines().enumerate() {
+        let n = i + 1;
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match keyword {
+            "MODULE" => checker.module(n, rest),
+            "INFO" => checker.info(n, rest),
+            "FILE" => checker.file(n, rest),
+            "INLINE_ORIGIN" => checker.inline_origin(n, rest),
+            "FUNC" => checker.func(n, rest),
+            "PUBLIC" => checker.public(n, rest),
+            "INLINE" => checker.inline(n, rest),
+            "STACK" => checker.stack(n, rest),
+            _ => checker.line_record(n, line),
+        }
+        if n == 1 && keyword != "MODULE" {
+            checker.error(n, "Missing MODULE record");
+        }
+    }
+
+    if !data.ends_with('\n') {
+        checker.error(
+            data.lines().count(),
+            "Missing final newline: truncated file?",
+        );
+    }
+
+    checker.check_ids();
+    checker.problems.sort_by_key(|p| p.line);
+    checker.problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::read;
+
+    #[test]
+    fn test_verify_valid() {
+        for file in [
+            "./test_data/linux/basic.full.inlines.sym",
+            "./test_data/linux/basic.dbg.sym",
+            "./test_data/windows/basic32.sym",
+            "./test_data/windows/mozwer.sym",
+        ] {
+            let data = read(file).unwrap();
+            assert_eq!(verify(&data), vec![], "Problems in {file}");
+        }
+    }
+
+    #[test]
+    fn test_verify_invalid() {
+        let data = b"MODULE Linux x86_64 20AD60B0B4C68177552708AA192E77390 basic.full
+FILE 0 basic.cpp
+INLINE_ORIGIN 0 inline_1(int)
+FUNC 1125 26 0 inline_1(int)
+INLINE 0 12 0 3 1130 4
+1125 7 2 0
+1120 9 3 1
+FUNC 1140 10 0 foo()
+PUBLIC 1100 0 bar
+PUBLIC 1000 0 baz
+STACK CFI INIT 1040 .cfa: $rsp 8 +
+1200 4 5 0";
+        let problems: Vec<_> = verify(data).into_iter().map(|p| p.to_string()).collect();
+        assert_eq!(
+            problems,
+            vec![
+                "line 5: Unknown INLINE_ORIGIN id 3",
+                "line 7: Line at 1120 is before the previous line at 1125",
+                "line 7: Unknown FILE id 1",
+                "line 8: FUNC at 1140 overlaps the FUNC at 1125 (line 4)",
+                "line 10: PUBLIC at 1000 is before the previous PUBLIC at 1100",
+                "line 11: Invalid STACK CFI INIT record: expected <address> <size> <rules>",
+                "line 12: Line record outside of a FUNC",
+                "line 12: Missing final newline: truncated file?",
+            ]
+        );
+    }
+}
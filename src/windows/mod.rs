@@ -4,4 +4,5 @@
 // copied, modified, or distributed except according to those terms.
 
 pub mod pdb;
+pub mod srcsrv;
 pub mod utils;
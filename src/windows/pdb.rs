@@ -3,12 +3,14 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use log::warn;
 use std::sync::Arc;
 use symbolic::debuginfo::{pdb::PdbObject, pe::PeObject, Object};
 
+use super::{srcsrv, utils};
 use crate::common;
 use crate::mapping::PathMappings;
-use crate::object_info::ObjectInfo;
+use crate::object_info::{CollectOptions, ObjectInfo};
 use crate::platform::Platform;
 
 impl ObjectInfo {
@@ -17,9 +19,15 @@ impl ObjectInfo {
         pdb_name: &str,
         pe_name: Option<&str>,
         pe: Option<PeObject>,
-        mapping: Option<Arc<PathMappings>>,
-        collect_inlines: bool,
+        options: &CollectOptions,
     ) -> common::Result<Self> {
+        // The explicit mappings win, then the srcsrv stream and the SourceLink one.
+        let mapping = srcsrv::get_mapping(&pdb, options.mapping.clone());
+        let mapping = get_source_link_mapping(&pdb, mapping);
+        let options = CollectOptions {
+            mapping,
+            ..options.clone()
+        };
         let pdb = Object::Pdb(pdb);
         let pe = pe.map(Object::Pe);
 
@@ -29,8 +37,7 @@ impl ObjectInfo {
             pe.as_ref(),
             pe_name,
             Platform::Win,
-            mapping,
-            collect_inlines,
+            &options,
         )
     }
 
@@ -44,12 +51,31 @@ impl ObjectInfo {
             None,
             Some(pe_name),
             Platform::Win,
-            None,
-            false,
+            &CollectOptions::default(),
         )
     }
 }
 
+/// Get the mappings from the SourceLink stream of the PDB file, if any, for
+/// the files which `mapping` doesn't map.
+fn get_source_link_mapping(
+    pdb: &PdbObject,
+    mapping: Option<Arc<PathMappings>>,
+) -> Option<Arc<PathMappings>> {
+    let data = match utils::get_named_stream(pdb, b"sourcelink") {
+        Some(data) => data,
+        None => return mapping,
+    };
+
+    match PathMappings::with_source_link(&data, mapping.clone()) {
+        Ok(source_link) => Some(Arc::new(source_link)),
+        Err(e) => {
+            warn!("Invalid SourceLink stream: {e}");
+            mapping
+        }
+    }
+}
+
 fn win_path_file_name(pdb_name: &str) -> &str {
     let index = pdb_name.rfind('\\').map_or(0, |i| i + 1);
     &pdb_name[index..]
@@ -95,15 +121,25 @@ mod tests {
         let (pe, pdb_buf, pdb_name) = crate::windows::utils::get_pe_pdb_buf(
             &PathBuf::from("."),
             &pe_buf,
-            crate::cache::get_sym_servers(Some(&format!("SRV*~/symcache*{MS}"))).as_ref(),
+            crate::cache::get_sym_servers(Some(&format!("SRV*~/symcache*{MS}")))
+                .unwrap()
+                .as_ref(),
         )
+        .unwrap()
         .unwrap();
 
         let pdb = PdbObject::parse(&pdb_buf).unwrap();
 
         let mut output = Vec::new();
         let cursor = Cursor::new(&mut output);
-        let pdb = ObjectInfo::from_pdb(pdb, &pdb_name, Some(name), Some(pe), None, false).unwrap();
+        let pdb = ObjectInfo::from_pdb(
+            pdb,
+            &pdb_name,
+            Some(name),
+            Some(pe),
+            &CollectOptions::default(),
+        )
+        .unwrap();
         pdb.dump(cursor).unwrap();
 
         let toks: Vec<_> = name.rsplitn(2, '.').collect();
@@ -119,13 +155,23 @@ mod tests {
             path.set_extension("exe");
         }
 
-        let pe_buf = crate::utils::read_file(&path);
+        let pe_buf = crate::utils::read_file(&path).unwrap();
         let (pe, pdb_buf, pdb_name) = crate::windows::utils::get_pe_pdb_buf(
             &path,
             &pe_buf,
-            crate::cache::get_sym_servers(Some(&format!("SRV*~/symcache*{MS}"))).as_ref(),
+            crate::cache::get_sym_servers(Some(&format!("SRV*~/symcache*{MS}")))
+                .unwrap()
+                .as_ref(),
         )
-        .unwrap_or_else(|| (PeObject::parse(&pe_buf).unwrap(), vec![], "".to_string()));
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| {
+            (
+                PeObject::parse(&pe_buf).unwrap(),
+                vec![].into(),
+                "".to_string(),
+            )
+        });
 
         let mut output = Vec::new();
         let cursor = Cursor::new(&mut output);
@@ -135,9 +181,12 @@ mod tests {
             pe.dump(cursor).unwrap();
         } else {
             let pdb = PdbObject::parse(&pdb_buf).unwrap();
+            let options = CollectOptions {
+                mapping,
+                ..Default::default()
+            };
             let pdb =
-                ObjectInfo::from_pdb(pdb, &pdb_name, Some(file_name), Some(pe), mapping, false)
-                    .unwrap();
+                ObjectInfo::from_pdb(pdb, &pdb_name, Some(file_name), Some(pe), &options).unwrap();
             pdb.dump(cursor).unwrap();
         }
 
@@ -416,6 +465,64 @@ mod tests {
         test_file("basic32", TestFlags::ALL);
     }
 
+    /// Get the STACK WIN records of basic32-min.dll dumped with `pdb_name`.
+    fn get_stack_win(pdb_name: &str) -> Vec<String> {
+        let path = PathBuf::from("./test_data/windows");
+        let pe_buf = std::fs::read(path.join("basic32-min.dll")).unwrap();
+        let pdb_buf = std::fs::read(path.join(pdb_name)).unwrap();
+        let pe = PeObject::parse(&pe_buf).unwrap();
+        let pdb = PdbObject::parse(&pdb_buf).unwrap();
+
+        let mut output = Vec::new();
+        let pdb = ObjectInfo::from_pdb(
+            pdb,
+            pdb_name,
+            Some("basic32-min.dll"),
+            Some(pe),
+            &CollectOptions::default(),
+        )
+        .unwrap();
+        pdb.dump(Cursor::new(&mut output)).unwrap();
+
+        String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with("STACK WIN "))
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn read_stack_win(file_name: &str, prefix: &str) -> Vec<String> {
+        let path = PathBuf::from("./test_data/windows").join(file_name);
+        std::fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| line.starts_with(prefix))
+            .map(str::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn test_basic32_fpo() {
+        // The frame data stream has been removed from this PDB file (see
+        // test_data/windows/Makefile) so all the functions are described by
+        // their FPO data.
+        let stack = get_stack_win("basic32-min-fpo.pdb");
+        assert_eq!(stack.len(), 86);
+        assert!(stack.iter().all(|line| line.starts_with("STACK WIN 0 ")));
+        assert_eq!(stack, read_stack_win("basic32-min-fpo.stack", "STACK WIN "));
+    }
+
+    #[test]
+    fn test_basic32_frame_data() {
+        // The FPO stream has been removed from this PDB file: the records
+        // are the type 4 ones dumped by the Breakpad dump_syms.
+        let stack = get_stack_win("basic32-min-frame-data.pdb");
+        assert_eq!(stack.len(), 4972);
+        assert!(stack[0].ends_with(" 1 $T0 .raSearch = $eip $T0 ^ = $esp $T0 4 + ="));
+        assert_eq!(stack, read_stack_win("basic32-min.sym", "STACK WIN 4 "));
+    }
+
     #[test]
     fn test_basic32_dwarf() {
         test_file("basic32-dwarf", TestFlags::ALL);
@@ -472,6 +579,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_srcsrv_mapping() {
+        // A srcsrv stream has been added to this PDB file (see
+        // test_data/windows/Makefile).
+        let path = PathBuf::from("./test_data/windows");
+        let pe_buf = std::fs::read(path.join("dump_syms_regtest64.exe")).unwrap();
+        let pdb_buf = std::fs::read(path.join("dump_syms_regtest64-srcsrv.pdb")).unwrap();
+        let mapping = PathMappings::new(
+            &None,
+            &Some(vec![r"f:\\dd\\vctools\\crt\\crtw32\\misc\\amd64\\(.*)"]),
+            &Some(vec!["local:{1}"]),
+            &None,
+            &None,
+            &None,
+        )
+        .unwrap();
+        let options = CollectOptions {
+            mapping: mapping.map(Arc::new),
+            ..Default::default()
+        };
+
+        let mut output = Vec::new();
+        let pdb = ObjectInfo::from_pdb(
+            PdbObject::parse(&pdb_buf).unwrap(),
+            "dump_syms_regtest64.pdb",
+            Some("dump_syms_regtest64.exe"),
+            Some(PeObject::parse(&pe_buf).unwrap()),
+            &options,
+        )
+        .unwrap();
+        pdb.dump(Cursor::new(&mut output)).unwrap();
+        let bp = BreakpadObject::parse(&output).unwrap();
+        let files: Vec<_> = bp.file_map().into_values().collect();
+
+        assert_eq!(
+            files[0],
+            "https://chromium.googlesource.com/breakpad/breakpad/+/9a4e1e7/src/tools/windows/dump_syms/testdata/dump_syms_regtest.cc?format=TEXT"
+        );
+        // The explicit mappings win over the srcsrv stream.
+        assert_eq!(files[2], "local:chandler.c");
+        assert_eq!(files[3], "local:gshandler.c");
+        // The files retrieved with a command aren't mapped.
+        assert_eq!(files[9], r"f:\dd\vctools\crt\crtw32\heap\free.c");
+    }
+
     #[test]
     fn test_win_mapping() {
         let mapping = PathMappings::new(
@@ -479,6 +631,8 @@ mod tests {
             &Some(vec![r"d:\\agent\\_work\\3\\s\\src\\(.*)"]),
             &Some(vec!["https://source/{rev}/{1}"]),
             &None,
+            &None,
+            &None,
         )
         .unwrap();
         let dll = "basic32.dll";
@@ -0,0 +1,162 @@
+// Licensed under the Apache License, Version 2.0, <LICENSE-APACHE or
+// http://apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT or
+// http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Map the source files of a PDB file with its source server stream (srcsrv),
+//! which is used by Visual Studio to retrieve them.
+
+use hashbrown::HashMap;
+use log::{info, warn};
+use srcsrv::{SourceRetrievalMethod, SrcSrvStream};
+use std::sync::Arc;
+use symbolic::debuginfo::pdb::PdbObject;
+
+use crate::common;
+use crate::mapping::PathMappings;
+use crate::windows::utils;
+
+/// Get the paths in the source files section of a srcsrv stream: each line
+/// is made of variables separated by `*`, the first one being the path.
+fn get_paths(stream: &str) -> impl Iterator<Item = &str> {
+    stream
+        .lines()
+        .skip_while(|line| !line.starts_with("SRCSRV: source files"))
+        .skip(1)
+        .take_while(|line| !line.starts_with("SRCSRV:"))
+        .filter_map(|line| line.split('*').next())
+        .filter(|path| !path.is_empty())
+}
+
+/// Get the URLs of the source files listed in a srcsrv stream.
+///
+/// Only the files which are downloaded are mapped: the ones retrieved with a
+/// command (e.g. `tf.exe` or `p4.exe`) are kept as they are.
+pub(crate) fn get_files(stream: &[u8]) -> common::Result<HashMap<String, String>> {
+    let srcsrv = SrcSrvStream::parse(stream)?;
+    let stream = std::str::from_utf8(stream)?;
+    let mut files = HashMap::default();
+    let mut not_downloaded = 0;
+
+    for path in get_paths(stream) {
+        match srcsrv.source_for_path(path, "") {
+            Ok(Some(SourceRetrievalMethod::Download { url })) => {
+                files.insert(path.to_string(), url);
+            }
+            // An empty command is the same as no command at all.
+            Ok(Some(SourceRetrievalMethod::ExecuteCommand {
+                command,
+                target_path,
+                ..
+            })) if command.is_empty() && target_path.starts_with("http") => {
+                files.insert(path.to_string(), target_path);
+            }
+            Ok(_) => not_downloaded += 1,
+            Err(e) => warn!("Cannot get the source server URL of {path}: {e}"),
+        }
+    }
+
+    if not_downloaded != 0 {
+        info!(
+            "{not_downloaded} files in the srcsrv stream aren't downloaded so they aren't mapped"
+        );
+    }
+
+    Ok(files)
+}
+
+/// Get the mappings for the files in the srcsrv stream of the PDB file, if
+/// any, which `mapping` doesn't map: the explicit mappings win.
+pub(crate) fn get_mapping(
+    pdb: &PdbObject,
+    mapping: Option<Arc<PathMappings>>,
+) -> Option<Arc<PathMappings>> {
+    let stream = match utils::get_named_stream(pdb, b"srcsrv") {
+        Some(stream) => stream,
+        None => return mapping,
+    };
+
+    match get_files(&stream) {
+        Ok(files) if !files.is_empty() => Some(Arc::new(PathMappings::with_files(files, mapping))),
+        Ok(_) => mapping,
+        Err(e) => {
+            warn!("Invalid srcsrv stream: {e}");
+            mapping
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM: &str = r#"SRCSRV: ini ------------------------------------------------
+VERSION=2
+INDEXVERSION=2
+VERCTRL=http
+SRCSRV: variables ------------------------------------------
+HGSERVER=https://hg.mozilla.org/mozilla-central
+HTTP_EXTRACT_TARGET=%hgserver%/raw-file/%var3%/%var2%
+TFS_EXTRACT_CMD=tf.exe view /version:%var3% /noprompt "$%var2%" /console >%srcsrvtrg%
+SRCSRVVERCTRL=%fnvar%(%var4%_verctrl)
+HTTP_VERCTRL=http
+TFS_VERCTRL=tfs
+SRCSRVTRG=%fnvar%(%var4%_extract_target)
+SRCSRVCMD=%fnvar%(%var4%_extract_cmd)
+TFS_EXTRACT_TARGET=%targ%\%var3%\%fnfile%(%var1%)
+HTTP_EXTRACT_CMD=
+SRCSRV: source files ---------------------------------------
+c:\build\src\dom\base\Document.cpp*dom/base/Document.cpp*6639deb894172375b05d6791f5f8c7d53ca79723*http
+C:\build\src\xpcom\base\nsCOMPtr.h*xpcom/base/nsCOMPtr.h*6639deb894172375b05d6791f5f8c7d53ca79723*http
+c:\build\tools\foo.cpp*tools/foo.cpp*42*tfs
+SRCSRV: end ------------------------------------------------
+"#;
+
+    #[test]
+    fn test_srcsrv_files() {
+        let files = get_files(STREAM.as_bytes()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            files[r"c:\build\src\dom\base\Document.cpp"],
+            "https://hg.mozilla.org/mozilla-central/raw-file/6639deb894172375b05d6791f5f8c7d53ca79723/dom/base/Document.cpp"
+        );
+
+        let explicit = PathMappings::new(
+            &None,
+            &Some(vec![r"(?i)c:\\build\\(src\\xpcom|tools)\\(.*)"]),
+            &Some(vec!["local:{2}"]),
+            &None,
+            &None,
+            &None,
+        )
+        .unwrap()
+        .map(Arc::new);
+        let mapping = PathMappings::with_files(files, explicit);
+
+        assert_eq!(
+            mapping
+                .map(r"C:\build\src\dom\base\Document.cpp")
+                .unwrap()
+                .unwrap(),
+            "https://hg.mozilla.org/mozilla-central/raw-file/6639deb894172375b05d6791f5f8c7d53ca79723/dom/base/Document.cpp"
+        );
+        // The explicit mappings win over the srcsrv stream.
+        assert_eq!(
+            mapping
+                .map(r"C:\build\src\xpcom\base\nsCOMPtr.h")
+                .unwrap()
+                .unwrap(),
+            r"local:base\nsCOMPtr.h"
+        );
+        assert_eq!(
+            mapping.map(r"c:\build\tools\foo.cpp").unwrap().unwrap(),
+            "local:foo.cpp"
+        );
+        assert!(PathMappings::with_files(HashMap::default(), None)
+            .map(r"c:\build\tools\foo.cpp")
+            .unwrap()
+            .is_none());
+
+        assert!(get_files(b"SRCSRV: ini --\nVERSION=2\n").is_err());
+    }
+}
@@ -3,37 +3,48 @@
 // http://opensource.org/licenses/MIT>, at your option. This file may not be
 // copied, modified, or distributed except according to those terms.
 
+use std::io::Cursor;
 use std::path::Path;
-use symbolic::{common::DebugId, debuginfo::pe::PeObject};
+use symbolic::common::DebugId;
+use symbolic::debuginfo::{
+    pdb::{pdb::PDB, PdbObject},
+    pe::PeObject,
+};
 
 #[cfg(feature = "http")]
 use crate::cache::{self, SymbolServer};
-use crate::utils;
+use crate::common;
+#[cfg(feature = "http")]
+use crate::error::Error;
+use crate::utils::{self, FileData};
 
 #[cfg(feature = "http")]
-fn try_to_find_pdb(path: &Path, pdb_filename: &str) -> Option<Vec<u8>> {
+fn try_to_find_pdb(path: &Path, pdb_filename: &str) -> common::Result<Option<FileData>> {
     // Just check that the file is in the same directory as the PE one
     let pdb = path.with_file_name(pdb_filename);
     let pdb_cab = pdb.with_extension("pd_");
 
     for pdb in vec![pdb, pdb_cab].into_iter() {
         if pdb.is_file() {
-            return Some(utils::read_file(pdb));
+            return utils::read_file(pdb).map(Some);
         }
     }
 
     // We try in CWD
-    let mut pdb = std::env::current_dir().expect("Unable to get the current working directory");
+    let mut pdb = std::env::current_dir().map_err(|e| Error::io(".", e))?;
     pdb.set_file_name(pdb_filename);
     if pdb.is_file() {
-        Some(utils::read_file(pdb))
+        utils::read_file(pdb).map(Some)
     } else {
-        None
+        Ok(None)
     }
 }
 
 #[cfg(feature = "http")]
-fn os_specific_try_to_find_pdb(path: &Path, pdb_filename: &str) -> (Option<Vec<u8>>, String) {
+fn os_specific_try_to_find_pdb(
+    path: &Path,
+    pdb_filename: &str,
+) -> common::Result<(Option<FileData>, String)> {
     // We may have gotten either an OS native path, or a Windows path.
     // On Windows, they're both the same. On Unix, they are different, and in that case,
     // we change backslashes to forward slashes for `file_name()` to do its job.
@@ -44,12 +55,12 @@ fn os_specific_try_to_find_pdb(path: &Path, pdb_filename: &str) -> (Option<Vec<u
     if let Some(file_name) = pdb_path.file_name() {
         let pdb_name = file_name.to_str().unwrap().to_string();
         if pdb_path.is_file() {
-            (Some(utils::read_file(pdb_path)), pdb_name)
+            Ok((Some(utils::read_file(pdb_path)?), pdb_name))
         } else {
-            (try_to_find_pdb(path, &pdb_name), pdb_name)
+            Ok((try_to_find_pdb(path, &pdb_name)?, pdb_name))
         }
     } else {
-        (None, "".to_string())
+        Ok((None, "".to_string()))
     }
 }
 
@@ -58,25 +69,24 @@ pub fn get_pe_pdb_buf<'a>(
     path: &Path,
     buf: &'a [u8],
     symbol_server: Option<&Vec<SymbolServer>>,
-) -> Option<(PeObject<'a>, Vec<u8>, String)> {
-    let pe = PeObject::parse(buf)
-        .unwrap_or_else(|_| panic!("Unable to parse the PE file {}", path.to_str().unwrap()));
+) -> common::Result<Option<(PeObject<'a>, FileData, String)>> {
+    let pe = PeObject::parse(buf).map_err(|e| Error::parse(path.display().to_string(), e))?;
     if let Some(pdb_filename) = pe.debug_file_name() {
         let pdb_filename = pdb_filename.into_owned();
-        let (pdb, pdb_name) = os_specific_try_to_find_pdb(path, &pdb_filename);
+        let (pdb, pdb_name) = os_specific_try_to_find_pdb(path, &pdb_filename)?;
         if pdb_name.is_empty() {
             log::warn!("Invalid pdb filename in PE file: \"{pdb_filename}\"");
-            None
+            Ok(None)
         } else if let Some(pdb_buf) = pdb {
-            Some((pe, pdb_buf, pdb_name))
+            Ok(Some((pe, pdb_buf, pdb_name)))
         } else {
             // Not here so try symbol server (or cache)
             let debug_id = pe.debug_id().breakpad().to_string();
-            let (pdb, pdb_name) = cache::search_file(pdb_name, &debug_id, symbol_server);
-            pdb.map(|pdb_buf| (pe, pdb_buf, pdb_name))
+            let (pdb, pdb_name) = cache::search_file(pdb_name, &debug_id, symbol_server)?;
+            Ok(pdb.map(|pdb_buf| (pe, pdb_buf, pdb_name)))
         }
     } else {
-        None
+        Ok(None)
     }
 }
 
@@ -90,22 +100,32 @@ fn fix_extension(ext: &str) -> &str {
 
 /// Tries to find the PE object for a PDB file, by looking for dll/exe files
 /// in the same directory with a matching debug ID.
-pub(crate) fn find_pe_for_pdb(path: &Path, pdb_debug_id: &DebugId) -> Option<(String, Vec<u8>)> {
+pub(crate) fn find_pe_for_pdb(
+    path: &Path,
+    pdb_debug_id: &DebugId,
+) -> common::Result<Option<(String, FileData)>> {
     let mut path = path.to_path_buf();
     for ext in vec!["dll", "dl_", "exe", "ex_"].drain(..) {
         path.set_extension(ext);
         if path.is_file() {
-            let buf = utils::read_file(&path);
+            let buf = utils::read_file(&path)?;
             if let Ok(pe) = PeObject::parse(&buf) {
                 if ext.ends_with('_') {
                     path.set_extension(fix_extension(ext));
                 }
                 let filename = utils::get_filename(&path);
                 if &pe.debug_id() == pdb_debug_id {
-                    return Some((filename, buf));
+                    return Ok(Some((filename, buf)));
                 }
             }
         }
     }
-    None
+    Ok(None)
+}
+
+/// Get the raw data of the named stream `name` (e.g. `srcsrv`) of a PDB file.
+pub(crate) fn get_named_stream(pdb: &PdbObject, name: &[u8]) -> Option<Vec<u8>> {
+    let mut pdb = PDB::open(Cursor::new(pdb.data())).ok()?;
+    let stream = pdb.named_stream(name).ok()?;
+    Some(stream.as_slice().to_vec())
 }
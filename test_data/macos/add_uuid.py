@@ -0,0 +1,51 @@
+#!/usr/bin/env python3
+# Insert a LC_UUID load command in a 64-bit Mach-O object file:
+# llvm-mc doesn't emit one and the debug id of a Mach-O file is its uuid.
+
+import struct
+import sys
+
+LC_SEGMENT_64 = 0x19
+LC_SYMTAB = 0x2
+LC_DYSYMTAB = 0xB
+LC_UUID = 0x1B
+# The size of the LC_UUID command padded to keep the alignment of the data.
+SHIFT = 32
+
+
+def shift(data, pos, fmt):
+    (value,) = struct.unpack_from(fmt, data, pos)
+    if value:
+        struct.pack_into(fmt, data, pos, value + SHIFT)
+
+
+def main(path, uuid):
+    data = bytearray(open(path, "rb").read())
+    magic, _, _, _, ncmds, sizeofcmds, _, _ = struct.unpack_from("<8I", data, 0)
+    assert magic == 0xFEEDFACF
+    pos = 32
+    for _ in range(ncmds):
+        cmd, cmdsize = struct.unpack_from("<2I", data, pos)
+        if cmd == LC_SEGMENT_64:
+            shift(data, pos + 40, "<Q")  # fileoff
+            nsects = struct.unpack_from("<I", data, pos + 64)[0]
+            for i in range(nsects):
+                section = pos + 72 + i * 80
+                shift(data, section + 48, "<I")  # offset
+                shift(data, section + 56, "<I")  # reloff
+        elif cmd == LC_SYMTAB:
+            shift(data, pos + 8, "<I")  # symoff
+            shift(data, pos + 16, "<I")  # stroff
+        elif cmd == LC_DYSYMTAB:
+            for offset in (32, 40, 48, 56, 64, 72):
+                shift(data, pos + offset, "<I")
+        pos += cmdsize
+
+    command = struct.pack("<2I", LC_UUID, 24) + bytes.fromhex(uuid)
+    data[pos:pos] = command + bytes(SHIFT - len(command))
+    struct.pack_into("<2I", data, 16, ncmds + 1, sizeofcmds + 24)
+    open(path, "wb").write(data)
+
+
+if __name__ == "__main__":
+    main(sys.argv[1], sys.argv[2])
@@ -0,0 +1,14 @@
+	.text
+	.globl	_foo
+	.p2align	2
+_foo:
+	.cfi_startproc
+	ret
+	.cfi_endproc
+
+	.globl	_main
+	.p2align	2
+_main:
+	.cfi_startproc
+	ret
+	.cfi_endproc
@@ -0,0 +1,295 @@
+#!/usr/bin/env python3
+# Link a 64-bit Mach-O object file made of functions only into a dylib with
+# an __unwind_info section, as ld64 does (no Mach-O linker is required):
+#  - the sections keep their relative addresses so __eh_frame is only
+#    relocated as in the object file;
+#  - the functions without a compact unwind entry are described by their FDE
+#    (DWARF mode) or have no unwind information (mode 0);
+#  - the x86_64 entries are in a compressed page (with some common
+#    encodings) and the arm64 ones in a regular page.
+#
+# Usage: link_unwind.py input.o output uuid
+
+import struct
+import sys
+
+MH_MAGIC_64 = 0xFEEDFACF
+MH_DYLIB = 6
+CPU_TYPE_X86_64 = 0x01000007
+CPU_TYPE_ARM64 = 0x0100000C
+LC_SEGMENT_64 = 0x19
+LC_SYMTAB = 0x2
+LC_UUID = 0x1B
+LC_ID_DYLIB = 0xD
+N_SECT = 0xE
+N_EXT = 0x1
+S_ATTR_PURE_INSTRUCTIONS = 0x80000000
+S_ATTR_SOME_INSTRUCTIONS = 0x00000400
+
+# The address of the __text section in the dylib.
+TEXT_ADDRESS = 0x1000
+DWARF_MODE = {CPU_TYPE_X86_64: 0x04000000, CPU_TYPE_ARM64: 0x03000000}
+# The alignments (powers of 2) of the sections, __text is aligned on 16 bytes
+# on x86_64.
+ALIGNMENTS = {"__text": 2, "__eh_frame": 3, "__unwind_info": 2}
+# The SUBTRACTOR relocation type (the UNSIGNED one is 0 on both).
+SUBTRACTOR = {CPU_TYPE_X86_64: 5, CPU_TYPE_ARM64: 1}
+
+
+def uleb(data, pos):
+    value = shift = 0
+    while True:
+        byte = data[pos]
+        pos += 1
+        value |= (byte & 0x7F) << shift
+        shift += 7
+        if byte < 0x80:
+            return value, pos
+
+
+def read_object(data):
+    magic, cputype, _, _, ncmds, _, _, _ = struct.unpack_from("<8I", data, 0)
+    assert magic == MH_MAGIC_64
+    sections, symbols = {}, []
+    pos = 32
+    for _ in range(ncmds):
+        cmd, cmdsize = struct.unpack_from("<2I", data, pos)
+        if cmd == LC_SEGMENT_64:
+            nsects = struct.unpack_from("<I", data, pos + 64)[0]
+            for i in range(nsects):
+                s = pos + 72 + i * 80
+                name = data[s : s + 16].rstrip(b"\0").decode()
+                addr, size, offset, _, reloff, nreloc, flags = struct.unpack_from(
+                    "<QQIIIII", data, s + 32
+                )
+                relocs = []
+                for r in range(nreloc):
+                    address, info = struct.unpack_from("<iI", data, reloff + r * 8)
+                    relocs.append(
+                        {
+                            "address": address,
+                            "symbol": info & 0xFFFFFF,
+                            "extern": (info >> 27) & 1,
+                            "type": info >> 28,
+                        }
+                    )
+                sections[name] = {
+                    "index": i + 1,
+                    "addr": addr,
+                    "data": bytearray(data[offset : offset + size]),
+                    "flags": flags,
+                    "relocs": relocs,
+                }
+        elif cmd == LC_SYMTAB:
+            symoff, nsyms, stroff, _ = struct.unpack_from("<4I", data, pos + 8)
+            for i in range(nsyms):
+                strx, ntype, sect, _, value = struct.unpack_from(
+                    "<IBBHQ", data, symoff + i * 16
+                )
+                name = data[stroff + strx : data.index(b"\0", stroff + strx)].decode()
+                symbols.append((name, ntype, sect, value))
+        pos += cmdsize
+    return cputype, sections, symbols
+
+
+def relocate_eh_frame(cputype, eh_frame, symbols):
+    # A SUBTRACTOR relocation followed by an UNSIGNED one at the same address:
+    # the value is the second symbol minus the first one plus the addend.
+    data = eh_frame["data"]
+    relocs = eh_frame["relocs"]
+    for sub, unsigned in zip(relocs, relocs[1:]):
+        if sub["type"] != SUBTRACTOR[cputype] or unsigned["type"] != 0:
+            continue
+        if sub["address"] != unsigned["address"]:
+            continue
+        delta = symbols[unsigned["symbol"]][3] - symbols[sub["symbol"]][3]
+        (addend,) = struct.unpack_from("<q", data, sub["address"])
+        struct.pack_into("<q", data, sub["address"], addend + delta)
+
+
+def get_fdes(eh_frame):
+    # Get the offsets of the FDEs by function address.
+    data, base = eh_frame["data"], eh_frame["addr"]
+    encodings, fdes = {}, {}
+    pos = 0
+    while pos < len(data):
+        (length,) = struct.unpack_from("<I", data, pos)
+        if length == 0:
+            break
+        (cie_id,) = struct.unpack_from("<I", data, pos + 4)
+        if cie_id == 0:
+            end = data.index(b"\0", pos + 9)
+            augmentation = data[pos + 9 : end].decode()
+            _, p = uleb(data, end + 1)  # code alignment
+            _, p = uleb(data, p)  # data alignment
+            p += 1  # return address register
+            if augmentation.startswith("z"):
+                _, p = uleb(data, p)
+            assert augmentation == "zR", augmentation
+            encodings[pos] = data[p]
+        else:
+            encoding = encodings[pos + 4 - cie_id]
+            field = pos + 8
+            if encoding == 0x10:  # pcrel, absptr
+                (value,) = struct.unpack_from("<q", data, field)
+            else:
+                assert encoding == 0x1B, encoding  # pcrel, sdata4
+                (value,) = struct.unpack_from("<i", data, field)
+            fdes[base + field + value] = pos
+        pos += length + 4
+    return fdes
+
+
+def get_entries(cputype, sections, symbols):
+    text = sections["__text"]
+    compact = {}
+    unwind = sections.get("__compact_unwind")
+    if unwind:
+        relocs = {r["address"]: r for r in unwind["relocs"]}
+        for pos in range(0, len(unwind["data"]), 32):
+            address, _, encoding = struct.unpack_from("<QII", unwind["data"], pos)
+            reloc = relocs.get(pos)
+            if reloc and reloc["extern"]:
+                address += symbols[reloc["symbol"]][3]
+            compact[address] = encoding
+
+    eh_frame = sections.get("__eh_frame")
+    fdes = {}
+    if eh_frame:
+        relocate_eh_frame(cputype, eh_frame, symbols)
+        fdes = get_fdes(eh_frame)
+
+    functions = sorted(
+        value
+        for _, ntype, sect, value in symbols
+        if ntype & N_SECT and ntype & N_EXT and sect == text["index"]
+    )
+    entries = []
+    for address in functions:
+        encoding = compact.get(address)
+        if encoding is None or encoding & 0x0F000000 == DWARF_MODE[cputype]:
+            fde = fdes.get(address)
+            encoding = 0 if fde is None else DWARF_MODE[cputype] | fde
+        entries.append((TEXT_ADDRESS + address, encoding))
+    return entries
+
+
+def build_unwind_info(cputype, entries, text_end):
+    first = entries[0][0]
+    if cputype == CPU_TYPE_X86_64:
+        # The two first distinct encodings are the common ones.
+        distinct = list(dict.fromkeys(e for _, e in entries))
+        common, local = distinct[:2], distinct[2:]
+        page = struct.pack("<IHHHH", 3, 12, len(entries), 12 + 4 * len(entries), len(local))
+        for address, encoding in entries:
+            index = distinct.index(encoding)
+            page += struct.pack("<I", (index << 24) | (address - first))
+        page += struct.pack("<%dI" % len(local), *local)
+    else:
+        common = []
+        page = struct.pack("<IHH", 2, 8, len(entries))
+        for address, encoding in entries:
+            page += struct.pack("<II", address, encoding)
+
+    header_size = 28
+    common_offset = header_size
+    index_offset = common_offset + 4 * len(common)
+    lsda_offset = index_offset + 2 * 12
+    page_offset = lsda_offset
+    data = struct.pack(
+        "<7I", 1, common_offset, len(common), index_offset, 0, index_offset, 2
+    )
+    data += struct.pack("<%dI" % len(common), *common)
+    data += struct.pack("<3I", first, page_offset, lsda_offset)
+    data += struct.pack("<3I", text_end, 0, lsda_offset)
+    return data + page
+
+
+def align(value, alignment):
+    return (value + alignment - 1) // alignment * alignment
+
+
+def main(src, dst, uuid):
+    cputype, sections, symbols = read_object(open(src, "rb").read())
+    cpusubtype = 3 if cputype == CPU_TYPE_X86_64 else 0
+    text = sections["__text"]
+    eh_frame = sections.get("__eh_frame")
+    entries = get_entries(cputype, sections, symbols)
+
+    # The sections keep their addresses relative to __text.
+    shift = TEXT_ADDRESS - text["addr"]
+    text_end = TEXT_ADDRESS + len(text["data"])
+    out = [("__text", TEXT_ADDRESS, text["data"], S_ATTR_PURE_INSTRUCTIONS | S_ATTR_SOME_INSTRUCTIONS)]
+    end = text_end
+    if eh_frame:
+        address = eh_frame["addr"] + shift
+        out.append(("__eh_frame", address, eh_frame["data"], eh_frame["flags"]))
+        end = address + len(eh_frame["data"])
+    unwind_address = align(end, 4)
+    out.append(("__unwind_info", unwind_address, build_unwind_info(cputype, entries, text_end), 0))
+    segment_size = align(unwind_address + len(out[-1][2]), 8)
+
+    # The symbol table, after the segment.
+    names = [(n, v) for n, t, s, v in symbols if t & N_EXT and s == text["index"]]
+    strtab = b"\0"
+    nlists = b""
+    for name, value in names:
+        nlists += struct.pack("<IBBHQ", len(strtab), N_SECT | N_EXT, 1, 0, value + shift)
+        strtab += name.encode() + b"\0"
+    symoff = segment_size
+    stroff = symoff + len(nlists)
+
+    commands = struct.pack(
+        "<2I16s4Q4I",
+        LC_SEGMENT_64,
+        72 + 80 * len(out),
+        b"__TEXT",
+        0,
+        segment_size,
+        0,
+        segment_size,
+        5,
+        5,
+        len(out),
+        0,
+    )
+    for name, address, data, flags in out:
+        commands += struct.pack(
+            "<16s16sQQ8I",
+            name.encode(),
+            b"__TEXT",
+            address,
+            len(data),
+            address,
+            ALIGNMENTS[name] if name != "__text" or cputype == CPU_TYPE_ARM64 else 4,
+            0,
+            0,
+            flags,
+            0,
+            0,
+            0,
+        )
+    commands += struct.pack("<6I", LC_SYMTAB, 24, symoff, len(names), stroff, len(strtab))
+    commands += struct.pack("<2I", LC_UUID, 24) + bytes.fromhex(uuid)
+    install_name = b"@rpath/libunwind.dylib\0"
+    install_name += bytes(-len(install_name) % 8)
+    commands += struct.pack(
+        "<6I", LC_ID_DYLIB, 24 + len(install_name), 24, 0, 0x10000, 0x10000
+    )
+    commands += install_name
+    header = struct.pack(
+        "<8I", MH_MAGIC_64, cputype, cpusubtype, MH_DYLIB, 4, len(commands), 0, 0
+    )
+
+    image = bytearray(stroff + len(strtab))
+    image[: len(header) + len(commands)] = header + commands
+    assert len(header) + len(commands) <= TEXT_ADDRESS
+    for _, address, data, _ in out:
+        image[address : address + len(data)] = data
+    image[symoff:stroff] = nlists
+    image[stroff:] = strtab
+    open(dst, "wb").write(image)
+
+
+if __name__ == "__main__":
+    main(*sys.argv[1:])
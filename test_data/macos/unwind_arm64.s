@@ -0,0 +1,45 @@
+	.text
+	.globl	_frame
+	.p2align	2
+_frame:
+	.cfi_startproc
+	stp	x20, x19, [sp, #-32]!
+	stp	x29, x30, [sp, #16]
+	add	x29, sp, #16
+	.cfi_def_cfa w29, 16
+	.cfi_offset w30, -8
+	.cfi_offset w29, -16
+	.cfi_offset w19, -24
+	.cfi_offset w20, -32
+	ldp	x29, x30, [sp, #16]
+	ldp	x20, x19, [sp], #32
+	ret
+	.cfi_endproc
+
+	.globl	_frameless
+	.p2align	2
+_frameless:
+	.cfi_startproc
+	sub	sp, sp, #48
+	.cfi_def_cfa_offset 48
+	add	sp, sp, #48
+	ret
+	.cfi_endproc
+
+	.globl	_dwarf
+	.p2align	2
+_dwarf:
+	.cfi_startproc
+	str	x19, [sp, #-16]!
+	.cfi_def_cfa_offset 16
+	.cfi_offset w19, -16
+	ldr	x19, [sp], #16
+	ret
+	.cfi_endproc
+
+	.globl	_leaf
+	.p2align	2
+_leaf:
+	ret
+
+.subsections_via_symbols
@@ -0,0 +1,74 @@
+	.text
+	.globl	_frame
+	.p2align	4
+_frame:
+	.cfi_startproc
+	pushq	%rbp
+	.cfi_def_cfa_offset 16
+	.cfi_offset %rbp, -16
+	movq	%rsp, %rbp
+	.cfi_def_cfa_register %rbp
+	pushq	%r12
+	pushq	%rbx
+	.cfi_offset %rbx, -32
+	.cfi_offset %r12, -24
+	popq	%rbx
+	popq	%r12
+	popq	%rbp
+	retq
+	.cfi_endproc
+
+	.globl	_frameless
+	.p2align	4
+_frameless:
+	.cfi_startproc
+	pushq	%r14
+	.cfi_def_cfa_offset 16
+	pushq	%rbx
+	.cfi_def_cfa_offset 24
+	subq	$40, %rsp
+	.cfi_def_cfa_offset 64
+	.cfi_offset %rbx, -24
+	.cfi_offset %r14, -16
+	addq	$40, %rsp
+	popq	%rbx
+	popq	%r14
+	retq
+	.cfi_endproc
+
+	.globl	_big_frameless
+	.p2align	4
+_big_frameless:
+	.cfi_startproc
+	pushq	%rbx
+	.cfi_def_cfa_offset 16
+	subq	$65536, %rsp
+	.cfi_def_cfa_offset 65552
+	.cfi_offset %rbx, -16
+	addq	$65536, %rsp
+	popq	%rbx
+	retq
+	.cfi_endproc
+
+	.globl	_leaf
+	.p2align	4
+_leaf:
+	retq
+
+	.globl	_dwarf
+	.p2align	4
+_dwarf:
+	.cfi_startproc
+	pushq	%rbx
+	.cfi_def_cfa_offset 16
+	.cfi_offset %rbx, -16
+	pushq	%rbp
+	.cfi_def_cfa_offset 24
+	# DW_CFA_GNU_args_size can't be described by a compact unwind encoding.
+	.cfi_escape 0x2e, 0x00
+	popq	%rbp
+	popq	%rbx
+	retq
+	.cfi_endproc
+
+.subsections_via_symbols
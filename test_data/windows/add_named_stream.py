@@ -0,0 +1,149 @@
+#!/usr/bin/env python3
+# Copy a PDB file and add a named stream to it (e.g. a srcsrv one): the new
+# stream, the PDB info stream and the stream directory are written in new
+# blocks at the end of the file, the old blocks of the info stream and of the
+# directory are just unused.
+#
+# Usage: add_named_stream.py input.pdb output.pdb name stream-file
+
+import struct
+import sys
+
+MAGIC = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0"
+INFO_STREAM = 1
+# The size of the header of the PDB info stream (version, signature, age, guid).
+INFO_HEADER_SIZE = 28
+
+
+def read_streams(data):
+    assert data.startswith(MAGIC), "not a PDB 7.0 file"
+    block_size, fpm, _, dir_size, _, dir_map = struct.unpack_from("<6I", data, 32)
+    dir_blocks = struct.unpack_from(
+        "<%dI" % ((dir_size + block_size - 1) // block_size), data, dir_map * block_size
+    )
+    directory = b"".join(data[b * block_size : (b + 1) * block_size] for b in dir_blocks)
+    count = struct.unpack_from("<I", directory)[0]
+    sizes = struct.unpack_from("<%dI" % count, directory, 4)
+    offset = 4 + 4 * count
+    streams = []
+    for size in sizes:
+        if size == 0xFFFFFFFF:
+            streams.append((size, ()))
+            continue
+        n = (size + block_size - 1) // block_size
+        streams.append((size, struct.unpack_from("<%dI" % n, directory, offset)))
+        offset += 4 * n
+    return block_size, fpm, streams
+
+
+def read_stream(data, block_size, stream):
+    size, blocks = stream
+    return b"".join(data[b * block_size : (b + 1) * block_size] for b in blocks)[:size]
+
+
+def hash_name(name):
+    # hashStringV1 (truncated to 16 bits) as used by the named stream map.
+    result = 0
+    longs = len(name) // 4
+    for (value,) in struct.iter_unpack("<I", name[: 4 * longs]):
+        result ^= value
+    rest = name[4 * longs :]
+    if len(rest) >= 2:
+        result ^= struct.unpack_from("<H", rest)[0]
+        rest = rest[2:]
+    if rest:
+        result ^= rest[0]
+    result |= 0x20202020
+    result ^= result >> 11
+    return (result ^ (result >> 16)) & 0xFFFF
+
+
+def add_name(info, name, stream):
+    pos = INFO_HEADER_SIZE
+    (strings_size,) = struct.unpack_from("<I", info, pos)
+    strings = info[pos + 4 : pos + 4 + strings_size]
+    pos += 4 + strings_size
+    size, capacity, present_words = struct.unpack_from("<3I", info, pos)
+    present = struct.unpack_from("<%dI" % present_words, info, pos + 12)
+    pos += 12 + 4 * present_words
+    (deleted_words,) = struct.unpack_from("<I", info, pos)
+    pos += 4 + 4 * deleted_words
+    names = {}
+    for bucket in range(capacity):
+        if present[bucket // 32] & (1 << (bucket % 32)):
+            offset, index = struct.unpack_from("<2I", info, pos)
+            pos += 8
+            names[strings[offset : strings.index(b"\0", offset)]] = index
+    assert len(names) == size
+    names[name] = stream
+
+    # Rebuild the hash table (with linear probing) and grow it as LLVM does.
+    if len(names) > capacity * 2 // 3:
+        capacity *= 2
+    buckets = [None] * capacity
+    strings = b""
+    for key, index in names.items():
+        bucket = hash_name(key) % capacity
+        while buckets[bucket] is not None:
+            bucket = (bucket + 1) % capacity
+        buckets[bucket] = (len(strings), index)
+        strings += key + b"\0"
+
+    words = (capacity + 31) // 32
+    present = [0] * words
+    entries = b""
+    for bucket, entry in enumerate(buckets):
+        if entry is not None:
+            present[bucket // 32] |= 1 << (bucket % 32)
+            entries += struct.pack("<2I", *entry)
+    table = struct.pack("<3I", len(names), capacity, words)
+    table += struct.pack("<%dI" % words, *present) + struct.pack("<I", 0)
+    # The end of the stream (niMac and the feature codes) is kept as is.
+    return (
+        info[:INFO_HEADER_SIZE]
+        + struct.pack("<I", len(strings))
+        + strings
+        + table
+        + entries
+        + info[pos:]
+    )
+
+
+def main(src, dst, name, stream_file):
+    data = bytearray(open(src, "rb").read())
+    block_size, fpm, streams = read_streams(data)
+    info = add_name(read_stream(data, block_size, streams[INFO_STREAM]), name.encode(), len(streams))
+    content = open(stream_file, "rb").read()
+
+    def allocate(content):
+        blocks = []
+        for i in range(0, max(len(content), 1), block_size):
+            block = len(data) // block_size
+            # The free page maps are in the blocks 1 and 2 of each interval.
+            assert block % block_size not in (1, 2), "the PDB file is too large"
+            data.extend(content[i : i + block_size].ljust(block_size, b"\0"))
+            # Mark the block as used in the free page map.
+            data[fpm * block_size + block // 8] &= ~(1 << (block % 8)) & 0xFF
+            blocks.append(block)
+        return blocks if content else []
+
+    streams[INFO_STREAM] = (len(info), allocate(info))
+    streams.append((len(content), allocate(content)))
+
+    # The stream directory: the sizes then the blocks of the streams.
+    directory = struct.pack("<I", len(streams))
+    directory += b"".join(struct.pack("<I", size) for size, _ in streams)
+    for _, blocks in streams:
+        directory += struct.pack("<%dI" % len(blocks), *blocks)
+    dir_blocks = allocate(directory)
+    (dir_map,) = allocate(struct.pack("<%dI" % len(dir_blocks), *dir_blocks))
+
+    num_blocks = len(data) // block_size
+    struct.pack_into("<I", data, 40, num_blocks)
+    struct.pack_into("<I", data, 44, len(directory))
+    struct.pack_into("<I", data, 52, dir_map)
+    open(dst, "wb").write(data)
+
+
+if __name__ == "__main__":
+    main(*sys.argv[1:])
@@ -0,0 +1,54 @@
+#!/usr/bin/env python3
+# Copy a PDB file without its FPO or its frame data stream: the stream is
+# only unreferenced in the optional debug header of the DBI stream.
+#
+# Usage: drop_dbi_stream.py input.pdb output.pdb fpo|frame-data
+
+import struct
+import sys
+
+MAGIC = b"Microsoft C/C++ MSF 7.00\r\n\x1aDS\0\0\0"
+DBI_STREAM = 3
+# The indices in the optional debug header.
+HEADER_INDICES = {"fpo": 0, "frame-data": 9}
+
+
+def read_streams(data):
+    assert data.startswith(MAGIC), "not a PDB 7.0 file"
+    block_size, _, _, dir_size, _, dir_map = struct.unpack_from("<6I", data, 32)
+    dir_blocks = struct.unpack_from(
+        "<%dI" % ((dir_size + block_size - 1) // block_size), data, dir_map * block_size
+    )
+    directory = b"".join(data[b * block_size : (b + 1) * block_size] for b in dir_blocks)
+    count = struct.unpack_from("<I", directory)[0]
+    sizes = struct.unpack_from("<%dI" % count, directory, 4)
+    offset = 4 + 4 * count
+    streams = []
+    for size in sizes:
+        if size == 0xFFFFFFFF:
+            streams.append((0, ()))
+            continue
+        n = (size + block_size - 1) // block_size
+        streams.append((size, struct.unpack_from("<%dI" % n, directory, offset)))
+        offset += 4 * n
+    return block_size, streams
+
+
+def main(src, dst, stream):
+    data = bytearray(open(src, "rb").read())
+    block_size, streams = read_streams(data)
+    size, blocks = streams[DBI_STREAM]
+    dbi = b"".join(data[b * block_size : (b + 1) * block_size] for b in blocks)[:size]
+
+    # The sizes of the substreams which are before the optional debug header.
+    substreams = struct.unpack_from("<6i", dbi, 24)
+    ec_size = struct.unpack_from("<i", dbi, 52)[0]
+    offset = 64 + sum(substreams[:5]) + ec_size + 2 * HEADER_INDICES[stream]
+
+    block, inner = divmod(offset, block_size)
+    struct.pack_into("<H", data, blocks[block] * block_size + inner, 0xFFFF)
+    open(dst, "wb").write(data)
+
+
+if __name__ == "__main__":
+    main(*sys.argv[1:])